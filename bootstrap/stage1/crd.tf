@@ -37,6 +37,11 @@ resource "kubernetes_manifest" "customresourcedefinition_hydradoomnodes_hydra_do
               "name"     = "Transactions"
               "type"     = "string"
             },
+            {
+              "jsonPath" = ".status.availableReplicas"
+              "name"     = "Available"
+              "type"     = "integer"
+            },
             {
               "jsonPath" = ".status.localUrl"
               "name"     = "Local URI"
@@ -59,6 +64,18 @@ resource "kubernetes_manifest" "customresourcedefinition_hydradoomnodes_hydra_do
                       "nullable" = true
                       "type"     = "boolean"
                     }
+                    "draining" = {
+                      "description" = "Draining heads finish their current games but aren't scheduled new ones."
+                      "nullable"    = true
+                      "type"        = "boolean"
+                    }
+                    "maxGames" = {
+                      "description" = "How many games the head may host at the same time. Defaults to a single game."
+                      "format"      = "uint32"
+                      "minimum"     = 0
+                      "nullable"    = true
+                      "type"        = "integer"
+                    }
                     "networkId" = {
                       "format"   = "uint8"
                       "minimum"  = 0
@@ -123,18 +140,44 @@ resource "kubernetes_manifest" "customresourcedefinition_hydradoomnodes_hydra_do
                 "status" = {
                   "nullable" = true
                   "properties" = {
+                    "availableReplicas" = {
+                      "description" = "Replicas of the node's deployment that are available, if the deployment exists."
+                      "format"      = "int32"
+                      "nullable"    = true
+                      "type"        = "integer"
+                    }
                     "externalUrl" = {
                       "type" = "string"
                     }
                     "gameState" = {
                       "type" = "string"
                     }
+                    "ingressAddress" = {
+                      "description" = "Address the ingress controller assigned to the node's ingress, once there is one."
+                      "nullable"    = true
+                      "type"        = "string"
+                    }
                     "localUrl" = {
                       "type" = "string"
                     }
                     "nodeState" = {
                       "type" = "string"
                     }
+                    "openGames" = {
+                      "default" = 0
+                      "format"  = "int64"
+                      "type"    = "integer"
+                    }
+                    "snapshotUsed" = {
+                      "default"     = false
+                      "description" = "Whether the head got as far as initializing, after which its snapshot can't be reused."
+                      "type"        = "boolean"
+                    }
+                    "stateSince" = {
+                      "description" = "When the node entered its current node state."
+                      "nullable"    = true
+                      "type"        = "string"
+                    }
                     "transactions" = {
                       "format" = "int64"
                       "type"   = "integer"
@@ -153,6 +153,11 @@ resource "kubernetes_ingress_v1" "control_plane_ingress" {
   metadata {
     name      = local.control_plane_component
     namespace = var.namespace
+    annotations = {
+      // Matchmaking tickets only live in the replica that issued them
+      "nginx.ingress.kubernetes.io/affinity"            = "cookie"
+      "nginx.ingress.kubernetes.io/session-cookie-name" = "hydra-doom-replica"
+    }
   }
 
   spec {
@@ -61,6 +61,11 @@ resource "kubernetes_deployment_v1" "operator" {
             value = var.referee_image
           }
 
+          env {
+            name  = "REFEREE_KEY"
+            value = var.referee_key
+          }
+
           env {
             name  = "AI_IMAGE"
             value = var.ai_image
@@ -197,6 +202,12 @@ resource "kubernetes_deployment_v1" "operator" {
             container_port = 8000
             protocol       = "TCP"
           }
+
+          port {
+            name           = "metrics"
+            container_port = 9090
+            protocol       = "TCP"
+          }
         }
 
         volume {
@@ -35,6 +35,12 @@ variable "referee_image" {
   type = string
 }
 
+variable "referee_key" {
+  type        = string
+  description = "Hex encoded ed25519 public key used to verify cheat reports."
+  default     = ""
+}
+
 variable "ai_image" {
   type = string
 }
@@ -25,3 +25,31 @@ resource "kubernetes_manifest" "pod_monitors" {
     }
   }
 }
+
+resource "kubernetes_manifest" "operator_pod_monitor" {
+  manifest = {
+    apiVersion = "monitoring.coreos.com/v1"
+    kind       = "PodMonitor"
+    metadata = {
+      labels = {
+        "app.kubernetes.io/component" = "o11y"
+        "app.kubernetes.io/part-of"   = "hydradoom"
+      }
+      name      = "hydradoom-operator"
+      namespace = var.namespace
+    }
+    spec = {
+      selector = {
+        matchLabels = {
+          role = local.operator_component
+        }
+      }
+      podMetricsEndpoints = [
+        {
+          port = "metrics",
+          path = "/metrics"
+        }
+      ]
+    }
+  }
+}
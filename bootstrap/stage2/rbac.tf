@@ -32,6 +32,12 @@ resource "kubernetes_cluster_role" "cluster_role" {
     resources  = ["*"]
     verbs      = ["*"]
   }
+
+  rule {
+    api_groups = ["events.k8s.io"]
+    resources  = ["events"]
+    verbs      = ["create"]
+  }
 }
 
 resource "kubernetes_cluster_role_binding" "cluster_role_binding" {
@@ -1,15 +1,19 @@
-use anyhow::Result;
+use anyhow::{bail, Context, Result};
 use aws_config::{meta::region::RegionProviderChain, BehaviorVersion};
 use aws_sdk_s3::config::Region;
 use futures::StreamExt;
 use kube::{runtime::controller::Controller, Api, Client};
 use std::sync::Arc;
+use tokio::net::TcpListener;
 use tracing::{error, info, instrument};
 
 use hydra_control_plane_operator::{
     config::Config,
-    controller::{error_policy, patch_statuses, reconcile, run_autoscaler, K8sContext},
+    controller::{
+        error_policy, patch_statuses, reconcile, run_autoscaler, run_health_checker, K8sContext,
+    },
     custom_resource::HydraDoomNode,
+    metrics::{serve_metrics, Metrics},
 };
 
 #[tokio::main]
@@ -28,7 +32,17 @@ async fn main() -> Result<()> {
         .load()
         .await;
     let s3_client = aws_sdk_s3::Client::new(&shared_config);
-    let context = Arc::new(K8sContext::new(client.clone(), config, s3_client));
+    let metrics = Arc::new(Metrics::try_new().expect("Failed to register metrics."));
+    let metrics_listener = TcpListener::bind(config.metrics_addr)
+        .await
+        .with_context(|| {
+            format!(
+                "failed to bind the metrics server to {}",
+                config.metrics_addr
+            )
+        })?;
+    let metrics_server = serve_metrics(metrics.clone(), metrics_listener);
+    let context = Arc::new(K8sContext::new(client.clone(), config, s3_client, metrics));
 
     // Create controller for MyApp custom resource
     let api: Api<HydraDoomNode> = Api::default_namespaced(client);
@@ -43,8 +57,14 @@ async fn main() -> Result<()> {
         });
     let patch_statuses_controller = patch_statuses(context.clone());
     let autoscaler_controller = run_autoscaler(context.clone());
+    let health_checker = run_health_checker(context.clone());
 
-    let _ = tokio::join!(controller, patch_statuses_controller, autoscaler_controller);
-
-    Ok(())
+    // Every loop runs forever, the operator exits as soon as one of them stops
+    tokio::select! {
+        () = controller => bail!("controller stopped"),
+        () = patch_statuses_controller => bail!("status patcher stopped"),
+        () = autoscaler_controller => bail!("autoscaler stopped"),
+        () = health_checker => bail!("health checker stopped"),
+        () = metrics_server => bail!("metrics server stopped"),
+    }
 }
@@ -1,5 +1,5 @@
 use lazy_static::lazy_static;
-use std::{env, time::Duration};
+use std::{env, net::SocketAddr, time::Duration};
 
 lazy_static! {
     static ref CONTROLLER_CONFIG: Config = Config::from_env();
@@ -15,6 +15,7 @@ pub struct Config {
     pub init_image: String,
     pub sidecar_image: String,
     pub referee_image: String,
+    pub referee_key: Option<String>,
     pub ai_image: String,
     pub configmap: String,
     pub secret: String,
@@ -33,6 +34,7 @@ pub struct Config {
     pub init_aws_secret_access_key: String,
     pub network_id: String,
     pub available_snapshot_prefix: String,
+    pub metrics_addr: SocketAddr,
 
     // Autoscaler
     pub autoscaler_delay: Duration,
@@ -40,6 +42,15 @@ pub struct Config {
     pub autoscaler_high_watermark: usize,
     pub autoscaler_region_prefix: String,
     pub autoscaler_max_batch: usize,
+
+    // Health checker
+    pub health_check_interval: Duration,
+    pub health_offline_timeout: Duration,
+    pub health_online_timeout: Duration,
+    pub health_initializing_timeout: Duration,
+    pub health_max_restarts: u32,
+    pub health_max_replacements: usize,
+    pub health_replacement_window: Duration,
 }
 
 impl Config {
@@ -48,6 +59,7 @@ impl Config {
             image: env::var("IMAGE").unwrap_or("ghcr.io/cardano-scaling/hydra-node".into()),
             sidecar_image: env::var("SIDECAR_IMAGE").expect("Missing SIDECAR_IMAGE env var"),
             referee_image: env::var("REFEREE_IMAGE").expect("Missing REFEREE_IMAGE env var"),
+            referee_key: env::var("REFEREE_KEY").ok().filter(|key| !key.is_empty()),
             ai_image: env::var("AI_IMAGE").expect("Missing AI_IMAGE env var"),
             configmap: env::var("CONFIGMAP").expect("Missing CONFIGMAP env var"),
             secret: env::var("SECRET").expect("Missing SECRET env var"),
@@ -71,6 +83,10 @@ impl Config {
                 .expect("Missing INIT_AWS_SECRET_ACCESS_KEY env var."),
             available_snapshot_prefix: env::var("AVAILABLE_SNAPSHOT_PREFIX")
                 .unwrap_or("snapshots".to_string()),
+            metrics_addr: env::var("METRICS_ADDR")
+                .unwrap_or("0.0.0.0:9090".to_string())
+                .parse()
+                .expect("Failed to parse METRICS_ADDR"),
 
             autoscaler_delay: env::var("AUTOSCALER_DELAY")
                 .map(|duration| {
@@ -92,6 +108,30 @@ impl Config {
                 .map(|x| x.parse().expect("Failed to parse AUTOSCALER_MAX_BATCH"))
                 .expect("Missing AUTOSCALER_MAX_BATCH env var."),
             network_id: env::var("NETWORK_ID").expect("Missing NETWORK_ID env var."),
+
+            health_check_interval: seconds_from_env("HEALTH_CHECK_INTERVAL", 30),
+            health_offline_timeout: seconds_from_env("HEALTH_OFFLINE_TIMEOUT", 600),
+            health_online_timeout: seconds_from_env("HEALTH_ONLINE_TIMEOUT", 600),
+            health_initializing_timeout: seconds_from_env("HEALTH_INITIALIZING_TIMEOUT", 900),
+            health_max_restarts: env::var("HEALTH_MAX_RESTARTS")
+                .map(|x| x.parse().expect("Failed to parse HEALTH_MAX_RESTARTS"))
+                .unwrap_or(1),
+            health_max_replacements: env::var("HEALTH_MAX_REPLACEMENTS")
+                .map(|x| x.parse().expect("Failed to parse HEALTH_MAX_REPLACEMENTS"))
+                .unwrap_or(3),
+            health_replacement_window: seconds_from_env("HEALTH_REPLACEMENT_WINDOW", 3600),
         }
     }
 }
+
+fn seconds_from_env(name: &str, default: u64) -> Duration {
+    Duration::from_secs(
+        env::var(name)
+            .map(|seconds| {
+                seconds
+                    .parse()
+                    .unwrap_or_else(|_| panic!("Failed to parse {}", name))
+            })
+            .unwrap_or(default),
+    )
+}
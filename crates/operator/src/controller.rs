@@ -1,21 +1,31 @@
-use anyhow::bail;
-use k8s_openapi::api::{
-    apps::v1::Deployment,
-    core::v1::{ConfigMap, Service},
-    networking::v1::Ingress,
+use k8s_openapi::{
+    api::{
+        apps::v1::Deployment,
+        core::v1::{ConfigMap, Service},
+        networking::v1::Ingress,
+    },
+    apimachinery::pkg::apis::meta::v1::Time,
+    chrono::{DateTime, Utc},
+    NamespaceResourceScope,
 };
 use kube::{
     api::{DeleteParams, ListParams, Patch, PatchParams},
-    runtime::controller::Action,
-    Api, Client, ResourceExt,
+    runtime::{
+        controller::Action,
+        events::{Event, EventType, Recorder, Reporter},
+    },
+    Api, Client, Resource, ResourceExt,
 };
 use rand::{distributions::Alphanumeric, seq::SliceRandom, thread_rng, Rng};
+use serde::{de::DeserializeOwned, Serialize};
 use serde_json::json;
 use std::{
     cmp::{min, Ordering},
-    collections::BTreeMap,
-    sync::Arc,
-    time::Duration,
+    collections::{BTreeMap, HashMap, VecDeque},
+    fmt::Debug,
+    future::Future,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
 };
 use thiserror::Error;
 use tracing::{error, info, warn};
@@ -30,7 +40,8 @@ pub fn random_name() -> String {
 
 use crate::{
     config::Config,
-    custom_resource::{HydraDoomNodeSpec, HydraDoomNodeStatus},
+    custom_resource::{HydraDoomNodeSpec, HydraDoomNodeStatus, NodeHealth, HEALTH_ANNOTATION},
+    metrics::Metrics,
 };
 
 use super::custom_resource::HydraDoomNode;
@@ -41,6 +52,7 @@ pub enum HydraDoomNodeState {
     HeadIsInitializing,
     HeadIsOpen,
     Sleeping,
+    Unhealthy,
 }
 impl From<f64> for HydraDoomNodeState {
     fn from(value: f64) -> Self {
@@ -60,6 +72,7 @@ impl From<HydraDoomNodeState> for String {
             HydraDoomNodeState::HeadIsInitializing => "HeadIsInitializing".to_string(),
             HydraDoomNodeState::HeadIsOpen => "HeadIsOpen".to_string(),
             HydraDoomNodeState::Sleeping => "Sleeping".to_string(),
+            HydraDoomNodeState::Unhealthy => "Unhealthy".to_string(),
         }
     }
 }
@@ -108,6 +121,7 @@ pub struct K8sConstants {
     pub node_state_metric: String,
     pub game_state_metric: String,
     pub transactions_metric: String,
+    pub games_open_metric: String,
     pub dmtrctl_image: String,
     pub storage_class_name: String,
     pub service_account_name: String,
@@ -133,6 +147,7 @@ impl Default for K8sConstants {
             node_state_metric: "hydra_doom_node_state".to_string(),
             game_state_metric: "hydra_doom_game_state".to_string(),
             transactions_metric: "hydra_doom_node_transactions".to_string(),
+            games_open_metric: "hydra_doom_games_open".to_string(),
             ingress_class_name: "nginx".to_string(),
             service_account_name: "hydra-doom-node".to_string(),
             ingress_annotations: [
@@ -169,95 +184,151 @@ pub struct K8sContext {
     pub config: Config,
     pub constants: K8sConstants,
     pub s3_client: aws_sdk_s3::Client,
+    pub metrics: Arc<Metrics>,
+    // Consecutive reconcile failures per node, to back off further on each retry.
+    reconcile_failures: Mutex<HashMap<String, u32>>,
+    // When the health checker last replaced nodes, to cap how many it replaces per window.
+    replacements: Mutex<VecDeque<Instant>>,
 }
 
 impl K8sContext {
-    pub fn new(client: Client, config: Config, s3_client: aws_sdk_s3::Client) -> Self {
+    pub fn new(
+        client: Client,
+        config: Config,
+        s3_client: aws_sdk_s3::Client,
+        metrics: Arc<Metrics>,
+    ) -> Self {
         Self {
             client,
             config,
             constants: Default::default(),
             s3_client,
+            metrics,
+            reconcile_failures: Default::default(),
+            replacements: Default::default(),
         }
     }
 
-    pub async fn patch(&self, crd: &HydraDoomNode) -> anyhow::Result<()> {
-        info!("Running patch");
-        match tokio::join!(
-            self.patch_deployment(crd),
-            self.patch_service(crd),
-            self.patch_ingress(crd),
-            self.patch_configmap(crd),
-        ) {
-            (Ok(_), Ok(_), Ok(_), Ok(_)) => (),
-            _ => bail!("Failed to apply patch for components."),
-        };
-
-        Ok(())
+    pub fn recorder(&self, crd: &HydraDoomNode) -> Recorder {
+        Recorder::new(
+            self.client.clone(),
+            Reporter::from("hydra-doom-pod-controller"),
+            crd.object_ref(&()),
+        )
     }
 
-    async fn patch_configmap(&self, crd: &HydraDoomNode) -> anyhow::Result<ConfigMap> {
-        let api: Api<ConfigMap> = Api::namespaced(self.client.clone(), &crd.namespace().unwrap());
+    pub async fn patch(&self, crd: &HydraDoomNode, recorder: &Recorder) -> Result<()> {
+        info!("Running patch");
+        let (deployment, service, ingress, configmap) = tokio::join!(
+            self.observe_patch(
+                "deployment",
+                self.apply(crd, crd.deployment(&self.config, &self.constants))
+            ),
+            self.observe_patch(
+                "service",
+                self.apply(crd, crd.service(&self.config, &self.constants))
+            ),
+            self.observe_patch(
+                "ingress",
+                self.apply(crd, crd.ingress(&self.config, &self.constants))
+            ),
+            self.observe_patch(
+                "configmap",
+                self.apply(crd, crd.configmap(&self.config, &self.constants))
+            ),
+        );
 
-        // Create or patch the configmap
-        api.patch(
-            &crd.internal_name(),
-            &PatchParams::apply("hydra-doom-pod-controller"),
-            &Patch::Apply(&crd.configmap(&self.config, &self.constants)),
-        )
-        .await
-        .map_err(|err| {
-            error!(err = err.to_string(), "Failed to create configmap.");
-            err.into()
-        })
+        let mut failures = vec![];
+        for (resource, result) in [
+            ("deployment", deployment),
+            ("service", service),
+            ("ingress", ingress),
+            ("configmap", configmap),
+        ] {
+            match result {
+                Ok(true) => {
+                    publish(
+                        recorder,
+                        Event {
+                            type_: EventType::Normal,
+                            reason: "Created".to_string(),
+                            note: Some(format!("Created {} {}", resource, crd.internal_name())),
+                            action: "Apply".to_string(),
+                            secondary: None,
+                        },
+                    )
+                    .await
+                }
+                Ok(false) => (),
+                Err(err) => {
+                    error!(err = err.to_string(), "Failed to apply {}.", resource);
+                    publish(
+                        recorder,
+                        Event {
+                            type_: EventType::Warning,
+                            reason: "ApplyFailed".to_string(),
+                            note: Some(format!("Failed to apply {}: {}", resource, err)),
+                            action: "Apply".to_string(),
+                            secondary: None,
+                        },
+                    )
+                    .await;
+                    failures.push((resource, err));
+                }
+            }
+        }
+
+        if failures.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::Apply(failures))
+        }
     }
 
-    async fn patch_deployment(&self, crd: &HydraDoomNode) -> anyhow::Result<Deployment> {
-        let api: Api<Deployment> = Api::namespaced(self.client.clone(), &crd.namespace().unwrap());
+    /// Times the patch of a child resource, and counts its failures.
+    async fn observe_patch<T, E>(
+        &self,
+        resource: &str,
+        patch: impl Future<Output = Result<T, E>>,
+    ) -> Result<T, E> {
+        let timer = self
+            .metrics
+            .reconcile_seconds
+            .with_label_values(&[resource])
+            .start_timer();
+        let result = patch.await;
+        timer.observe_duration();
+        if result.is_err() {
+            self.metrics
+                .reconcile_errors
+                .with_label_values(&[resource])
+                .inc();
+        }
 
-        // Create or patch the deployment
-        api.patch(
-            &crd.internal_name(),
-            &PatchParams::apply("hydra-doom-pod-controller"),
-            &Patch::Apply(&crd.deployment(&self.config, &self.constants)),
-        )
-        .await
-        .map_err(|err| {
-            error!(err = err.to_string(), "Failed to create deployment.");
-            err.into()
-        })
+        result
     }
 
-    async fn patch_service(&self, crd: &HydraDoomNode) -> anyhow::Result<Service> {
-        // Apply the service to the cluster
-        let services: Api<Service> =
-            Api::namespaced(self.client.clone(), &crd.namespace().unwrap());
-        services
-            .patch(
-                &crd.internal_name(),
-                &PatchParams::apply("hydra-doom-pod-controller"),
-                &Patch::Apply(&crd.service(&self.config, &self.constants)),
-            )
-            .await
-            .map_err(|err| {
-                error!(err = err.to_string(), "Failed to create service.");
-                err.into()
-            })
-    }
+    /// Creates or updates a child resource of the node. Returns whether it had to be created.
+    async fn apply<K>(&self, crd: &HydraDoomNode, child: K) -> Result<bool, kube::Error>
+    where
+        K: Resource<Scope = NamespaceResourceScope, DynamicType = ()>
+            + Clone
+            + Debug
+            + Serialize
+            + DeserializeOwned,
+    {
+        let api: Api<K> = Api::namespaced(self.client.clone(), &crd.namespace().unwrap());
+        let name = crd.internal_name();
 
-    async fn patch_ingress(&self, crd: &HydraDoomNode) -> anyhow::Result<Ingress> {
-        // Apply the service to the cluster
-        let api: Api<Ingress> = Api::namespaced(self.client.clone(), &crd.namespace().unwrap());
+        let existed = api.get_metadata_opt(&name).await?.is_some();
         api.patch(
-            &crd.internal_name(),
+            &name,
             &PatchParams::apply("hydra-doom-pod-controller"),
-            &Patch::Apply(&crd.ingress(&self.config, &self.constants)),
+            &Patch::Apply(&child),
         )
-        .await
-        .map_err(|err| {
-            error!(err = err.to_string(), "Failed to create ingress.");
-            err.into()
-        })
+        .await?;
+
+        Ok(!existed)
     }
 
     fn get_internal_url(&self, crd: &HydraDoomNode) -> String {
@@ -274,6 +345,59 @@ impl K8sContext {
     }
 
     async fn get_status_from_crd(&self, crd: &HydraDoomNode) -> HydraDoomNodeStatus {
+        let (mut status, (available_replicas, ingress_address)) =
+            tokio::join!(self.scrape_status(crd), self.get_readiness(crd));
+        status.available_replicas = available_replicas;
+        status.ingress_address = ingress_address;
+
+        let unhealthy = crd
+            .health()
+            .is_some_and(|health| health.unhealthy.is_some());
+        track_state(crd.status.as_ref(), &mut status, unhealthy, Utc::now());
+
+        status
+    }
+
+    /// Available replicas of the node's deployment and the address of its ingress. Children that
+    /// don't exist yet, or couldn't be read, are left out.
+    async fn get_readiness(&self, crd: &HydraDoomNode) -> (Option<i32>, Option<String>) {
+        let namespace = crd.namespace().unwrap();
+        let name = crd.internal_name();
+        let deployments: Api<Deployment> = Api::namespaced(self.client.clone(), &namespace);
+        let ingresses: Api<Ingress> = Api::namespaced(self.client.clone(), &namespace);
+
+        let (deployment, ingress) =
+            tokio::join!(deployments.get_opt(&name), ingresses.get_opt(&name));
+        let available_replicas = match deployment {
+            Ok(deployment) => deployment.map(|deployment| {
+                deployment
+                    .status
+                    .and_then(|status| status.available_replicas)
+                    .unwrap_or_default()
+            }),
+            Err(err) => {
+                warn!(err = err.to_string(), "Failed to get deployment {}.", name);
+                None
+            }
+        };
+        let ingress_address = match ingress {
+            Ok(ingress) => ingress
+                .and_then(|ingress| ingress.status?.load_balancer?.ingress)
+                .and_then(|ingresses| {
+                    ingresses
+                        .into_iter()
+                        .find_map(|ingress| ingress.hostname.or(ingress.ip))
+                }),
+            Err(err) => {
+                warn!(err = err.to_string(), "Failed to get ingress {}.", name);
+                None
+            }
+        };
+
+        (available_replicas, ingress_address)
+    }
+
+    async fn scrape_status(&self, crd: &HydraDoomNode) -> HydraDoomNodeStatus {
         let url = format!(
             "http://{}:{}{}",
             crd.internal_host(),
@@ -286,6 +410,11 @@ impl K8sContext {
                 node_state: HydraDoomNodeState::Sleeping.into(),
                 game_state: HydraDoomGameState::Done.into(),
                 transactions: 0,
+                open_games: 0,
+                available_replicas: None,
+                ingress_address: None,
+                state_since: None,
+                snapshot_used: false,
                 local_url: self.get_internal_url(crd),
                 external_url: self.get_external_url(crd),
             };
@@ -332,17 +461,36 @@ impl K8sContext {
                                     _ => 0,
                                 });
 
+                            let open_games = metrics
+                                .clone()
+                                .samples
+                                .into_iter()
+                                .find(|sample| sample.metric == self.constants.games_open_metric)
+                                .map(|sample| match sample.value {
+                                    prometheus_parse::Value::Gauge(value) => value.round() as i64,
+                                    _ => 0,
+                                })
+                                .unwrap_or_default();
+
                             match (node_state, game_state, transactions) {
                                 (Some(node_state), Some(game_state), Some(transactions)) => {
                                     HydraDoomNodeStatus {
                                         transactions,
+                                        open_games,
+                                        available_replicas: None,
+                                        ingress_address: None,
+                                        state_since: None,
+                                        snapshot_used: false,
                                         node_state: node_state.into(),
                                         game_state: game_state.into(),
                                         local_url: self.get_internal_url(crd),
                                         external_url: self.get_external_url(crd),
                                     }
                                 }
-                                _ => default,
+                                _ => {
+                                    self.scrape_failed("missing_metrics");
+                                    default
+                                }
                             }
                         }
                         Err(err) => {
@@ -351,6 +499,7 @@ impl K8sContext {
                                 "Failed to parse metrics for {}",
                                 crd.name_any()
                             );
+                            self.scrape_failed("parse");
                             default
                         }
                     }
@@ -361,6 +510,7 @@ impl K8sContext {
                         "Failed to parse request response to metrics endpoint for {}",
                         crd.name_any()
                     );
+                    self.scrape_failed("body");
                     default
                 }
             },
@@ -370,14 +520,28 @@ impl K8sContext {
                     "Failed to request metrics for {}",
                     crd.name_any()
                 );
+                self.scrape_failed("request");
                 default
             }
         }
     }
 
+    fn scrape_failed(&self, reason: &str) {
+        self.metrics
+            .status_scrape_failures
+            .with_label_values(&[reason])
+            .inc();
+    }
+
     async fn patch_statuses(&self) -> anyhow::Result<()> {
         let api: Api<HydraDoomNode> = Api::default_namespaced(self.client.clone());
         let crds = api.list(&ListParams::default()).await?;
+        self.metrics.set_nodes(&crds.items);
+        // Deleted nodes aren't reconciled again, so their failures are only forgotten here
+        self.reconcile_failures
+            .lock()
+            .unwrap()
+            .retain(|name, _| crds.items.iter().any(|crd| &crd.name_any() == name));
 
         let mut awaitables = vec![];
         for crd in &crds {
@@ -385,11 +549,12 @@ impl K8sContext {
                 let name = crd.name_any();
                 let api: Api<HydraDoomNode> =
                     Api::namespaced(self.client.clone(), &crd.namespace().unwrap());
+                let status = self.get_status_from_crd(crd).await;
                 if let Err(err) = api
                     .patch_status(
                         &name,
                         &PatchParams::default(),
-                        &Patch::Merge(json!({ "status": self.get_status_from_crd(crd).await })),
+                        &Patch::Merge(json!({ "status": status })),
                     )
                     .await
                 {
@@ -397,7 +562,9 @@ impl K8sContext {
                         err = err.to_string(),
                         "Failed to update status for CRD {}.", name
                     );
+                    return;
                 };
+                self.record_transitions(crd, &status).await;
             })
         }
 
@@ -406,6 +573,33 @@ impl K8sContext {
         Ok(())
     }
 
+    /// Records an event on the node for each of its states that changed since the last status.
+    async fn record_transitions(&self, crd: &HydraDoomNode, status: &HydraDoomNodeStatus) {
+        let Some(previous) = &crd.status else {
+            return;
+        };
+
+        let recorder = self.recorder(crd);
+        for (reason, from, to) in [
+            ("NodeStateChanged", &previous.node_state, &status.node_state),
+            ("GameStateChanged", &previous.game_state, &status.game_state),
+        ] {
+            if from != to {
+                publish(
+                    &recorder,
+                    Event {
+                        type_: EventType::Normal,
+                        reason: reason.to_string(),
+                        note: Some(format!("{} -> {}", from, to)),
+                        action: "Observe".to_string(),
+                        secondary: None,
+                    },
+                )
+                .await;
+            }
+        }
+    }
+
     async fn get_snapshot(&self) -> Option<String> {
         let mut response = self
             .s3_client
@@ -430,6 +624,24 @@ impl K8sContext {
         }
     }
 
+    /// Counts the snapshots under the available prefix, across every page of the listing.
+    async fn count_snapshots(&self) -> anyhow::Result<usize> {
+        let mut pages = self
+            .s3_client
+            .list_objects_v2()
+            .bucket(self.config.bucket.clone())
+            .prefix(self.config.available_snapshot_prefix.clone())
+            .into_paginator()
+            .send();
+
+        let mut count = 0;
+        while let Some(page) = pages.next().await {
+            count += page?.contents().len();
+        }
+
+        Ok(count)
+    }
+
     async fn use_snapshot(&self, snapshot_key: &str) -> anyhow::Result<String> {
         let new_key = snapshot_key.replace(
             &self.config.available_snapshot_prefix,
@@ -470,6 +682,8 @@ impl K8sContext {
                         network_id: None,
                         start_chain_from: None,
                         resources: None,
+                        max_games: None,
+                        draining: None,
                         snapshot: Some(new_snapshot_key),
                     },
                     Err(e) => {
@@ -524,14 +738,45 @@ impl K8sContext {
         }
     }
 
+    /// Moves a snapshot back from the used to the available prefix, for another node to take.
+    async fn release_snapshot(&self, snapshot_key: &str) -> anyhow::Result<()> {
+        let new_key = snapshot_key.replacen(
+            &self.constants.used_snapshot_prefix,
+            &self.config.available_snapshot_prefix,
+            1,
+        );
+
+        self.s3_client
+            .copy_object()
+            .copy_source(format!("{}/{}", self.config.bucket, snapshot_key))
+            .bucket(self.config.bucket.clone())
+            .key(new_key)
+            .send()
+            .await?;
+        self.s3_client
+            .delete_object()
+            .bucket(self.config.bucket.clone())
+            .key(snapshot_key)
+            .send()
+            .await?;
+
+        Ok(())
+    }
+
     pub async fn scale(&self) -> anyhow::Result<()> {
         let api: Api<HydraDoomNode> = Api::default_namespaced(self.client.clone());
         let crds = api.list(&ListParams::default()).await?;
 
         let mut available_hydra_nodes: Vec<HydraDoomNode> = crds
             .into_iter()
+            .filter(|crd| !crd.spec.draining.unwrap_or(false))
+            // Claimed nodes are about to host a game, even if their status doesn't show it yet
+            .filter(|crd| crd.live_claims().is_empty())
             .filter(|crd| match &crd.status {
-                Some(status) => status.game_state == String::from(HydraDoomGameState::Waiting),
+                Some(status) => {
+                    status.game_state == String::from(HydraDoomGameState::Waiting)
+                        && status.node_state != String::from(HydraDoomNodeState::Unhealthy)
+                }
                 None => false,
             })
             .collect();
@@ -552,6 +797,13 @@ impl K8sContext {
             "Amount of nodes in waiting state: {}",
             available_hydra_nodes.len()
         );
+        self.metrics
+            .available_nodes
+            .set(available_hydra_nodes.len() as i64);
+        match self.count_snapshots().await {
+            Ok(count) => self.metrics.available_snapshots.set(count as i64),
+            Err(err) => warn!(err = err.to_string(), "Failed to count snapshots."),
+        }
 
         if available_hydra_nodes.len() < self.config.autoscaler_low_watermark {
             info!(
@@ -569,6 +821,7 @@ impl K8sContext {
             // One after the other to avoid race conditions.
             for _ in 0..amount {
                 self.deploy_node().await?;
+                self.metrics.scale_events.with_label_values(&["out"]).inc();
             }
         } else if available_hydra_nodes.len() > self.config.autoscaler_high_watermark {
             while available_hydra_nodes.len() > self.config.autoscaler_high_watermark {
@@ -580,34 +833,375 @@ impl K8sContext {
                 // High watermark will never be < 1.
                 self.remove_node(&available_hydra_nodes.pop().unwrap())
                     .await?;
+                self.metrics.scale_events.with_label_values(&["in"]).inc();
+            }
+        }
+
+        Ok(())
+    }
+
+    pub async fn check_health(&self) -> anyhow::Result<()> {
+        let api: Api<HydraDoomNode> = Api::default_namespaced(self.client.clone());
+        let crds = api.list(&ListParams::default()).await?;
+
+        // One after the other, so that replacements respect the cap.
+        for crd in &crds {
+            if let Err(err) = self.check_node_health(crd).await {
+                warn!(
+                    err = err.to_string(),
+                    "Failed to check health of {}.",
+                    crd.name_any()
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Restarts a node stuck in the same state for too long. Once it used up its restarts, it's
+    /// marked unhealthy and replaced.
+    async fn check_node_health(&self, crd: &HydraDoomNode) -> anyhow::Result<()> {
+        let Some(status) = &crd.status else {
+            return Ok(());
+        };
+        if crd.spec.asleep.unwrap_or(false) {
+            return Ok(());
+        }
+        let health = crd.health().unwrap_or_default();
+        let recorder = self.recorder(crd);
+
+        match HealthPolicy::from(&self.config).action(status, &health, Utc::now()) {
+            HealthAction::Nothing => {}
+            HealthAction::Recover => {
+                info!("Node {} recovered.", crd.name_any());
+                // Keeps the restart time, changing it would restart the node again.
+                self.set_health(
+                    crd,
+                    &NodeHealth {
+                        restarted_at: health.restarted_at,
+                        ..Default::default()
+                    },
+                )
+                .await?;
+                publish(
+                    &recorder,
+                    Event {
+                        type_: EventType::Normal,
+                        reason: "Recovered".to_string(),
+                        note: Some("Head is open".to_string()),
+                        action: "CheckHealth".to_string(),
+                        secondary: None,
+                    },
+                )
+                .await;
+            }
+            HealthAction::Replace(reason) => {
+                self.replace_node(crd, status, &reason, &recorder).await?;
+            }
+            HealthAction::Restart(reason) => {
+                warn!("Restarting node {}: {}.", crd.name_any(), reason);
+                self.set_health(
+                    crd,
+                    &NodeHealth {
+                        restarts: health.restarts + 1,
+                        restarted_at: Some(Time(Utc::now())),
+                        unhealthy: None,
+                    },
+                )
+                .await?;
+                self.metrics
+                    .health_actions
+                    .with_label_values(&["restart"])
+                    .inc();
+                publish(
+                    &recorder,
+                    Event {
+                        type_: EventType::Warning,
+                        reason: "Restarted".to_string(),
+                        note: Some(reason),
+                        action: "CheckHealth".to_string(),
+                        secondary: None,
+                    },
+                )
+                .await;
+            }
+            HealthAction::MarkUnhealthy(reason) => {
+                warn!("Node {} is unhealthy: {}.", crd.name_any(), reason);
+                self.set_health(
+                    crd,
+                    &NodeHealth {
+                        unhealthy: Some(reason.clone()),
+                        ..health
+                    },
+                )
+                .await?;
+                self.metrics
+                    .health_actions
+                    .with_label_values(&["unhealthy"])
+                    .inc();
+                publish(
+                    &recorder,
+                    Event {
+                        type_: EventType::Warning,
+                        reason: "Unhealthy".to_string(),
+                        note: Some(reason.clone()),
+                        action: "CheckHealth".to_string(),
+                        secondary: None,
+                    },
+                )
+                .await;
+                self.replace_node(crd, status, &reason, &recorder).await?;
             }
         }
 
         Ok(())
     }
+
+    async fn set_health(&self, crd: &HydraDoomNode, health: &NodeHealth) -> anyhow::Result<()> {
+        let api: Api<HydraDoomNode> =
+            Api::namespaced(self.client.clone(), &crd.namespace().unwrap());
+        api.patch(
+            &crd.name_any(),
+            &PatchParams::default(),
+            &Patch::Merge(json!({
+                "metadata": {
+                    "annotations": { HEALTH_ANNOTATION: serde_json::to_string(health)? }
+                }
+            })),
+        )
+        .await?;
+
+        Ok(())
+    }
+
+    /// Removes an unhealthy node, leaving it to the autoscaler to deploy a fresh one. Its snapshot
+    /// goes back to the available ones if the head never got to use it.
+    async fn replace_node(
+        &self,
+        crd: &HydraDoomNode,
+        status: &HydraDoomNodeStatus,
+        reason: &str,
+        recorder: &Recorder,
+    ) -> anyhow::Result<()> {
+        if status.open_games > 0 || !crd.live_claims().is_empty() {
+            info!("Not replacing node {}, it has games on it.", crd.name_any());
+            return Ok(());
+        }
+
+        {
+            let mut replacements = self.replacements.lock().unwrap();
+            let now = Instant::now();
+            while replacements
+                .front()
+                .is_some_and(|at| now.duration_since(*at) > self.config.health_replacement_window)
+            {
+                replacements.pop_front();
+            }
+            if replacements.len() >= self.config.health_max_replacements {
+                info!(
+                    "Not replacing node {} yet, {} nodes were replaced recently.",
+                    crd.name_any(),
+                    replacements.len()
+                );
+                return Ok(());
+            }
+        }
+
+        self.remove_node(crd).await?;
+        // Only counted once the node is gone, failed attempts are retried on the next check.
+        // Nodes are checked one after the other, so the cap can't be overrun in between.
+        self.replacements.lock().unwrap().push_back(Instant::now());
+        self.metrics
+            .health_actions
+            .with_label_values(&["replace"])
+            .inc();
+
+        if let Some(snapshot) = crd.spec.snapshot.as_ref().filter(|_| !status.snapshot_used) {
+            info!("Returning unused snapshot {}.", snapshot);
+            if let Err(err) = self.release_snapshot(snapshot).await {
+                warn!(
+                    err = err.to_string(),
+                    "Failed to return snapshot {}.", snapshot
+                );
+            }
+        }
+
+        publish(
+            recorder,
+            Event {
+                type_: EventType::Warning,
+                reason: "Replaced".to_string(),
+                note: Some(reason.to_string()),
+                action: "CheckHealth".to_string(),
+                secondary: None,
+            },
+        )
+        .await;
+
+        Ok(())
+    }
 }
 
-pub async fn patch_statuses(context: Arc<K8sContext>) -> Result<()> {
+/// Tracks when the node entered its current state and whether its snapshot was used, from its
+/// previous status. Nodes given up on stay unhealthy until they're replaced, unless they recover
+/// meanwhile.
+fn track_state(
+    previous: Option<&HydraDoomNodeStatus>,
+    status: &mut HydraDoomNodeStatus,
+    unhealthy: bool,
+    now: DateTime<Utc>,
+) {
+    if unhealthy && status.node_state != String::from(HydraDoomNodeState::HeadIsOpen) {
+        status.node_state = HydraDoomNodeState::Unhealthy.into();
+    }
+
+    status.state_since = match previous {
+        Some(previous)
+            if previous.node_state == status.node_state && previous.state_since.is_some() =>
+        {
+            previous.state_since.clone()
+        }
+        _ => Some(Time(now)),
+    };
+    status.snapshot_used = previous.is_some_and(|previous| previous.snapshot_used)
+        || status.node_state == String::from(HydraDoomNodeState::HeadIsInitializing)
+        || status.node_state == String::from(HydraDoomNodeState::HeadIsOpen);
+}
+
+/// What the health checker does about a node on a check.
+#[derive(Debug, PartialEq)]
+enum HealthAction {
+    Nothing,
+    /// The head opened after the node was restarted or given up on.
+    Recover,
+    Restart(String),
+    /// The node used up its restarts, it's given up on and replaced.
+    MarkUnhealthy(String),
+    /// The node was given up on before, but couldn't be replaced yet.
+    Replace(String),
+}
+
+/// The health checker settings of `Config`.
+struct HealthPolicy {
+    offline_timeout: Duration,
+    online_timeout: Duration,
+    initializing_timeout: Duration,
+    max_restarts: u32,
+}
+
+impl From<&Config> for HealthPolicy {
+    fn from(config: &Config) -> Self {
+        Self {
+            offline_timeout: config.health_offline_timeout,
+            online_timeout: config.health_online_timeout,
+            initializing_timeout: config.health_initializing_timeout,
+            max_restarts: config.health_max_restarts,
+        }
+    }
+}
+
+impl HealthPolicy {
+    /// How long a node may stay in the given state before it's considered stuck.
+    fn timeout(&self, node_state: &str) -> Option<Duration> {
+        if node_state == String::from(HydraDoomNodeState::Offline) {
+            Some(self.offline_timeout)
+        } else if node_state == String::from(HydraDoomNodeState::Online) {
+            Some(self.online_timeout)
+        } else if node_state == String::from(HydraDoomNodeState::HeadIsInitializing) {
+            Some(self.initializing_timeout)
+        } else {
+            None
+        }
+    }
+
+    fn action(
+        &self,
+        status: &HydraDoomNodeStatus,
+        health: &NodeHealth,
+        now: DateTime<Utc>,
+    ) -> HealthAction {
+        if status.node_state == String::from(HydraDoomNodeState::HeadIsOpen) {
+            return if health.restarts > 0 || health.unhealthy.is_some() {
+                HealthAction::Recover
+            } else {
+                HealthAction::Nothing
+            };
+        }
+        if let Some(reason) = &health.unhealthy {
+            return HealthAction::Replace(reason.clone());
+        }
+
+        let Some(timeout) = self.timeout(&status.node_state) else {
+            return HealthAction::Nothing;
+        };
+        // A restart doesn't necessarily change the state, so it restarts the clock as well.
+        let Some(since) = [status.state_since.as_ref(), health.restarted_at.as_ref()]
+            .into_iter()
+            .flatten()
+            .map(|time| time.0)
+            .max()
+        else {
+            return HealthAction::Nothing;
+        };
+        if (now - since).to_std().unwrap_or_default() < timeout {
+            return HealthAction::Nothing;
+        }
+
+        let reason = format!("{} for more than {}s", status.node_state, timeout.as_secs());
+        if health.restarts < self.max_restarts {
+            HealthAction::Restart(reason)
+        } else {
+            HealthAction::MarkUnhealthy(reason)
+        }
+    }
+}
+
+pub async fn patch_statuses(context: Arc<K8sContext>) {
     info!("Running status patcher loop.");
 
     loop {
-        context.patch_statuses().await?;
+        // Failures are usually transient, like the API server or S3 being briefly unreachable, so
+        // the next round retries.
+        if let Err(err) = context.patch_statuses().await {
+            warn!(err = err.to_string(), "Failed to patch statuses.");
+        }
         tokio::time::sleep(Duration::from_secs(5)).await;
     }
 }
 
-pub async fn run_autoscaler(context: Arc<K8sContext>) -> Result<()> {
+pub async fn run_autoscaler(context: Arc<K8sContext>) {
     info!("Running autoscaler loop.");
 
     loop {
-        context.scale().await?;
+        if let Err(err) = context.scale().await {
+            warn!(err = err.to_string(), "Failed to scale nodes.");
+        }
         tokio::time::sleep(context.config.autoscaler_delay).await;
     }
 }
 
+async fn publish(recorder: &Recorder, event: Event) {
+    if let Err(err) = recorder.publish(event).await {
+        warn!(err = err.to_string(), "Failed to publish event.");
+    }
+}
+
+pub async fn run_health_checker(context: Arc<K8sContext>) {
+    info!("Running health checker loop.");
+
+    loop {
+        if let Err(err) = context.check_health().await {
+            warn!(err = err.to_string(), "Failed to check node health.");
+        }
+        tokio::time::sleep(context.config.health_check_interval).await;
+    }
+}
+
 // Auxiliary error value because K8s controller api doesnt go along with anyhow.
 #[derive(Debug, Error)]
 pub enum Error {
+    #[error("failed to apply {}", failed_resources(.0))]
+    Apply(Vec<(&'static str, kube::Error)>),
     #[error("ReconcileError")]
     ReconcileError,
 }
@@ -619,17 +1213,225 @@ impl From<anyhow::Error> for Error {
 }
 type Result<T, E = Error> = std::result::Result<T, E>;
 
+fn failed_resources(failures: &[(&'static str, kube::Error)]) -> String {
+    failures
+        .iter()
+        .map(|(resource, _)| *resource)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+const MAX_BACKOFF: Duration = Duration::from_secs(300);
+
+impl Error {
+    /// How long to wait before the first retry. Conflicts, throttling and server errors clear up
+    /// on their own, while a rejected child will keep being rejected until the node changes.
+    fn backoff(&self) -> Duration {
+        match self {
+            Error::Apply(failures) if failures.iter().all(|(_, err)| is_transient(err)) => {
+                Duration::from_secs(5)
+            }
+            Error::Apply(_) => Duration::from_secs(60),
+            Error::ReconcileError => Duration::from_secs(15),
+        }
+    }
+}
+
+fn is_transient(err: &kube::Error) -> bool {
+    match err {
+        kube::Error::Api(response) => {
+            response.code == 409 || response.code == 429 || response.code >= 500
+        }
+        // Connection errors and the like.
+        _ => true,
+    }
+}
+
 pub async fn reconcile(crd: Arc<HydraDoomNode>, ctx: Arc<K8sContext>) -> Result<Action, Error> {
     tracing::info!("Reconciling {}", crd.name_any());
-    ctx.patch(&crd).await?;
+    ctx.patch(&crd, &ctx.recorder(&crd)).await?;
+    ctx.reconcile_failures
+        .lock()
+        .unwrap()
+        .remove(&crd.name_any());
     Ok(Action::await_change())
 }
 
-pub fn error_policy(crd: Arc<HydraDoomNode>, err: &Error, _ctx: Arc<K8sContext>) -> Action {
+pub fn error_policy(crd: Arc<HydraDoomNode>, err: &Error, ctx: Arc<K8sContext>) -> Action {
     error!(
         error = err.to_string(),
         crd = serde_json::to_string(&crd).unwrap(),
         "reconcile failed"
     );
-    Action::requeue(Duration::from_secs(5))
+    let mut failures = ctx.reconcile_failures.lock().unwrap();
+    let attempts = failures.entry(crd.name_any()).or_default();
+    *attempts = attempts.saturating_add(1);
+
+    Action::requeue(retry_backoff(err, *attempts))
+}
+
+/// Doubles the wait on every consecutive failure of the same node, up to `MAX_BACKOFF`.
+fn retry_backoff(err: &Error, attempts: u32) -> Duration {
+    err.backoff()
+        .saturating_mul(1 << attempts.saturating_sub(1).min(10))
+        .min(MAX_BACKOFF)
+}
+
+#[cfg(test)]
+mod tests {
+    use kube::core::ErrorResponse;
+
+    use super::*;
+
+    fn api_error(code: u16) -> kube::Error {
+        kube::Error::Api(ErrorResponse {
+            status: "Failure".to_string(),
+            message: String::new(),
+            reason: String::new(),
+            code,
+        })
+    }
+
+    #[test]
+    fn test_is_transient() {
+        assert!(is_transient(&api_error(409)));
+        assert!(is_transient(&api_error(429)));
+        assert!(is_transient(&api_error(503)));
+        assert!(is_transient(&kube::Error::ReadEvents(
+            std::io::Error::other("connection reset")
+        )));
+        assert!(!is_transient(&api_error(400)));
+        assert!(!is_transient(&api_error(422)));
+    }
+
+    #[test]
+    fn test_backoff() {
+        let transient = Error::Apply(vec![
+            ("service", api_error(409)),
+            ("ingress", api_error(503)),
+        ]);
+        let rejected = Error::Apply(vec![
+            ("service", api_error(409)),
+            ("ingress", api_error(422)),
+        ]);
+        assert_eq!(transient.backoff(), Duration::from_secs(5));
+        assert_eq!(rejected.backoff(), Duration::from_secs(60));
+        assert_eq!(Error::ReconcileError.backoff(), Duration::from_secs(15));
+
+        assert_eq!(retry_backoff(&transient, 1), Duration::from_secs(5));
+        assert_eq!(retry_backoff(&transient, 3), Duration::from_secs(20));
+        assert_eq!(retry_backoff(&rejected, 4), MAX_BACKOFF);
+        assert_eq!(retry_backoff(&transient, u32::MAX), MAX_BACKOFF);
+    }
+
+    fn policy() -> HealthPolicy {
+        HealthPolicy {
+            offline_timeout: Duration::from_secs(600),
+            online_timeout: Duration::from_secs(300),
+            initializing_timeout: Duration::from_secs(900),
+            max_restarts: 1,
+        }
+    }
+
+    fn at(seconds: i64) -> DateTime<Utc> {
+        DateTime::from_timestamp(1_700_000_000 + seconds, 0).unwrap()
+    }
+
+    fn status(node_state: &str, since: i64) -> HydraDoomNodeStatus {
+        HydraDoomNodeStatus {
+            node_state: node_state.to_string(),
+            state_since: Some(Time(at(since))),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_health_timeout() {
+        let policy = policy();
+        assert_eq!(policy.timeout("Offline"), Some(Duration::from_secs(600)));
+        assert_eq!(policy.timeout("Online"), Some(Duration::from_secs(300)));
+        assert_eq!(
+            policy.timeout("HeadIsInitializing"),
+            Some(Duration::from_secs(900))
+        );
+        assert_eq!(policy.timeout("HeadIsOpen"), None);
+        assert_eq!(policy.timeout("Sleeping"), None);
+        assert_eq!(policy.timeout("Unhealthy"), None);
+    }
+
+    #[test]
+    fn test_track_state() {
+        let mut offline = status("Offline", 0);
+        track_state(None, &mut offline, false, at(10));
+        assert_eq!(offline.state_since, Some(Time(at(10))));
+        assert!(!offline.snapshot_used);
+
+        // Same state, the clock keeps running
+        let mut still_offline = status("Offline", 20);
+        track_state(Some(&offline), &mut still_offline, false, at(20));
+        assert_eq!(still_offline.state_since, Some(Time(at(10))));
+
+        let mut initializing = status("HeadIsInitializing", 0);
+        track_state(Some(&still_offline), &mut initializing, false, at(30));
+        assert_eq!(initializing.state_since, Some(Time(at(30))));
+        assert!(initializing.snapshot_used);
+
+        // Once used, the snapshot stays used, and unhealthy nodes are reported as such
+        let mut unhealthy = status("Offline", 0);
+        track_state(Some(&initializing), &mut unhealthy, true, at(40));
+        assert_eq!(unhealthy.node_state, "Unhealthy");
+        assert_eq!(unhealthy.state_since, Some(Time(at(40))));
+        assert!(unhealthy.snapshot_used);
+
+        // Unless the head opened after all
+        let mut open = status("HeadIsOpen", 0);
+        track_state(Some(&unhealthy), &mut open, true, at(50));
+        assert_eq!(open.node_state, "HeadIsOpen");
+    }
+
+    #[test]
+    fn test_restart_then_replace() {
+        let policy = policy();
+        let offline = status("Offline", 0);
+        let mut health = NodeHealth::default();
+
+        assert_eq!(
+            policy.action(&offline, &health, at(599)),
+            HealthAction::Nothing
+        );
+        let HealthAction::Restart(reason) = policy.action(&offline, &health, at(600)) else {
+            panic!("expected a restart");
+        };
+        assert_eq!(reason, "Offline for more than 600s");
+        health = NodeHealth {
+            restarts: 1,
+            restarted_at: Some(Time(at(600))),
+            unhealthy: None,
+        };
+
+        // The restart restarts the clock, even though the state didn't change
+        assert_eq!(
+            policy.action(&offline, &health, at(1199)),
+            HealthAction::Nothing
+        );
+        let HealthAction::MarkUnhealthy(reason) = policy.action(&offline, &health, at(1200)) else {
+            panic!("expected the node to be given up on");
+        };
+        health.unhealthy = Some(reason.clone());
+
+        // Replacing is retried on the following checks, until it goes through
+        let unhealthy = status("Unhealthy", 1200);
+        assert_eq!(
+            policy.action(&unhealthy, &health, at(1230)),
+            HealthAction::Replace(reason)
+        );
+        assert_eq!(
+            policy.action(&status("HeadIsOpen", 1250), &health, at(1260)),
+            HealthAction::Recover
+        );
+        assert_eq!(
+            policy.action(&status("HeadIsOpen", 0), &NodeHealth::default(), at(1260)),
+            HealthAction::Nothing
+        );
+    }
 }
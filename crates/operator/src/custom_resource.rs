@@ -11,7 +11,11 @@ use k8s_openapi::{
             IngressServiceBackend, IngressSpec, ServiceBackendPort,
         },
     },
-    apimachinery::pkg::{api::resource::Quantity, apis::meta::v1::OwnerReference},
+    apimachinery::pkg::{
+        api::resource::Quantity,
+        apis::meta::v1::{OwnerReference, Time},
+    },
+    chrono::Utc,
 };
 use kube::{api::ObjectMeta, CustomResource, Resource, ResourceExt};
 use schemars::JsonSchema;
@@ -80,6 +84,7 @@ impl From<Resources> for ResourceRequirements {
         {"name": "Node State", "jsonPath":".status.nodeState", "type": "string"},
         {"name": "Game State", "jsonPath":".status.gameState", "type": "string"},
         {"name": "Transactions", "jsonPath":".status.transactions", "type": "string"},
+        {"name": "Available", "jsonPath":".status.availableReplicas", "type": "integer"},
         {"name": "Local URI", "jsonPath":".status.localUrl", "type": "string"},
         {"name": "External URI", "jsonPath": ".status.externalUrl", "type": "string"}
     "#)]
@@ -91,6 +96,10 @@ pub struct HydraDoomNodeSpec {
     pub start_chain_from: Option<String>,
     pub asleep: Option<bool>,
     pub resources: Option<Resources>,
+    /// How many games the head may host at the same time. Defaults to a single game.
+    pub max_games: Option<u32>,
+    /// Draining heads finish their current games but aren't scheduled new ones.
+    pub draining: Option<bool>,
 }
 
 impl Default for HydraDoomNodeSpec {
@@ -102,6 +111,8 @@ impl Default for HydraDoomNodeSpec {
             start_chain_from: None,
             asleep: None,
             resources: None,
+            max_games: None,
+            draining: None,
         }
     }
 }
@@ -114,13 +125,63 @@ pub struct HydraDoomNodeStatus {
     pub node_state: String,
     pub game_state: String,
     pub transactions: i64,
+    #[serde(default)]
+    pub open_games: i64,
+    /// Replicas of the node's deployment that are available, if the deployment exists.
+    #[serde(default)]
+    pub available_replicas: Option<i32>,
+    /// Address the ingress controller assigned to the node's ingress, once there is one.
+    #[serde(default)]
+    pub ingress_address: Option<String>,
+    /// When the node entered its current node state.
+    #[serde(default)]
+    #[schemars(with = "Option<String>")]
+    pub state_since: Option<Time>,
+    /// Whether the head got as far as initializing, after which its snapshot can't be reused.
+    #[serde(default)]
+    pub snapshot_used: bool,
+}
+
+/// Annotation holding the claims control plane replicas put on a node while they create a game on
+/// it, as a JSON list of `NodeClaim`s.
+pub const CLAIMS_ANNOTATION: &str = "hydra.doom/claims";
+
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct NodeClaim {
+    pub claimed_by: String,
+    pub claimed_at: Time,
+    /// Set by the replica making the claim, after which the claim stops counting in case the game
+    /// it was made for was never created.
+    pub expires_at: Time,
 }
+
+/// Annotation holding what the health checker did to a node since it was last healthy, as a JSON
+/// `NodeHealth`.
+pub const HEALTH_ANNOTATION: &str = "hydra.doom/health";
+// Set on the pod template, so that changing it rolls the node's pods.
+const RESTARTED_AT_ANNOTATION: &str = "hydra.doom/restarted-at";
+
+#[derive(Deserialize, Serialize, Clone, Debug, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct NodeHealth {
+    pub restarts: u32,
+    pub restarted_at: Option<Time>,
+    /// Why the node was given up on, once restarting it didn't help.
+    pub unhealthy: Option<String>,
+}
+
 impl HydraDoomNodeStatus {
     pub fn offline(crd: &HydraDoomNode, config: &Config, constants: &K8sConstants) -> Self {
         Self {
             node_state: "Offline".to_string(),
             game_state: "Done".to_string(),
             transactions: 0,
+            open_games: 0,
+            available_replicas: None,
+            ingress_address: None,
+            state_since: None,
+            snapshot_used: false,
             local_url: format!("ws://{}:{}", crd.internal_host(), constants.port),
             external_url: format!(
                 "{}://{}:{}",
@@ -133,6 +194,27 @@ impl HydraDoomNodeStatus {
 }
 
 impl HydraDoomNode {
+    /// The claims on this node that haven't expired yet.
+    pub fn live_claims(&self) -> Vec<NodeClaim> {
+        self.metadata
+            .annotations
+            .as_ref()
+            .and_then(|annotations| annotations.get(CLAIMS_ANNOTATION))
+            .and_then(|claims| serde_json::from_str::<Vec<NodeClaim>>(claims).ok())
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|claim| Utc::now() < claim.expires_at.0)
+            .collect()
+    }
+
+    pub fn health(&self) -> Option<NodeHealth> {
+        self.metadata
+            .annotations
+            .as_ref()
+            .and_then(|annotations| annotations.get(HEALTH_ANNOTATION))
+            .and_then(|health| serde_json::from_str(health).ok())
+    }
+
     pub fn internal_name(&self) -> String {
         format!("hydra-doom-node-{}", self.name_any())
     }
@@ -245,6 +327,22 @@ impl HydraDoomNode {
             aux
         };
 
+        let mut sidecar_args = vec![
+            "metrics-exporter".to_string(),
+            "--host".to_string(),
+            "localhost".to_string(),
+            "--port".to_string(),
+            constants.port.to_string(),
+            "--admin-key-file".to_string(),
+            format!("{}/admin.sk", constants.secret_dir),
+            "--evidence-dir".to_string(),
+            format!("{}/evidence", constants.data_dir),
+        ];
+        if let Some(referee_key) = &config.referee_key {
+            sidecar_args.push("--referee-key".to_string());
+            sidecar_args.push(referee_key.clone());
+        }
+
         let mut containers = vec![
             Container {
                 name: "main".to_string(),
@@ -300,20 +398,19 @@ impl HydraDoomNode {
             Container {
                 name: "sidecar".to_string(),
                 image: Some(config.sidecar_image.clone()),
-                args: Some(vec![
-                    "metrics-exporter".to_string(),
-                    "--host".to_string(),
-                    "localhost".to_string(),
-                    "--port".to_string(),
-                    constants.port.to_string(),
-                    "--admin-key-file".to_string(),
-                    format!("{}/admin.sk", constants.secret_dir),
+                args: Some(sidecar_args),
+                volume_mounts: Some(vec![
+                    VolumeMount {
+                        name: "secret".to_string(),
+                        mount_path: constants.secret_dir.clone(),
+                        ..Default::default()
+                    },
+                    VolumeMount {
+                        name: "data".to_string(),
+                        mount_path: constants.data_dir.clone(),
+                        ..Default::default()
+                    },
                 ]),
-                volume_mounts: Some(vec![VolumeMount {
-                    name: "secret".to_string(),
-                    mount_path: constants.secret_dir.clone(),
-                    ..Default::default()
-                }]),
                 ports: Some(vec![ContainerPort {
                     name: Some("metrics".to_string()),
                     container_port: constants.metrics_port,
@@ -506,6 +603,14 @@ impl HydraDoomNode {
                 template: PodTemplateSpec {
                     metadata: Some(ObjectMeta {
                         labels: Some(labels.clone()),
+                        annotations: self.health().and_then(|health| health.restarted_at).map(
+                            |restarted_at| {
+                                BTreeMap::from([(
+                                    RESTARTED_AT_ANNOTATION.to_string(),
+                                    restarted_at.0.to_rfc3339(),
+                                )])
+                            },
+                        ),
                         ..Default::default()
                     }),
                     spec: Some(PodSpec {
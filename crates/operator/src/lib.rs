@@ -1,5 +1,6 @@
 pub mod config;
 pub mod controller;
 pub mod custom_resource;
+pub mod metrics;
 
 pub use custom_resource::HydraDoomNode;
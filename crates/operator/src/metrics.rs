@@ -0,0 +1,201 @@
+use std::{collections::HashMap, convert::Infallible, sync::Arc, time::Duration};
+
+use http_body_util::Full;
+use hyper::{
+    body::{Bytes, Incoming},
+    header::CONTENT_TYPE,
+    server::conn::http1,
+    service::service_fn,
+    Method, Request, Response, StatusCode,
+};
+use hyper_util::rt::TokioIo;
+use prometheus::{
+    exponential_buckets, histogram_opts, opts, Encoder, HistogramVec, IntCounterVec, IntGauge,
+    IntGaugeVec, Registry, TextEncoder,
+};
+use tokio::net::TcpListener;
+use tracing::{info, warn};
+
+use crate::custom_resource::HydraDoomNode;
+
+// Delay before accepting connections again after failing to.
+const ACCEPT_RETRY_DELAY: Duration = Duration::from_secs(1);
+
+/// Metrics about the operator itself, served on `/metrics` by `serve_metrics`.
+pub struct Metrics {
+    registry: Registry,
+    pub reconcile_seconds: HistogramVec,
+    pub reconcile_errors: IntCounterVec,
+    pub nodes: IntGaugeVec,
+    pub scale_events: IntCounterVec,
+    pub available_nodes: IntGauge,
+    pub available_snapshots: IntGauge,
+    pub status_scrape_failures: IntCounterVec,
+    pub health_actions: IntCounterVec,
+}
+
+impl Metrics {
+    pub fn try_new() -> Result<Self, prometheus::Error> {
+        let reconcile_seconds = HistogramVec::new(
+            histogram_opts!(
+                "hydra_doom_operator_reconcile_seconds",
+                "Time taken to apply each child resource of a node, in seconds.",
+                exponential_buckets(0.01, 2.0, 12)?
+            ),
+            &["resource"],
+        )?;
+
+        let reconcile_errors = IntCounterVec::new(
+            opts!(
+                "hydra_doom_operator_reconcile_errors",
+                "Number of failures to apply each child resource of a node."
+            ),
+            &["resource"],
+        )?;
+
+        let nodes = IntGaugeVec::new(
+            opts!(
+                "hydra_doom_operator_nodes",
+                "Number of nodes by their node and game state."
+            ),
+            &["node_state", "game_state"],
+        )?;
+
+        let scale_events = IntCounterVec::new(
+            opts!(
+                "hydra_doom_operator_scale_events",
+                "Number of nodes added or removed by the autoscaler."
+            ),
+            &["direction"],
+        )?;
+
+        let available_nodes = IntGauge::new(
+            "hydra_doom_operator_available_nodes",
+            "Number of nodes available for new games, as seen by the autoscaler.",
+        )?;
+
+        let available_snapshots = IntGauge::new(
+            "hydra_doom_operator_available_snapshots",
+            "Number of unused head snapshots in the bucket.",
+        )?;
+
+        let status_scrape_failures = IntCounterVec::new(
+            opts!(
+                "hydra_doom_operator_status_scrape_failures",
+                "Number of failures to read a node's status from its exporter, by reason."
+            ),
+            &["reason"],
+        )?;
+
+        let health_actions = IntCounterVec::new(
+            opts!(
+                "hydra_doom_operator_health_actions",
+                "Number of stuck nodes the health checker restarted, marked unhealthy or replaced."
+            ),
+            &["action"],
+        )?;
+
+        let registry = Registry::default();
+        registry.register(Box::new(reconcile_seconds.clone()))?;
+        registry.register(Box::new(reconcile_errors.clone()))?;
+        registry.register(Box::new(nodes.clone()))?;
+        registry.register(Box::new(scale_events.clone()))?;
+        registry.register(Box::new(available_nodes.clone()))?;
+        registry.register(Box::new(available_snapshots.clone()))?;
+        registry.register(Box::new(status_scrape_failures.clone()))?;
+        registry.register(Box::new(health_actions.clone()))?;
+
+        Ok(Self {
+            registry,
+            reconcile_seconds,
+            reconcile_errors,
+            nodes,
+            scale_events,
+            available_nodes,
+            available_snapshots,
+            status_scrape_failures,
+            health_actions,
+        })
+    }
+
+    /// Replaces the node counts with those of the given nodes. Nodes without a status yet aren't
+    /// counted.
+    pub fn set_nodes(&self, nodes: &[HydraDoomNode]) {
+        let mut counts: HashMap<(&str, &str), i64> = HashMap::new();
+        for status in nodes.iter().filter_map(|node| node.status.as_ref()) {
+            *counts
+                .entry((status.node_state.as_str(), status.game_state.as_str()))
+                .or_default() += 1;
+        }
+
+        self.nodes.reset();
+        for ((node_state, game_state), count) in counts {
+            self.nodes
+                .with_label_values(&[node_state, game_state])
+                .set(count);
+        }
+    }
+
+    pub fn gather(&self) -> String {
+        let mut buffer = Vec::new();
+        TextEncoder::new()
+            .encode(&self.registry.gather(), &mut buffer)
+            .unwrap();
+
+        String::from_utf8(buffer).unwrap()
+    }
+}
+
+/// Serves the metrics over plain HTTP on `listener`. Any path other than `/metrics` gets a 404.
+/// Failing to accept a connection, e.g. because the process ran out of file descriptors, is
+/// logged and retried after a short delay.
+pub async fn serve_metrics(metrics: Arc<Metrics>, listener: TcpListener) {
+    info!(
+        "Serving metrics on {}",
+        listener
+            .local_addr()
+            .map(|addr| addr.to_string())
+            .unwrap_or_default()
+    );
+
+    loop {
+        let stream = match listener.accept().await {
+            Ok((stream, _)) => stream,
+            Err(err) => {
+                warn!(
+                    err = err.to_string(),
+                    "Failed to accept metrics connection."
+                );
+                tokio::time::sleep(ACCEPT_RETRY_DELAY).await;
+                continue;
+            }
+        };
+
+        let metrics = metrics.clone();
+        tokio::spawn(async move {
+            let service = service_fn(move |request| {
+                let response = respond(&metrics, &request);
+                async move { Ok::<_, Infallible>(response) }
+            });
+            if let Err(err) = http1::Builder::new()
+                .serve_connection(TokioIo::new(stream), service)
+                .await
+            {
+                warn!(err = err.to_string(), "Failed to serve metrics request.");
+            }
+        });
+    }
+}
+
+fn respond(metrics: &Metrics, request: &Request<Incoming>) -> Response<Full<Bytes>> {
+    let response = match (request.method(), request.uri().path()) {
+        (&Method::GET, "/metrics") => Response::builder()
+            .header(CONTENT_TYPE, "text/plain; version=0.0.4")
+            .body(Full::new(Bytes::from(metrics.gather()))),
+        _ => Response::builder()
+            .status(StatusCode::NOT_FOUND)
+            .body(Full::default()),
+    };
+
+    response.expect("static response parts are valid")
+}
@@ -1,25 +1,39 @@
 use anyhow::{Context, Result};
 use clap::{arg, Parser};
+use hex::FromHex;
 use hydra_control_plane_rpc::model::{
-    cluster::{ConnectionInfo, KeyEnvelope},
+    api_error::default_catcher,
+    cluster::{ConnectionInfo, KeyEnvelope, NodeClient},
+    game::scoreboard::{GameEvent, GameEventKind},
     hydra::{
         hydra_message::{HydraData, HydraEventMessage},
         hydra_socket::HydraSocket,
     },
+    telemetry::{Telemetry, TelemetryConfig},
 };
-use pallas::{crypto::key::ed25519::SecretKey, ledger::addresses::Network};
-use rocket::{get, post, routes, State};
+use pallas::{
+    crypto::key::ed25519::{PublicKey, SecretKey},
+    ledger::addresses::Network,
+};
+use rocket::{catchers, get, post, routes, serde::json::Json, State};
 use routes::game::{
-    add_player::add_player, cleanup::cleanup, end_game::end_game as node_end_game,
-    new_game::new_game, start_game::start_game as node_start_game,
+    add_player::add_player, cleanup::cleanup, end_game::end_game as node_end_game, games::games,
+    new_game::new_game, report_cheater::report_cheater, results::game_results,
+    scoreboard::scoreboard, start_game::start_game as node_start_game,
 };
-use std::{env, fs::File, sync::Arc, time::Duration};
+use std::{env, fs::File, path::PathBuf, sync::Arc, time::Duration};
 use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
 use tracing::{error, info, warn};
 
 mod metrics;
+mod orchestrator;
+mod results;
 mod routes;
+mod scoreboards;
 use metrics::{Metrics, NodeState};
+use orchestrator::{run_lobby_timer, GameOrchestrator};
+use results::GameResults;
+use scoreboards::Scoreboards;
 
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
@@ -32,6 +46,22 @@ struct Args {
     secure: bool,
     #[arg(long)]
     admin_key_file: String,
+    /// Seconds to wait for a lobby to fill before starting the game with the players present.
+    #[arg(long, default_value_t = 60)]
+    lobby_timeout: u64,
+    /// Lovelace each player stakes into the game's prize pool, paid from a deposit the player
+    /// committed to their outbound address.
+    #[arg(long, default_value_t = 0)]
+    stake: u64,
+    /// Hex encoded ed25519 public key of the referee. Cheat reports are rejected when unset.
+    #[arg(long)]
+    referee_key: Option<String>,
+    /// Directory where verified cheat reports are stored.
+    #[arg(long, default_value = "evidence")]
+    evidence_dir: PathBuf,
+    /// OTLP gRPC endpoint traces are exported to, e.g. `http://otel-collector:4317`.
+    #[arg(long)]
+    otlp_endpoint: Option<String>,
 }
 
 pub struct LocalState {
@@ -39,6 +69,20 @@ pub struct LocalState {
     hydra: ConnectionInfo,
     admin_key: SecretKey,
     metrics: Arc<Metrics>,
+    orchestrator: Arc<GameOrchestrator>,
+    results: Arc<GameResults>,
+    scoreboards: Arc<Scoreboards>,
+    stake: u64,
+    referee_key: Option<PublicKey>,
+    evidence_dir: PathBuf,
+}
+
+impl LocalState {
+    /// A client for the local node, timing how long its transactions take to confirm.
+    fn node_client(&self) -> NodeClient {
+        NodeClient::new(self.hydra.clone(), self.admin_key.clone(), self.network)
+            .with_confirmation_latency(self.metrics.tx_confirmation_seconds.clone())
+    }
 }
 
 #[rocket::main]
@@ -46,11 +90,13 @@ async fn main() -> Result<()> {
     let (tx, rx): (UnboundedSender<HydraData>, UnboundedReceiver<HydraData>) =
         mpsc::unbounded_channel();
 
-    tracing_subscriber::fmt()
-        .with_max_level(tracing::Level::INFO)
-        .init();
-
     let args = Args::parse();
+    let telemetry = Telemetry::init(
+        "hydra-doom-exporter",
+        &TelemetryConfig {
+            otlp_endpoint: args.otlp_endpoint.clone(),
+        },
+    )?;
     let connection_info = ConnectionInfo {
         host: args.host,
         port: args.port,
@@ -65,6 +111,12 @@ async fn main() -> Result<()> {
         .try_into()
         .context("Failed to get secret key from file")?;
 
+    let referee_key: Option<PublicKey> = args
+        .referee_key
+        .map(|key| <[u8; 32]>::from_hex(key).context("invalid referee key"))
+        .transpose()?
+        .map(PublicKey::from);
+
     let network: Network = env::var("NETWORK_ID")
         .map(|network_str| {
             network_str
@@ -82,6 +134,16 @@ async fn main() -> Result<()> {
         &tx,
     ));
     let metrics = Arc::new(Metrics::try_new().expect("Failed to register metrics."));
+    let scoreboards = Arc::new(Scoreboards::new(metrics.clone()));
+    let results = Arc::new(GameResults::new(scoreboards.clone()));
+    let orchestrator = Arc::new(GameOrchestrator::new(
+        connection_info.clone(),
+        admin_key.clone(),
+        network,
+        metrics.clone(),
+        results.clone(),
+        Duration::from_secs(args.lobby_timeout),
+    ));
 
     // Initialize websocket.
     socket.listen();
@@ -89,14 +151,22 @@ async fn main() -> Result<()> {
     // Check online status.
     tokio::spawn(update_connection_state(metrics.clone(), socket.clone()));
     // Listen and update metrics.
-    tokio::spawn(update(metrics.clone(), rx));
+    tokio::spawn(update(metrics.clone(), orchestrator.clone(), rx));
+    // Start games whose lobby filled up or timed out.
+    tokio::spawn(run_lobby_timer(orchestrator.clone()));
 
     let _ = rocket::build()
         .manage(LocalState {
             admin_key,
             hydra: connection_info,
             metrics,
+            orchestrator,
+            results,
+            scoreboards,
             network,
+            stake: args.stake,
+            referee_key,
+            evidence_dir: args.evidence_dir,
         })
         .mount(
             "/",
@@ -107,6 +177,8 @@ async fn main() -> Result<()> {
                 end_game,
                 player_joined,
                 player_left,
+                bot_joined,
+                bot_left,
                 player_killed,
                 player_suicided,
                 new_game,
@@ -114,10 +186,16 @@ async fn main() -> Result<()> {
                 node_start_game,
                 node_end_game,
                 cleanup,
+                report_cheater,
+                game_results,
+                games,
+                scoreboard,
             ],
         )
+        .register("/", catchers![default_catcher])
         .launch()
         .await?;
+    telemetry.shutdown();
 
     Ok(())
 }
@@ -132,34 +210,68 @@ fn start_server(state: &State<LocalState>) {
     state.metrics.start_server();
 }
 
+// Games are timed from their states by the orchestrator, so this is only kept for the game
+// servers that still report it.
 #[post("/start_game")]
-fn start_game(state: &State<LocalState>) {
-    state.metrics.start_game();
-}
+fn start_game() {}
 
 #[post("/end_game")]
 fn end_game(state: &State<LocalState>) {
     state.metrics.end_game();
 }
 
-#[post("/player_joined")]
-fn player_joined(state: &State<LocalState>) {
+#[post("/player_joined", data = "<event>")]
+fn player_joined(event: Option<Json<GameEvent>>, state: &State<LocalState>) {
     state.metrics.player_joined();
+    if let Some(event) = event {
+        apply_event(state, GameEventKind::Joined, &event);
+    }
 }
 
-#[post("/player_left")]
-fn player_left(state: &State<LocalState>) {
+#[post("/player_left", data = "<event>")]
+fn player_left(event: Option<Json<GameEvent>>, state: &State<LocalState>) {
     state.metrics.player_left();
+    if let Some(event) = event {
+        apply_event(state, GameEventKind::Left, &event);
+    }
 }
 
-#[post("/player_killed")]
-fn player_killed(state: &State<LocalState>) {
+#[post("/bot_joined")]
+fn bot_joined(state: &State<LocalState>) {
+    state.metrics.bot_joined();
+}
+
+#[post("/bot_left")]
+fn bot_left(state: &State<LocalState>) {
+    state.metrics.bot_left();
+}
+
+#[post("/player_killed", data = "<event>")]
+fn player_killed(event: Option<Json<GameEvent>>, state: &State<LocalState>) {
     state.metrics.player_killed();
+    if let Some(event) = event {
+        apply_event(state, GameEventKind::Killed, &event);
+    }
 }
 
-#[post("/player_suicided")]
-fn player_suicided(state: &State<LocalState>) {
+#[post("/player_suicided", data = "<event>")]
+fn player_suicided(event: Option<Json<GameEvent>>, state: &State<LocalState>) {
     state.metrics.player_suicided();
+    if let Some(event) = event {
+        apply_event(state, GameEventKind::Suicided, &event);
+    }
+}
+
+/// Keeps the scoreboards of the games being played on this node, events about other games are
+/// dropped.
+fn apply_event(state: &LocalState, kind: GameEventKind, event: &GameEvent) {
+    match state.orchestrator.roster(&event.game_id) {
+        Some(roster) => state.scoreboards.apply(kind, event, &roster),
+        None => warn!(
+            game_id = event.game_id,
+            "dropping event for an unknown game"
+        ),
+    }
 }
 
 async fn update_connection_state(metrics: Arc<Metrics>, socket: Arc<HydraSocket>) {
@@ -167,6 +279,8 @@ async fn update_connection_state(metrics: Arc<Metrics>, socket: Arc<HydraSocket>
         tokio::time::sleep(Duration::from_secs(10)).await;
         let current_value = metrics.node_state.get();
         let is_online = socket.online.load(std::sync::atomic::Ordering::SeqCst);
+        metrics
+            .set_websocket_reconnects(socket.reconnects.load(std::sync::atomic::Ordering::SeqCst));
 
         if !is_online {
             metrics.set_node_state(NodeState::Offline);
@@ -176,7 +290,11 @@ async fn update_connection_state(metrics: Arc<Metrics>, socket: Arc<HydraSocket>
     }
 }
 
-async fn update(metrics: Arc<Metrics>, mut rx: UnboundedReceiver<HydraData>) {
+async fn update(
+    metrics: Arc<Metrics>,
+    orchestrator: Arc<GameOrchestrator>,
+    mut rx: UnboundedReceiver<HydraData>,
+) {
     loop {
         match rx.recv().await {
             Some(HydraData::Received { message, .. }) => match message {
@@ -185,14 +303,14 @@ async fn update(metrics: Arc<Metrics>, mut rx: UnboundedReceiver<HydraData>) {
                     metrics.set_node_state(metrics::NodeState::HeadIsOpen);
                 }
                 HydraEventMessage::CommandFailed(command_failed) => {
-                    println!("command failed {:?}", command_failed);
+                    warn!("command failed {:?}", command_failed);
                 }
                 HydraEventMessage::HeadIsInitializing(_) => {
                     info!("node is initializing a head, marking as occupied");
                     metrics.set_node_state(NodeState::HeadIsInitializing);
                 }
                 HydraEventMessage::InvalidInput(invalid_input) => {
-                    println!("Received InvalidInput: {:?}", invalid_input);
+                    warn!("Received InvalidInput: {:?}", invalid_input);
                 }
                 HydraEventMessage::Greetings(greetings) => {
                     match greetings.head_status.as_ref() {
@@ -203,6 +321,24 @@ async fn update(metrics: Arc<Metrics>, mut rx: UnboundedReceiver<HydraData>) {
                 }
                 HydraEventMessage::TxValid(valid) => {
                     metrics.new_transaction(valid.transaction.cbor.len() as u64);
+                    orchestrator.observe_tx(&valid);
+                }
+                HydraEventMessage::TxInvalid(invalid) => {
+                    metrics.invalid_transaction(invalid.reason_label());
+                }
+                HydraEventMessage::PeerConnected(peer) => {
+                    metrics.peer_connected(&peer.peer);
+                }
+                HydraEventMessage::PeerDisconnected(peer) => {
+                    metrics.peer_disconnected(&peer.peer);
+                }
+                HydraEventMessage::SnapshotConfirmed(snapshot) => {
+                    metrics.snapshot_confirmed(
+                        snapshot.snapshot_number,
+                        &snapshot.timestamp,
+                        snapshot.confirmed_transactions.len(),
+                    );
+                    orchestrator.observe(&snapshot.utxo);
                 }
                 _ => {}
             },
@@ -1,8 +1,16 @@
-use std::sync::Mutex;
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Mutex,
+};
 
+use chrono::{DateTime, FixedOffset};
+use hydra_control_plane_rpc::model::game::{
+    contract::game_state::State,
+    scoreboard::{PlayerScore, Scoreboard},
+};
 use prometheus::{
-    histogram_opts, linear_buckets, Encoder, Histogram, HistogramTimer, IntCounter, IntGauge,
-    Registry, TextEncoder,
+    exponential_buckets, histogram_opts, linear_buckets, opts, Encoder, Histogram, HistogramTimer,
+    IntCounter, IntCounterVec, IntGauge, IntGaugeVec, Registry, TextEncoder,
 };
 
 pub enum NodeState {
@@ -23,6 +31,7 @@ impl From<NodeState> for i64 {
     }
 }
 
+#[derive(Debug, PartialEq)]
 pub enum GameState {
     Waiting,
     Lobby,
@@ -30,6 +39,28 @@ pub enum GameState {
     Done,
 }
 
+impl GameState {
+    /// The state of the node given the states of the games in its head: running while any game
+    /// is, otherwise in the lobby while any game is, and done while finished games haven't been
+    /// collected yet.
+    pub fn of_games(states: impl IntoIterator<Item = State>) -> Self {
+        states
+            .into_iter()
+            .map(|state| match state {
+                State::Running => GameState::Running,
+                State::Lobby => GameState::Lobby,
+                State::Finished | State::Cheated | State::Aborted => GameState::Done,
+            })
+            .max_by_key(|state| match state {
+                GameState::Waiting => 0,
+                GameState::Done => 1,
+                GameState::Lobby => 2,
+                GameState::Running => 3,
+            })
+            .unwrap_or(GameState::Waiting)
+    }
+}
+
 impl From<GameState> for i64 {
     fn from(value: GameState) -> Self {
         match value {
@@ -47,15 +78,29 @@ pub struct Metrics {
     pub game_state: IntGauge,
     pub transactions: IntCounter,
     pub bytes: IntCounter,
+    pub transactions_invalid: IntCounterVec,
+    pub tx_confirmation_seconds: Histogram,
+    pub snapshot_interval_seconds: Histogram,
+    pub snapshot_transactions: Histogram,
+    pub peers_connected: IntGauge,
+    pub websocket_reconnects: IntCounter,
 
     pub games_current: IntGauge,
+    pub games_open: IntGauge,
     pub games_seconds: Histogram,
     pub players_total: IntCounter,
     pub players_current: IntGauge,
+    pub bots_total: IntCounter,
+    pub bots_current: IntGauge,
     pub kills: IntCounter,
+    pub kills_by_weapon: IntCounterVec,
     pub suicides: IntCounter,
+    pub player_kills: IntGaugeVec,
+    pub player_deaths: IntGaugeVec,
 
-    game_timer: Mutex<Option<HistogramTimer>>,
+    game_timers: Mutex<HashMap<String, HistogramTimer>>,
+    last_snapshot: Mutex<Option<(u64, Option<DateTime<FixedOffset>>)>>,
+    peers: Mutex<HashSet<String>>,
 }
 
 impl Metrics {
@@ -84,9 +129,57 @@ impl Metrics {
         )
         .unwrap();
 
+        let transactions_invalid = IntCounterVec::new(
+            opts!(
+                "hydra_doom_node_transactions_invalid",
+                "Number of transactions rejected by the head, by reason."
+            ),
+            &["reason"],
+        )
+        .unwrap();
+
+        let tx_confirmation_seconds = Histogram::with_opts(histogram_opts!(
+            "hydra_doom_node_tx_confirmation_seconds",
+            "Time between submitting a transaction and the head confirming it, in seconds.",
+            exponential_buckets(0.01, 2.0, 12)?,
+        ))
+        .unwrap();
+
+        let snapshot_interval_seconds = Histogram::with_opts(histogram_opts!(
+            "hydra_doom_node_snapshot_interval_seconds",
+            "Time between consecutive confirmed snapshots, in seconds.",
+            exponential_buckets(0.05, 2.0, 12)?,
+        ))
+        .unwrap();
+
+        let snapshot_transactions = Histogram::with_opts(histogram_opts!(
+            "hydra_doom_node_snapshot_transactions",
+            "Number of transactions confirmed by each snapshot.",
+            exponential_buckets(1.0, 2.0, 10)?,
+        ))
+        .unwrap();
+
+        let peers_connected = IntGauge::new(
+            "hydra_doom_node_peers_connected",
+            "Number of peers the hydra node is connected to.",
+        )
+        .unwrap();
+
+        let websocket_reconnects = IntCounter::new(
+            "hydra_doom_node_websocket_reconnects",
+            "Number of times the exporter reconnected to the hydra node.",
+        )
+        .unwrap();
+
         let games_current = IntGauge::new(
             "hydra_doom_games_current",
-            "Number of games currently running.",
+            "Number of games currently running in the head.",
+        )
+        .unwrap();
+
+        let games_open = IntGauge::new(
+            "hydra_doom_games_open",
+            "Number of game UTxOs in the head that haven't finished yet.",
         )
         .unwrap();
 
@@ -109,22 +202,74 @@ impl Metrics {
         )
         .unwrap();
 
+        let bots_total = IntCounter::new(
+            "hydra_doom_bots_total",
+            "Total number of bots that have joined the game.",
+        )
+        .unwrap();
+
+        let bots_current = IntGauge::new(
+            "hydra_doom_bots_current",
+            "Number of bots currently in the game.",
+        )
+        .unwrap();
+
         let kills = IntCounter::new("hydra_doom_kills", "Number of kills in the game.").unwrap();
 
+        let kills_by_weapon = IntCounterVec::new(
+            opts!(
+                "hydra_doom_kills_by_weapon",
+                "Number of kills in the game, by weapon."
+            ),
+            &["weapon"],
+        )
+        .unwrap();
+
         let suicides =
             IntCounter::new("hydra_doom_suicides", "Number of suicides in the game.").unwrap();
 
+        // Only the players of games with a scoreboard are labelled, see `remove_scoreboard`
+        let player_kills = IntGaugeVec::new(
+            opts!(
+                "hydra_doom_player_kills",
+                "Kills of each player in the games on this node."
+            ),
+            &["game_id", "player"],
+        )
+        .unwrap();
+
+        let player_deaths = IntGaugeVec::new(
+            opts!(
+                "hydra_doom_player_deaths",
+                "Deaths of each player in the games on this node."
+            ),
+            &["game_id", "player"],
+        )
+        .unwrap();
+
         let registry = Registry::default();
         registry.register(Box::new(node_state.clone()))?;
         registry.register(Box::new(game_state.clone()))?;
         registry.register(Box::new(transactions.clone()))?;
         registry.register(Box::new(bytes.clone()))?;
+        registry.register(Box::new(transactions_invalid.clone()))?;
+        registry.register(Box::new(tx_confirmation_seconds.clone()))?;
+        registry.register(Box::new(snapshot_interval_seconds.clone()))?;
+        registry.register(Box::new(snapshot_transactions.clone()))?;
+        registry.register(Box::new(peers_connected.clone()))?;
+        registry.register(Box::new(websocket_reconnects.clone()))?;
         registry.register(Box::new(games_current.clone()))?;
+        registry.register(Box::new(games_open.clone()))?;
         registry.register(Box::new(games_seconds.clone()))?;
         registry.register(Box::new(players_total.clone()))?;
         registry.register(Box::new(players_current.clone()))?;
+        registry.register(Box::new(bots_total.clone()))?;
+        registry.register(Box::new(bots_current.clone()))?;
         registry.register(Box::new(kills.clone()))?;
+        registry.register(Box::new(kills_by_weapon.clone()))?;
         registry.register(Box::new(suicides.clone()))?;
+        registry.register(Box::new(player_kills.clone()))?;
+        registry.register(Box::new(player_deaths.clone()))?;
 
         Ok(Self {
             registry,
@@ -132,14 +277,28 @@ impl Metrics {
             game_state,
             transactions,
             bytes,
+            transactions_invalid,
+            tx_confirmation_seconds,
+            snapshot_interval_seconds,
+            snapshot_transactions,
+            peers_connected,
+            websocket_reconnects,
             games_current,
+            games_open,
             games_seconds,
             players_total,
             players_current,
+            bots_total,
+            bots_current,
             kills,
+            kills_by_weapon,
             suicides,
+            player_kills,
+            player_deaths,
 
-            game_timer: Mutex::new(None),
+            game_timers: Mutex::new(HashMap::new()),
+            last_snapshot: Mutex::new(None),
+            peers: Mutex::new(HashSet::new()),
         })
     }
 
@@ -147,47 +306,121 @@ impl Metrics {
         self.node_state.set(state.into())
     }
 
+    pub fn set_game_state(&self, state: GameState) {
+        self.game_state.set(state.into())
+    }
+
     pub fn new_transaction(&self, bytes: u64) {
         self.transactions.inc();
         self.bytes.inc_by(bytes);
     }
 
-    pub fn start_server(&self) {
-        self.game_state.set(GameState::Waiting.into());
-        self.players_current.set(0);
+    pub fn invalid_transaction(&self, reason: &str) {
+        self.transactions_invalid.with_label_values(&[reason]).inc();
     }
 
-    pub fn start_game(&self) {
-        self.games_current.set(1);
-        self.game_state.set(GameState::Running.into());
-        let mut guard = self.game_timer.lock().unwrap();
-        if let Some(prev) = guard.take() {
-            // The previous game didn't end properly, so we discard the duration so as not to pollute the timing
-            prev.stop_and_discard();
+    /// Observes the cadence and size of snapshots. Snapshots replayed after a reconnect are
+    /// skipped, and the interval is only measured between consecutive snapshots.
+    pub fn snapshot_confirmed(&self, number: u64, timestamp: &str, transactions: usize) {
+        let at = DateTime::parse_from_rfc3339(timestamp).ok();
+        let mut last_snapshot = self.last_snapshot.lock().unwrap();
+        if let Some((last_number, last_at)) = *last_snapshot {
+            if number <= last_number {
+                return;
+            }
+            let interval = last_at
+                .zip(at)
+                .and_then(|(last_at, at)| (at - last_at).to_std().ok())
+                .filter(|_| number == last_number + 1);
+            if let Some(interval) = interval {
+                self.snapshot_interval_seconds
+                    .observe(interval.as_secs_f64());
+            }
         }
-        *guard = Some(self.games_seconds.start_timer());
+
+        self.snapshot_transactions.observe(transactions as f64);
+        *last_snapshot = Some((number, at));
+    }
+
+    pub fn peer_connected(&self, peer: &str) {
+        let mut peers = self.peers.lock().unwrap();
+        peers.insert(peer.to_string());
+        self.peers_connected.set(peers.len() as i64);
+    }
+
+    pub fn peer_disconnected(&self, peer: &str) {
+        let mut peers = self.peers.lock().unwrap();
+        peers.remove(peer);
+        self.peers_connected.set(peers.len() as i64);
+    }
+
+    /// Catches the reconnect counter up with the total kept by the socket.
+    pub fn set_websocket_reconnects(&self, total: u64) {
+        self.websocket_reconnects
+            .inc_by(total.saturating_sub(self.websocket_reconnects.get()));
+    }
+
+    pub fn start_server(&self) {
+        self.players_current.set(0);
+        self.bots_current.set(0);
     }
 
     pub fn end_game(&self) {
         self.players_current.set(0);
-        self.games_current.set(0);
-        self.game_state.set(GameState::Done.into());
-        let mut guard = self.game_timer.lock().unwrap();
-        if let Some(timer) = guard.take() {
-            timer.observe_duration();
+        self.bots_current.set(0);
+    }
+
+    /// Times each game from when it's first seen running until it's over or its UTxO is
+    /// collected, observing one sample per game. A game seen back in its lobby keeps its timer,
+    /// since that comes from a snapshot taken before the game started.
+    pub fn time_games<'a>(&self, games: impl IntoIterator<Item = (&'a String, State)>) {
+        let mut timers = self.game_timers.lock().unwrap();
+        let mut playing = HashSet::new();
+        for (game_id, state) in games {
+            match state {
+                State::Running => {
+                    timers
+                        .entry(game_id.clone())
+                        .or_insert_with(|| self.games_seconds.start_timer());
+                    playing.insert(game_id);
+                }
+                State::Lobby if timers.contains_key(game_id) => {
+                    playing.insert(game_id);
+                }
+                _ => {}
+            }
+        }
+
+        let ended: Vec<String> = timers
+            .keys()
+            .filter(|game_id| !playing.contains(game_id))
+            .cloned()
+            .collect();
+        for game_id in ended {
+            if let Some(timer) = timers.remove(&game_id) {
+                timer.observe_duration();
+            }
         }
     }
 
     pub fn player_joined(&self) {
         self.players_total.inc();
         self.players_current.inc();
-        self.game_state.set(GameState::Lobby.into());
     }
 
     pub fn player_left(&self) {
         self.players_current.dec();
     }
 
+    pub fn bot_joined(&self) {
+        self.bots_total.inc();
+        self.bots_current.inc();
+    }
+
+    pub fn bot_left(&self) {
+        self.bots_current.dec();
+    }
+
     pub fn player_killed(&self) {
         self.kills.inc();
     }
@@ -196,6 +429,27 @@ impl Metrics {
         self.suicides.inc();
     }
 
+    pub fn kill_with(&self, weapon: &str) {
+        self.kills_by_weapon.with_label_values(&[weapon]).inc();
+    }
+
+    pub fn set_player_score(&self, game_id: &str, player: &str, score: &PlayerScore) {
+        self.player_kills
+            .with_label_values(&[game_id, player])
+            .set(score.kills as i64);
+        self.player_deaths
+            .with_label_values(&[game_id, player])
+            .set(score.deaths as i64);
+    }
+
+    pub fn remove_scoreboard(&self, scoreboard: &Scoreboard) {
+        for player in scoreboard.players.keys() {
+            let labels = [scoreboard.game_id.as_str(), player.as_str()];
+            let _ = self.player_kills.remove_label_values(&labels);
+            let _ = self.player_deaths.remove_label_values(&labels);
+        }
+    }
+
     pub fn gather(&self) -> String {
         // Encode the metrics in a format that Prometheus can read
         let encoder = TextEncoder::new();
@@ -208,3 +462,61 @@ impl Metrics {
         String::from_utf8(buffer).unwrap()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_snapshot_confirmed() {
+        let metrics = Metrics::try_new().expect("failed to register metrics");
+        metrics.snapshot_confirmed(1, "2024-10-08T13:05:50Z", 2);
+        metrics.snapshot_confirmed(2, "2024-10-08T13:05:50.5Z", 1);
+        // Replayed after a reconnect
+        metrics.snapshot_confirmed(1, "2024-10-08T13:05:50Z", 2);
+        // Missed snapshot 3, so there's no interval to measure
+        metrics.snapshot_confirmed(4, "2024-10-08T13:05:52Z", 3);
+
+        assert_eq!(metrics.snapshot_transactions.get_sample_count(), 3);
+        assert_eq!(metrics.snapshot_transactions.get_sample_sum(), 6.0);
+        assert_eq!(metrics.snapshot_interval_seconds.get_sample_count(), 1);
+        assert_eq!(metrics.snapshot_interval_seconds.get_sample_sum(), 0.5);
+    }
+
+    #[test]
+    fn test_peers_and_reconnects() {
+        let metrics = Metrics::try_new().expect("failed to register metrics");
+        metrics.peer_connected("alice");
+        metrics.peer_connected("bob");
+        // Replayed after a reconnect
+        metrics.peer_connected("alice");
+        metrics.peer_disconnected("bob");
+        assert_eq!(metrics.peers_connected.get(), 1);
+
+        metrics.set_websocket_reconnects(2);
+        metrics.set_websocket_reconnects(3);
+        assert_eq!(metrics.websocket_reconnects.get(), 3);
+    }
+
+    #[test]
+    fn test_time_games() {
+        let metrics = Metrics::try_new().expect("failed to register metrics");
+        let (game1, game2) = ("game1".to_string(), "game2".to_string());
+
+        metrics.time_games([(&game1, State::Running), (&game2, State::Lobby)]);
+        metrics.time_games([(&game1, State::Running), (&game2, State::Running)]);
+        assert_eq!(metrics.games_seconds.get_sample_count(), 0);
+
+        // A snapshot from before game2 started doesn't end it
+        metrics.time_games([(&game1, State::Finished), (&game2, State::Lobby)]);
+        assert_eq!(metrics.games_seconds.get_sample_count(), 1);
+
+        // Nor does seeing game1 again once it's over
+        metrics.time_games([(&game1, State::Finished), (&game2, State::Running)]);
+        assert_eq!(metrics.games_seconds.get_sample_count(), 1);
+
+        // game2's UTxO is collected
+        metrics.time_games([]);
+        assert_eq!(metrics.games_seconds.get_sample_count(), 2);
+    }
+}
@@ -0,0 +1,634 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use hydra_control_plane_rpc::model::{
+    cluster::{shared::NodeGame, ConnectionInfo, NodeClient},
+    game::contract::{
+        game_state::{GameState, State},
+        game_token::GameToken,
+        validator::Validator,
+    },
+    hydra::{messages::tx_valid::TxValid, utxo::UTxO},
+};
+use pallas::{crypto::key::ed25519::SecretKey, ledger::addresses::Network};
+use tracing::{error, info, warn};
+
+use crate::{
+    metrics::{self, Metrics},
+    results::GameResults,
+};
+
+struct Lobby {
+    opened_at: Instant,
+    players: u64,
+    player_count: u64,
+}
+
+struct TrackedGame {
+    state: State,
+    players: HashSet<String>,
+    player_count: u64,
+    bot_count: u64,
+}
+
+impl From<&GameState> for TrackedGame {
+    fn from(game_state: &GameState) -> Self {
+        Self {
+            state: game_state.state(),
+            players: game_state.players.iter().map(ToString::to_string).collect(),
+            player_count: game_state.player_count(),
+            bot_count: game_state.bot_count(),
+        }
+    }
+}
+
+/// The players who joined a game, as the hex encoded hashes of their payment keys, and how many
+/// bots play alongside them.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Roster {
+    pub players: HashSet<String>,
+    pub bots: usize,
+}
+
+/// Watches the game UTxOs through the head's snapshots and submits the StartGame transaction for
+/// each game once its lobby is full, or once the lobby timeout expires with at least one player in
+/// it. The states of the games also drive the node's game state metric.
+pub struct GameOrchestrator {
+    client: NodeClient,
+    network: Network,
+    metrics: Arc<Metrics>,
+    results: Arc<GameResults>,
+    lobby_timeout: Duration,
+    lobbies: Mutex<HashMap<String, Lobby>>,
+    starting: Mutex<HashSet<String>>,
+    game_states: Mutex<HashMap<String, TrackedGame>>,
+}
+
+impl GameOrchestrator {
+    pub fn new(
+        hydra: ConnectionInfo,
+        admin_key: SecretKey,
+        network: Network,
+        metrics: Arc<Metrics>,
+        results: Arc<GameResults>,
+        lobby_timeout: Duration,
+    ) -> Self {
+        Self {
+            client: NodeClient::new(hydra, admin_key, network)
+                .with_confirmation_latency(metrics.tx_confirmation_seconds.clone()),
+            network,
+            metrics,
+            results,
+            lobby_timeout,
+            lobbies: Mutex::new(HashMap::new()),
+            starting: Mutex::new(HashSet::new()),
+            game_states: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// The games held by the game validator among the given UTxOs.
+    fn games_in(&self, utxos: &[UTxO]) -> Vec<(String, GameState)> {
+        let script_address = Validator::address(self.network);
+        let admin_pkh = self.client.tx_builder.admin_pkh;
+        utxos
+            .iter()
+            .filter(|utxo| utxo.address == script_address)
+            .filter_map(|utxo| {
+                let token = GameToken::from_utxo(admin_pkh, utxo)?;
+                let game_state = GameState::try_from(utxo.datum.clone()).ok()?;
+                Some((token.game_id(), game_state))
+            })
+            .collect()
+    }
+
+    /// Updates the games created or moved by a transaction, ahead of the next snapshot.
+    pub fn observe_tx(&self, tx: &TxValid) {
+        let outputs = match tx.transaction.outputs() {
+            Ok(outputs) => outputs,
+            Err(err) => {
+                warn!(
+                    tx_id = tx.tx_id.as_str(),
+                    "failed to decode transaction: {:#}", err
+                );
+                return;
+            }
+        };
+
+        let mut game_states = self.game_states.lock().unwrap();
+        for (game_id, game_state) in self.games_in(&outputs) {
+            game_states.insert(game_id, (&game_state).into());
+        }
+        self.update_game_state(&game_states);
+    }
+
+    /// Records the state a game was moved to by one of the game routes.
+    pub fn set_game_state(&self, game_id: &str, state: State) {
+        let mut game_states = self.game_states.lock().unwrap();
+        game_states
+            .entry(game_id.to_string())
+            .and_modify(|game| game.state = state)
+            .or_insert(TrackedGame {
+                state,
+                players: HashSet::new(),
+                player_count: 0,
+                bot_count: 0,
+            });
+        self.update_game_state(&game_states);
+    }
+
+    /// Forgets a game whose UTxO was collected.
+    pub fn remove_game(&self, game_id: &str) {
+        let mut game_states = self.game_states.lock().unwrap();
+        game_states.remove(game_id);
+        self.update_game_state(&game_states);
+    }
+
+    /// The games in the head, until their UTxO is collected.
+    pub fn games(&self) -> Vec<NodeGame> {
+        let game_states = self.game_states.lock().unwrap();
+        let lobbies = self.lobbies.lock().unwrap();
+        game_states
+            .iter()
+            .map(|(game_id, game)| NodeGame {
+                game_id: game_id.clone(),
+                state: format!("{:?}", game.state),
+                players: game.players.len() as u64,
+                player_count: game.player_count,
+                bot_count: game.bot_count,
+                starts_in: lobbies.get(game_id).map(|lobby| {
+                    self.lobby_timeout
+                        .saturating_sub(lobby.opened_at.elapsed())
+                        .as_secs()
+                }),
+            })
+            .collect()
+    }
+
+    /// Who may play a game that is still being played, or `None` when there is no such game.
+    pub fn roster(&self, game_id: &str) -> Option<Roster> {
+        self.game_states
+            .lock()
+            .unwrap()
+            .get(game_id)
+            .filter(|game| !game.state.is_terminal())
+            .map(|game| Roster {
+                players: game.players.clone(),
+                bots: game.bot_count as usize,
+            })
+    }
+
+    fn update_game_state(&self, game_states: &HashMap<String, TrackedGame>) {
+        self.metrics.set_game_state(metrics::GameState::of_games(
+            game_states.values().map(|game| game.state),
+        ));
+        self.metrics.time_games(
+            game_states
+                .iter()
+                .map(|(game_id, game)| (game_id, game.state)),
+        );
+        self.metrics.games_current.set(
+            game_states
+                .values()
+                .filter(|game| game.state == State::Running)
+                .count() as i64,
+        );
+    }
+
+    /// Updates the tracked lobbies from the UTxO set of a confirmed snapshot.
+    pub fn observe(self: &Arc<Self>, utxos: &[UTxO]) {
+        let games = self.games_in(utxos);
+
+        {
+            let mut game_states = self.game_states.lock().unwrap();
+            *game_states = games
+                .iter()
+                .map(|(game_id, game_state)| (game_id.clone(), game_state.into()))
+                .collect();
+            self.update_game_state(&game_states);
+        }
+
+        self.metrics.games_open.set(
+            games
+                .iter()
+                .filter(|(_, game_state)| !game_state.state().is_terminal())
+                .count() as i64,
+        );
+        for (game_id, game_state) in &games {
+            if game_state.state().is_terminal() {
+                self.results.record(game_id, game_state);
+            }
+        }
+
+        let full: Vec<String> = {
+            let mut lobbies = self.lobbies.lock().unwrap();
+            lobbies.retain(|game_id, _| {
+                games
+                    .iter()
+                    .any(|(id, game_state)| id == game_id && game_state.state() == State::Lobby)
+            });
+
+            games
+                .into_iter()
+                .filter(|(_, game_state)| game_state.state() == State::Lobby)
+                .filter_map(|(game_id, game_state)| {
+                    let players = game_state.players.len() as u64;
+                    lobbies
+                        .entry(game_id.clone())
+                        .and_modify(|lobby| lobby.players = players)
+                        .or_insert_with(|| {
+                            info!(
+                                game_id,
+                                players,
+                                player_count = game_state.player_count(),
+                                "lobby opened"
+                            );
+                            Lobby {
+                                opened_at: Instant::now(),
+                                players,
+                                player_count: game_state.player_count(),
+                            }
+                        });
+                    game_state.is_full().then_some(game_id)
+                })
+                .collect()
+        };
+
+        for game_id in full {
+            info!(game_id, "lobby is full, starting game");
+            self.start(game_id);
+        }
+    }
+
+    /// Starts every game whose lobby has been open for longer than the lobby timeout. A full lobby
+    /// whose start transaction failed is retried here as well.
+    pub fn tick(self: &Arc<Self>) {
+        for game_id in self.ready_lobbies(Instant::now()) {
+            info!(game_id, "lobby is ready, starting game");
+            self.start(game_id);
+        }
+    }
+
+    /// The lobbies that are full, or that have at least one player and were opened more than the
+    /// lobby timeout before `now`.
+    fn ready_lobbies(&self, now: Instant) -> Vec<String> {
+        self.lobbies
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(_, lobby)| {
+                lobby.players > 0
+                    && (lobby.players >= lobby.player_count
+                        || now.saturating_duration_since(lobby.opened_at) >= self.lobby_timeout)
+            })
+            .map(|(game_id, _)| game_id.clone())
+            .collect()
+    }
+
+    fn start(self: &Arc<Self>, game_id: String) {
+        if !self.starting.lock().unwrap().insert(game_id.clone()) {
+            return;
+        }
+
+        let orchestrator = self.clone();
+        tokio::spawn(async move {
+            match orchestrator.client.start_game(&game_id).await {
+                Ok(tx_hash) => {
+                    info!(game_id, tx_hash = hex::encode(tx_hash), "game started");
+                    orchestrator.set_game_state(&game_id, State::Running);
+                    orchestrator.lobbies.lock().unwrap().remove(&game_id);
+                }
+                Err(err) => {
+                    error!(game_id, "failed to start game: {}", err);
+                }
+            }
+            orchestrator.starting.lock().unwrap().remove(&game_id);
+        });
+    }
+}
+
+pub async fn run_lobby_timer(orchestrator: Arc<GameOrchestrator>) {
+    loop {
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        orchestrator.tick();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use hydra_control_plane_rpc::model::{
+        game::{contract::game_state::PaymentCredential, player::Player},
+        hydra::{messages::Transaction, utxo::Datum},
+    };
+    use pallas::{crypto::hash::Hash, ledger::addresses::Address};
+    use tokio::{io::AsyncReadExt, net::TcpListener, time::timeout};
+
+    use super::*;
+    use crate::scoreboards::Scoreboards;
+
+    fn credential(byte: u8) -> PaymentCredential {
+        Hash::<28>::from([byte; 28]).into()
+    }
+
+    fn orchestrator() -> Arc<GameOrchestrator> {
+        orchestrator_on(4001)
+    }
+
+    fn orchestrator_on(port: u32) -> Arc<GameOrchestrator> {
+        Arc::new(GameOrchestrator::new(
+            ConnectionInfo {
+                host: "127.0.0.1".to_string(),
+                port,
+                secure: false,
+            },
+            SecretKey::from([7; 32]),
+            Network::Testnet,
+            Arc::new(Metrics::try_new().expect("failed to register metrics")),
+            Arc::new(GameResults::new(Arc::new(Scoreboards::new(Arc::new(
+                Metrics::try_new().expect("failed to register metrics"),
+            ))))),
+            Duration::from_secs(60),
+        ))
+    }
+
+    fn game_utxo(orchestrator: &GameOrchestrator, seed: u64, game_state: GameState) -> UTxO {
+        let admin_pkh = orchestrator.client.tx_builder.admin_pkh;
+        let seed = UTxO {
+            hash: vec![0; 32],
+            index: seed,
+            address: Validator::address(orchestrator.network),
+            datum: Datum::None,
+            reference_script: None,
+            value: HashMap::new(),
+        };
+        let token = GameToken::from_seed(admin_pkh, &seed);
+
+        UTxO {
+            index: 0,
+            datum: Datum::Inline(game_state.into()),
+            value: HashMap::from([("lovelace".to_string(), 0), (token.unit(), 1)]),
+            ..seed
+        }
+    }
+
+    fn open_lobby(orchestrator: &GameOrchestrator, game_id: &str, players: u64) {
+        orchestrator.lobbies.lock().unwrap().insert(
+            game_id.to_string(),
+            Lobby {
+                opened_at: Instant::now(),
+                players,
+                player_count: 2,
+            },
+        );
+    }
+
+    fn node_game_state(orchestrator: &GameOrchestrator) -> i64 {
+        orchestrator.metrics.game_state.get()
+    }
+
+    fn game(state: State) -> GameState {
+        let game = GameState::new(credential(0), 2, 0)
+            .add_player(credential(1))
+            .expect("failed to add player");
+        match state {
+            State::Lobby => Ok(game),
+            State::Running => game.start(),
+            State::Finished => game.start().and_then(|game| game.finish(credential(1))),
+            State::Aborted => game.abort(),
+            State::Cheated => game.start().and_then(|game| game.cheated(credential(1))),
+        }
+        .expect("invalid transition")
+    }
+
+    #[test]
+    fn test_game_state_from_snapshots() {
+        let orchestrator = orchestrator();
+        let (game_id, _) = orchestrator
+            .games_in(&[game_utxo(&orchestrator, 0, game(State::Lobby))])
+            .remove(0);
+
+        orchestrator.observe(&[]);
+        assert_eq!(
+            node_game_state(&orchestrator),
+            metrics::GameState::Waiting.into()
+        );
+
+        orchestrator.observe(&[game_utxo(&orchestrator, 0, game(State::Lobby))]);
+        assert_eq!(
+            node_game_state(&orchestrator),
+            metrics::GameState::Lobby.into()
+        );
+
+        orchestrator.observe(&[game_utxo(&orchestrator, 0, game(State::Running))]);
+        assert_eq!(
+            node_game_state(&orchestrator),
+            metrics::GameState::Running.into()
+        );
+
+        orchestrator.observe(&[game_utxo(&orchestrator, 0, game(State::Finished))]);
+        assert_eq!(
+            node_game_state(&orchestrator),
+            metrics::GameState::Done.into()
+        );
+        assert_eq!(orchestrator.results.recent()[0].game_id, game_id);
+
+        // The game UTxO is gone once it's collected
+        orchestrator.observe(&[]);
+        assert_eq!(
+            node_game_state(&orchestrator),
+            metrics::GameState::Waiting.into()
+        );
+    }
+
+    #[test]
+    fn test_game_state_from_routes() {
+        let orchestrator = orchestrator();
+
+        orchestrator.set_game_state("game1", State::Lobby);
+        assert_eq!(
+            node_game_state(&orchestrator),
+            metrics::GameState::Lobby.into()
+        );
+        orchestrator.set_game_state("game1", State::Running);
+        assert_eq!(
+            node_game_state(&orchestrator),
+            metrics::GameState::Running.into()
+        );
+
+        // Another game's lobby doesn't hide the running game
+        orchestrator.set_game_state("game2", State::Lobby);
+        assert_eq!(
+            node_game_state(&orchestrator),
+            metrics::GameState::Running.into()
+        );
+
+        orchestrator.set_game_state("game1", State::Aborted);
+        orchestrator.remove_game("game2");
+        assert_eq!(
+            node_game_state(&orchestrator),
+            metrics::GameState::Done.into()
+        );
+
+        orchestrator.remove_game("game1");
+        assert_eq!(
+            node_game_state(&orchestrator),
+            metrics::GameState::Waiting.into()
+        );
+    }
+
+    #[test]
+    fn test_cheated_game() {
+        let orchestrator = orchestrator();
+        let (game_id, _) = orchestrator
+            .games_in(&[game_utxo(&orchestrator, 0, game(State::Running))])
+            .remove(0);
+        orchestrator.observe(&[game_utxo(&orchestrator, 0, game(State::Running))]);
+        assert_eq!(
+            orchestrator.roster(&game_id),
+            Some(Roster {
+                players: HashSet::from([credential(1).to_string()]),
+                bots: 0,
+            })
+        );
+
+        // What report_cheater does once the verdict is submitted
+        orchestrator.set_game_state(&game_id, State::Cheated);
+        assert_eq!(
+            node_game_state(&orchestrator),
+            metrics::GameState::Done.into()
+        );
+        assert_eq!(orchestrator.metrics.games_current.get(), 0);
+        assert_eq!(orchestrator.roster(&game_id), None);
+
+        // The verdict shows up in the next snapshot
+        orchestrator.observe(&[game_utxo(&orchestrator, 0, game(State::Cheated))]);
+        let results = orchestrator.results.recent();
+        assert_eq!(results[0].game_id, game_id);
+        assert_eq!(results[0].outcome, "Cheated");
+        assert_eq!(results[0].winner, None);
+    }
+
+    #[test]
+    fn test_game_state_from_transactions() {
+        let orchestrator = orchestrator();
+        let tx_builder = &orchestrator.client.tx_builder;
+        let mut admin_address = tx_builder.admin_pkh.to_vec();
+        admin_address.insert(0, 0b0110_0000);
+        let admin_utxo = UTxO {
+            hash: vec![0; 32],
+            index: 0,
+            address: Address::from_bytes(&admin_address).expect("valid address"),
+            datum: Datum::None,
+            reference_script: None,
+            value: HashMap::from([("lovelace".to_string(), 0)]),
+        };
+        let (game_id, tx) = tx_builder
+            .new_game(
+                Player::from(Hash::<28>::from([1; 28])),
+                vec![admin_utxo],
+                2,
+                0,
+            )
+            .expect("failed to build tx");
+
+        orchestrator.observe_tx(&TxValid {
+            head_id: "head".to_string(),
+            seq: 1,
+            transaction: Transaction {
+                cbor: tx.tx_bytes.as_ref().to_vec(),
+                description: String::new(),
+                tx_id: hex::encode([1; 32]),
+                tx_type: "Tx ConwayEra".to_string(),
+            },
+            timestamp: String::new(),
+            tx_id: hex::encode([1; 32]),
+        });
+
+        // Tracked before the transaction makes it into a snapshot
+        assert_eq!(
+            node_game_state(&orchestrator),
+            metrics::GameState::Lobby.into()
+        );
+        let games = orchestrator.games();
+        assert_eq!(games.len(), 1);
+        assert_eq!(games[0].game_id, game_id);
+        assert_eq!(games[0].state, "Lobby");
+        assert_eq!(games[0].players, 1);
+        assert_eq!(games[0].player_count, 2);
+    }
+
+    #[test]
+    fn test_ready_lobbies_full() {
+        let orchestrator = orchestrator();
+        open_lobby(&orchestrator, "game1", 2);
+
+        assert_eq!(orchestrator.ready_lobbies(Instant::now()), vec!["game1"]);
+    }
+
+    #[test]
+    fn test_ready_lobbies_timed_out() {
+        let orchestrator = orchestrator();
+        open_lobby(&orchestrator, "game1", 1);
+
+        let now = Instant::now();
+        assert!(orchestrator.ready_lobbies(now).is_empty());
+        assert_eq!(
+            orchestrator.ready_lobbies(now + orchestrator.lobby_timeout),
+            vec!["game1"]
+        );
+    }
+
+    #[test]
+    fn test_ready_lobbies_empty() {
+        let orchestrator = orchestrator();
+        open_lobby(&orchestrator, "game1", 0);
+
+        let now = Instant::now();
+        assert!(orchestrator.ready_lobbies(now).is_empty());
+        assert!(orchestrator
+            .ready_lobbies(now + orchestrator.lobby_timeout)
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_tick_starts_lobby_once() {
+        let node = TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("failed to bind");
+        let orchestrator = orchestrator_on(node.local_addr().unwrap().port() as u32);
+        open_lobby(&orchestrator, "game1", 2);
+
+        orchestrator.tick();
+        let (mut request, _) = timeout(Duration::from_secs(5), node.accept())
+            .await
+            .expect("game wasn't started")
+            .expect("failed to accept");
+
+        // The start is still in flight, so these don't start the game again
+        orchestrator.tick();
+        orchestrator.tick();
+        assert!(timeout(Duration::from_millis(200), node.accept())
+            .await
+            .is_err());
+
+        // Failing the UTxO request fails the start, which the next tick retries
+        let _ = request.read(&mut [0; 1024]).await;
+        drop(request);
+        timeout(Duration::from_secs(5), async {
+            while orchestrator.starting.lock().unwrap().contains("game1") {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("start didn't fail");
+        orchestrator.tick();
+        timeout(Duration::from_secs(5), node.accept())
+            .await
+            .expect("game wasn't retried")
+            .expect("failed to accept");
+    }
+}
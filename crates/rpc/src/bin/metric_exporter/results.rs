@@ -0,0 +1,132 @@
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use hydra_control_plane_rpc::model::{
+    cluster::shared::GameResult, game::contract::game_state::GameState,
+};
+
+use crate::scoreboards::Scoreboards;
+
+// The control plane collects results every few seconds, so they don't need to be kept for long.
+const RESULT_RETENTION: Duration = Duration::from_secs(60 * 60);
+
+/// The outcomes of the games that finished on this node recently.
+pub struct GameResults {
+    scoreboards: Arc<Scoreboards>,
+    results: Mutex<VecDeque<GameResult>>,
+}
+
+impl GameResults {
+    pub fn new(scoreboards: Arc<Scoreboards>) -> Self {
+        Self {
+            scoreboards,
+            results: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    /// Records the outcome of a game that reached a terminal state, once, along with the kills
+    /// on its scoreboard.
+    pub fn record(&self, game_id: &str, game_state: &GameState) {
+        let mut results = self.results.lock().unwrap();
+        if results.iter().any(|result| result.game_id == game_id) {
+            return;
+        }
+
+        results.push_back(GameResult {
+            game_id: game_id.to_string(),
+            outcome: format!("{:?}", game_state.state()),
+            players: game_state
+                .players
+                .iter()
+                .map(|player| player.to_string())
+                .collect(),
+            winner: game_state.winner().map(|winner| winner.to_string()),
+            kills: self
+                .scoreboards
+                .finish(game_id)
+                .map(|scoreboard| {
+                    scoreboard
+                        .kills()
+                        .map(|(player, kills)| (player.clone(), kills))
+                        .collect()
+                })
+                .unwrap_or_default(),
+            finished_at: unix_now(),
+        });
+    }
+
+    pub fn recent(&self) -> Vec<GameResult> {
+        let mut results = self.results.lock().unwrap();
+        let cutoff = unix_now().saturating_sub(RESULT_RETENTION.as_secs());
+        results.retain(|result| result.finished_at >= cutoff);
+
+        results.iter().cloned().collect()
+    }
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+#[cfg(test)]
+mod tests {
+    use hydra_control_plane_rpc::model::game::{
+        contract::game_state::PaymentCredential,
+        scoreboard::{GameEvent, GameEventKind},
+    };
+    use pallas::crypto::hash::Hash;
+
+    use std::collections::HashSet;
+
+    use super::*;
+    use crate::{metrics::Metrics, orchestrator::Roster};
+
+    fn credential(byte: u8) -> PaymentCredential {
+        Hash::<28>::from([byte; 28]).into()
+    }
+
+    #[test]
+    fn test_result_kills() {
+        let scoreboards = Arc::new(Scoreboards::new(Arc::new(
+            Metrics::try_new().expect("failed to register metrics"),
+        )));
+        let results = GameResults::new(scoreboards.clone());
+        let (winner, loser) = (credential(1), credential(2));
+        scoreboards.apply(
+            GameEventKind::Killed,
+            &GameEvent {
+                game_id: "game1".to_string(),
+                killer: Some(winner.to_string()),
+                victim: Some(loser.to_string()),
+                ..Default::default()
+            },
+            &Roster {
+                players: HashSet::from([winner.to_string(), loser.to_string()]),
+                bots: 0,
+            },
+        );
+
+        let game_state = GameState::new(credential(0), 2, 0)
+            .add_player(winner.clone())
+            .and_then(|game| game.add_player(loser.clone()))
+            .and_then(|game| game.start())
+            .and_then(|game| game.finish(winner.clone()))
+            .expect("valid game");
+        results.record("game1", &game_state);
+        // Recorded only once, the scoreboard is gone by now anyway
+        results.record("game1", &game_state);
+
+        let recent = results.recent();
+        assert_eq!(recent.len(), 1);
+        assert_eq!(recent[0].winner, Some(winner.to_string()));
+        assert_eq!(recent[0].kills.get(&winner.to_string()), Some(&1));
+        assert_eq!(recent[0].kills.get(&loser.to_string()), Some(&0));
+        assert!(scoreboards.get("game1").is_none());
+    }
+}
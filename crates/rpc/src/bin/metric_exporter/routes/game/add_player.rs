@@ -1,29 +1,37 @@
-use hydra_control_plane_rpc::model::cluster::{shared::AddPlayerLocalResponse, NodeClient};
+use hydra_control_plane_rpc::model::{
+    api_error::{ApiError, ApiResult},
+    cluster::shared::AddPlayerLocalResponse,
+    telemetry::TraceParent,
+};
 use pallas::ledger::addresses::Address;
-use rocket::{get, http::Status, serde::json::Json, State};
-use tracing::error;
+use rocket::{get, serde::json::Json, State};
+use tracing::{error, instrument};
 
 use crate::LocalState;
 
-#[get("/game/add_player?<address>")]
+#[get("/game/add_player?<game_id>&<address>")]
+#[instrument(skip_all, fields(game_id = game_id))]
 pub async fn add_player(
+    game_id: &str,
     address: &str,
+    parent: TraceParent,
     state: &State<LocalState>,
-) -> Result<Json<AddPlayerLocalResponse>, Status> {
-    let pkh = match Address::from_bech32(address).map_err(|_| Status::BadRequest)? {
+) -> ApiResult<Json<AddPlayerLocalResponse>> {
+    parent.adopt();
+    let pkh = match Address::from_bech32(address).map_err(|_| ApiError::invalid_address())? {
         Address::Shelley(shelley) => Ok(*shelley.payment().as_hash()),
-        _ => Err(Status::BadRequest),
+        _ => Err(ApiError::invalid_address()),
     }?;
 
-    let client = NodeClient::new(state.hydra.clone(), state.admin_key.clone(), state.network);
+    let client = state.node_client().with_stake(state.stake);
 
     let tx_hash = client
-        .add_player(pkh.into())
+        .add_player(game_id, pkh.into())
         .await
-        .inspect_err(|err| error!("error adding player: {}", err))
-        .map_err(|_| Status::InternalServerError)?;
+        .inspect_err(|err| error!("error adding player: {}", err))?;
 
     Ok(Json(AddPlayerLocalResponse {
+        game_id: game_id.to_string(),
         player_state: format!("{}#1", hex::encode(tx_hash)),
         admin_pkh: hex::encode(client.tx_builder.admin_pkh),
     }))
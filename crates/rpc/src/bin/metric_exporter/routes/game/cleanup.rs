@@ -1,18 +1,25 @@
-use hydra_control_plane_rpc::model::cluster::NodeClient;
-use rocket::{http::Status, post, State};
-use tracing::error;
+use hydra_control_plane_rpc::model::{api_error::ApiResult, telemetry::TraceParent};
+use rocket::{post, State};
+use tracing::{error, instrument};
 
 use crate::LocalState;
 
-#[post("/game/cleanup")]
-pub async fn cleanup(state: &State<LocalState>) -> Result<(), Status> {
-    let client = NodeClient::new(state.hydra.clone(), state.admin_key.clone(), state.network);
+#[post("/game/cleanup?<game_id>")]
+#[instrument(skip_all, fields(game_id = game_id))]
+pub async fn cleanup(
+    game_id: &str,
+    parent: TraceParent,
+    state: &State<LocalState>,
+) -> ApiResult<()> {
+    parent.adopt();
+    let client = state.node_client();
 
     client
-        .cleanup_game()
+        .cleanup_game(game_id)
         .await
-        .inspect_err(|err| error!("failed to cleanup game: {}", err))
-        .map_err(|_| Status::InternalServerError)?;
+        .inspect_err(|err| error!("failed to cleanup game: {}", err))?;
+
+    state.orchestrator.remove_game(game_id);
 
     Ok(())
 }
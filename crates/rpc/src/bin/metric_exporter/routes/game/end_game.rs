@@ -1,19 +1,30 @@
-use hydra_control_plane_rpc::model::cluster::NodeClient;
-use rocket::{http::Status, post, State};
-use tracing::error;
+use hydra_control_plane_rpc::model::{
+    api_error::ApiResult, game::contract::game_state::State as GameState, telemetry::TraceParent,
+};
+use rocket::{post, State};
+use tracing::{error, instrument};
 
 use crate::LocalState;
 
-#[post("/game/end_game")]
-pub async fn end_game(state: &State<LocalState>) -> Result<(), Status> {
-    let client = NodeClient::new(state.hydra.clone(), state.admin_key.clone(), state.network);
+#[post("/game/end_game?<game_id>")]
+#[instrument(skip_all, fields(game_id = game_id))]
+pub async fn end_game(
+    game_id: &str,
+    parent: TraceParent,
+    state: &State<LocalState>,
+) -> ApiResult<()> {
+    parent.adopt();
+    let client = state.node_client();
 
     // TODO: we need to take in the "end state" of the game. Currently, we are always aborting
     client
-        .end_game()
+        .end_game(game_id, None)
         .await
-        .inspect_err(|err| error!("failed to end game: {}", err))
-        .map_err(|_| Status::InternalServerError)?;
+        .inspect_err(|err| error!("failed to end game: {}", err))?;
+
+    state
+        .orchestrator
+        .set_game_state(game_id, GameState::Aborted);
 
     Ok(())
 }
@@ -0,0 +1,10 @@
+use hydra_control_plane_rpc::model::cluster::shared::NodeGame;
+use rocket::{get, serde::json::Json, State};
+
+use crate::LocalState;
+
+/// The games in the head of this node, whether they are still open or waiting to be collected.
+#[get("/game/games")]
+pub async fn games(state: &State<LocalState>) -> Json<Vec<NodeGame>> {
+    Json(state.orchestrator.games())
+}
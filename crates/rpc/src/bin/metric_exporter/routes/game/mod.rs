@@ -1,5 +1,9 @@
 pub mod add_player;
 pub mod cleanup;
 pub mod end_game;
+pub mod games;
 pub mod new_game;
+pub mod report_cheater;
+pub mod results;
+pub mod scoreboard;
 pub mod start_game;
@@ -1,34 +1,41 @@
-use anyhow::{anyhow, Context};
-use hydra_control_plane_rpc::model::cluster::{shared::NewGameLocalResponse, NodeClient};
+use anyhow::Context;
+use hydra_control_plane_rpc::model::{
+    api_error::{ApiError, ApiResult},
+    cluster::shared::NewGameLocalResponse,
+    telemetry::TraceParent,
+};
 use pallas::ledger::addresses::Address;
 use rocket::{get, serde::json::Json, State};
-use rocket_errors::anyhow::Result;
-use tracing::info;
+use tracing::{info, instrument};
 
 use crate::LocalState;
 
 #[get("/game/new_game?<address>&<player_count>&<bot_count>")]
+#[instrument(skip_all, fields(address = address))]
 pub async fn new_game(
     address: &str,
     player_count: u64,
     bot_count: u64,
+    parent: TraceParent,
     state: &State<LocalState>,
-) -> Result<Json<NewGameLocalResponse>> {
+) -> ApiResult<Json<NewGameLocalResponse>> {
+    parent.adopt();
     info!("Creating a new game for {}", address);
 
-    let pkh = match Address::from_bech32(address).context("invalid address")? {
+    let pkh = match Address::from_bech32(address).map_err(|_| ApiError::invalid_address())? {
         Address::Shelley(shelley) => *shelley.payment().as_hash(),
-        _ => return Result::Err(anyhow!("unsupported address type").into()),
+        _ => return Err(ApiError::invalid_address()),
     };
 
-    let client = NodeClient::new(state.hydra.clone(), state.admin_key.clone(), state.network);
+    let client = state.node_client().with_stake(state.stake);
 
-    let tx_hash = client
+    let (game_id, tx_hash) = client
         .new_game(pkh.into(), player_count, bot_count)
         .await
         .context("error creating new game")?;
 
     Ok(Json(NewGameLocalResponse {
+        game_id,
         player_state: format!("{}#1", hex::encode(tx_hash)),
         admin_pkh: hex::encode(client.tx_builder.admin_pkh),
     }))
@@ -0,0 +1,94 @@
+use anyhow::Context;
+use hydra_control_plane_rpc::model::{
+    api_error::{ApiError, ApiResult, ErrorCode},
+    game::{contract::game_state::State as GameState, referee::CheatReport},
+    telemetry::TraceParent,
+};
+use rocket::{post, serde::json::Json, State};
+use serde::Serialize;
+use tokio::io::AsyncWriteExt;
+use tracing::{error, info, instrument, warn};
+
+use crate::LocalState;
+
+#[derive(Serialize)]
+pub struct ReportCheaterResponse {
+    tx_hash: String,
+}
+
+#[post("/game/report_cheater", data = "<report>")]
+#[instrument(skip_all, fields(game_id = report.game_id.as_str()))]
+pub async fn report_cheater(
+    report: Json<CheatReport>,
+    parent: TraceParent,
+    state: &State<LocalState>,
+) -> ApiResult<Json<ReportCheaterResponse>> {
+    parent.adopt();
+    let referee_key = state
+        .referee_key
+        .as_ref()
+        .ok_or_else(|| ApiError::new(ErrorCode::Unavailable, "no referee key is configured"))?;
+
+    let player = report
+        .verify(referee_key)
+        .inspect_err(|err| warn!("rejected cheat report: {}", err))
+        .map_err(|err| ApiError::new(ErrorCode::Unauthorized, err.to_string()))?;
+
+    // The evidence is stored before the verdict is submitted, so that every cheated game in the
+    // head can be traced back to its report.
+    let evidence = serde_json::to_vec_pretty(&report.0).context("failed to encode evidence")?;
+    let file_name = format!(
+        "{}-{}-{}.json",
+        report.game_id,
+        hex::encode(player.signing_key),
+        report.evidence_hash
+    );
+    store_evidence(state, &file_name, evidence)
+        .await
+        .inspect_err(|err| error!("failed to store cheat evidence: {}", err))
+        .context("failed to store cheat evidence")?;
+
+    let client = state.node_client();
+    let tx_hash = client
+        .end_game(&report.game_id, Some((player, true)))
+        .await
+        .inspect_err(|err| error!("failed to submit cheat verdict: {}", err))?;
+
+    info!(
+        game_id = report.game_id.as_str(),
+        player = report.player.as_str(),
+        evidence_hash = report.evidence_hash.as_str(),
+        "cheat verdict submitted"
+    );
+    state
+        .orchestrator
+        .set_game_state(&report.game_id, GameState::Cheated);
+
+    Ok(Json(ReportCheaterResponse {
+        tx_hash: hex::encode(tx_hash),
+    }))
+}
+
+/// Writes the evidence to its own file, named after the game, the player and the evidence hash,
+/// which the report has already checked to be hex. Earlier reports are never overwritten, and a
+/// report that is sent again, e.g. after its verdict failed to submit, finds its evidence stored.
+async fn store_evidence(
+    state: &LocalState,
+    file_name: &str,
+    evidence: Vec<u8>,
+) -> std::io::Result<()> {
+    tokio::fs::create_dir_all(&state.evidence_dir).await?;
+    let file = tokio::fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(state.evidence_dir.join(file_name))
+        .await;
+    match file {
+        Ok(mut file) => {
+            file.write_all(&evidence).await?;
+            file.sync_all().await
+        }
+        Err(err) if err.kind() == std::io::ErrorKind::AlreadyExists => Ok(()),
+        Err(err) => Err(err),
+    }
+}
@@ -0,0 +1,10 @@
+use hydra_control_plane_rpc::model::cluster::shared::GameResult;
+use rocket::{get, serde::json::Json, State};
+
+use crate::LocalState;
+
+/// The games that finished on this node in the last hour.
+#[get("/game/results")]
+pub async fn game_results(state: &State<LocalState>) -> Json<Vec<GameResult>> {
+    Json(state.results.recent())
+}
@@ -0,0 +1,34 @@
+use hydra_control_plane_rpc::model::{
+    api_error::{ApiError, ApiResult, ErrorCode},
+    game::scoreboard::Scoreboard,
+};
+use rocket::{get, serde::json::Json, State};
+use serde::Serialize;
+
+use crate::LocalState;
+
+#[derive(Serialize)]
+pub struct ScoreboardResponse {
+    #[serde(flatten)]
+    scoreboard: Scoreboard,
+    /// The player currently winning the game, if any.
+    leader: Option<String>,
+}
+
+#[get("/game/scoreboard?<game_id>")]
+pub async fn scoreboard(
+    game_id: &str,
+    state: &State<LocalState>,
+) -> ApiResult<Json<ScoreboardResponse>> {
+    let scoreboard = state.scoreboards.get(game_id).ok_or_else(|| {
+        ApiError::new(
+            ErrorCode::NotFound,
+            format!("no scoreboard for game {}", game_id),
+        )
+    })?;
+
+    Ok(Json(ScoreboardResponse {
+        leader: scoreboard.leader().map(str::to_string),
+        scoreboard,
+    }))
+}
@@ -1,19 +1,29 @@
-use hydra_control_plane_rpc::model::cluster::NodeClient;
-use rocket::{http::Status, post, State};
-use rocket_errors::anyhow::Result;
-use tracing::error;
+use hydra_control_plane_rpc::model::{
+    api_error::ApiResult, game::contract::game_state::State as GameState, telemetry::TraceParent,
+};
+use rocket::{post, State};
+use tracing::{error, instrument};
 
 use crate::LocalState;
 
-#[post("/game/start_game")]
-pub async fn start_game(state: &State<LocalState>) -> Result<(), Status> {
-    let client = NodeClient::new(state.hydra.clone(), state.admin_key.clone(), state.network);
+#[post("/game/start_game?<game_id>")]
+#[instrument(skip_all, fields(game_id = game_id))]
+pub async fn start_game(
+    game_id: &str,
+    parent: TraceParent,
+    state: &State<LocalState>,
+) -> ApiResult<()> {
+    parent.adopt();
+    let client = state.node_client();
 
     client
-        .start_game()
+        .start_game(game_id)
         .await
-        .inspect_err(|err| error!("failed to submit start game tx: {}", err))
-        .map_err(|_| Status::InternalServerError)?;
+        .inspect_err(|err| error!("failed to submit start game tx: {}", err))?;
+
+    state
+        .orchestrator
+        .set_game_state(game_id, GameState::Running);
 
     Ok(())
 }
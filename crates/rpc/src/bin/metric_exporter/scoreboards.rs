@@ -0,0 +1,169 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Arc, Mutex},
+    time::Instant,
+};
+
+use hydra_control_plane_rpc::model::game::scoreboard::{GameEvent, GameEventKind, Scoreboard};
+use tracing::warn;
+
+use crate::{metrics::Metrics, orchestrator::Roster};
+
+// Keeps the per player metrics bounded, even when games never report their end.
+const MAX_SCOREBOARDS: usize = 32;
+
+/// The scoreboards of the games on this node, mirrored in the per player metrics.
+pub struct Scoreboards {
+    metrics: Arc<Metrics>,
+    games: Mutex<HashMap<String, (Scoreboard, Instant)>>,
+}
+
+impl Scoreboards {
+    pub fn new(metrics: Arc<Metrics>) -> Self {
+        Self {
+            metrics,
+            games: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Applies an event to the scoreboard of its game. Anyone named in the event who didn't join
+    /// the game is taken for a bot, and events naming more bots than the game has are dropped, so
+    /// that only the game's players end up in the per player metrics.
+    pub fn apply(&self, kind: GameEventKind, event: &GameEvent, roster: &Roster) {
+        let mut games = self.games.lock().unwrap();
+        let known = games
+            .get(&event.game_id)
+            .map(|(scoreboard, _)| &scoreboard.players);
+        let known_bots = known.map_or(0, |known| {
+            known
+                .keys()
+                .filter(|player| !roster.players.contains(*player))
+                .count()
+        });
+        let new_bots: HashSet<&String> = event
+            .players()
+            .filter(|player| !roster.players.contains(*player))
+            .filter(|player| !known.is_some_and(|known| known.contains_key(*player)))
+            .collect();
+        if known_bots + new_bots.len() > roster.bots {
+            warn!(
+                game_id = event.game_id,
+                bots = roster.bots,
+                "dropping event naming players who aren't in the game"
+            );
+            return;
+        }
+
+        if !games.contains_key(&event.game_id) && games.len() >= MAX_SCOREBOARDS {
+            let oldest = games
+                .iter()
+                .min_by_key(|(_, (_, updated))| *updated)
+                .map(|(game_id, _)| game_id.clone());
+            if let Some((scoreboard, _)) = oldest.and_then(|game_id| games.remove(&game_id)) {
+                self.metrics.remove_scoreboard(&scoreboard);
+            }
+        }
+
+        let (scoreboard, updated) = games
+            .entry(event.game_id.clone())
+            .or_insert_with(|| (Scoreboard::new(&event.game_id), Instant::now()));
+        *updated = Instant::now();
+        for player in scoreboard.apply(kind, event) {
+            self.metrics
+                .set_player_score(&event.game_id, &player, &scoreboard.players[&player]);
+        }
+        if kind == GameEventKind::Killed {
+            self.metrics.kill_with(event.weapon_label());
+        }
+    }
+
+    pub fn get(&self, game_id: &str) -> Option<Scoreboard> {
+        self.games
+            .lock()
+            .unwrap()
+            .get(game_id)
+            .map(|(scoreboard, _)| scoreboard.clone())
+    }
+
+    /// Drops the scoreboard of a game that ended, returning its final state.
+    pub fn finish(&self, game_id: &str) -> Option<Scoreboard> {
+        let (scoreboard, _) = self.games.lock().unwrap().remove(game_id)?;
+        self.metrics.remove_scoreboard(&scoreboard);
+
+        Some(scoreboard)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn joined(player: &str) -> GameEvent {
+        GameEvent {
+            game_id: "game1".to_string(),
+            player: Some(player.to_string()),
+            ..Default::default()
+        }
+    }
+
+    fn roster(players: &[&str], bots: usize) -> Roster {
+        Roster {
+            players: players.iter().map(ToString::to_string).collect(),
+            bots,
+        }
+    }
+
+    #[test]
+    fn test_players_checked_against_roster() {
+        let metrics = Arc::new(Metrics::try_new().expect("failed to register metrics"));
+        let scoreboards = Scoreboards::new(metrics.clone());
+        let roster = roster(&["alice", "bob"], 1);
+        scoreboards.apply(GameEventKind::Joined, &joined("alice"), &roster);
+        scoreboards.apply(GameEventKind::Joined, &joined("bot"), &roster);
+        // The only bot seat is taken
+        scoreboards.apply(GameEventKind::Joined, &joined("mallory"), &roster);
+        scoreboards.apply(
+            GameEventKind::Killed,
+            &GameEvent {
+                game_id: "game1".to_string(),
+                killer: Some("alice".to_string()),
+                victim: Some("eve".to_string()),
+                ..Default::default()
+            },
+            &roster,
+        );
+        // Players of the game score even before they joined
+        scoreboards.apply(
+            GameEventKind::Killed,
+            &GameEvent {
+                game_id: "game1".to_string(),
+                killer: Some("alice".to_string()),
+                victim: Some("bob".to_string()),
+                ..Default::default()
+            },
+            &roster,
+        );
+
+        let scoreboard = scoreboards.get("game1").expect("scoreboard");
+        assert_eq!(
+            scoreboard.players.keys().collect::<Vec<_>>(),
+            vec!["alice", "bob", "bot"]
+        );
+        assert_eq!(scoreboard.players["alice"].kills, 1);
+        assert!(!metrics.gather().contains("mallory"));
+        assert!(!metrics.gather().contains("eve"));
+    }
+
+    #[test]
+    fn test_no_bots_without_bot_seats() {
+        let metrics = Arc::new(Metrics::try_new().expect("failed to register metrics"));
+        let scoreboards = Scoreboards::new(metrics);
+        // Seats left in the game don't let strangers in
+        let roster = roster(&["alice"], 0);
+        scoreboards.apply(GameEventKind::Joined, &joined("mallory"), &roster);
+        scoreboards.apply(GameEventKind::Joined, &joined("alice"), &roster);
+
+        let scoreboard = scoreboards.get("game1").expect("scoreboard");
+        assert_eq!(scoreboard.players.keys().collect::<Vec<_>>(), vec!["alice"]);
+    }
+}
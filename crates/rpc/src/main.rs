@@ -1,20 +1,36 @@
-use anyhow::{Context, Result};
-use model::cluster::ClusterState;
+use anyhow::{bail, Context, Result};
+use model::{
+    admin::{AdminKey, AdminState},
+    api_error::default_catcher,
+    auth::AuthState,
+    cluster::{ClusterConfig, ClusterState, Regions},
+    leaderboard::{run_leaderboard_refresh, Leaderboards},
+    matchmaking::Matchmaker,
+    rate_limit::{RateLimitConfig, RateLimiter},
+    stats::{run_stats_refresh, StatsConfig, StatsState},
+    telemetry::{Telemetry, TelemetryConfig},
+};
 use pallas::ledger::addresses::Network;
-use rocket::{http::Method, routes};
+use rocket::{catchers, http::Method, routes};
 use rocket_cors::{AllowedOrigins, CorsOptions};
 use routes::{
     add_player::add_player,
+    admin::{audit_log, delete_head, drain, end_game, sleep, undrain, wake},
+    auth::{challenge, login},
+    events::events,
     head::head,
     heads::heads,
     health::health,
+    leaderboard::leaderboard,
+    matchmaking::{cancel, enqueue, run_matchmaker, ticket_state},
     new_game::new_game,
     sample_transactions::sample_transactions,
-    stats::{global_stats, refresh_stats, StatsState},
+    stats::{global_stats, stats_history},
+    v1,
 };
 use serde::Deserialize;
-use std::env;
-use tracing::error;
+use std::{env, sync::Arc, time::Duration};
+use tracing::{error, warn};
 
 mod model;
 mod providers;
@@ -24,6 +40,27 @@ mod routes;
 pub struct Config {
     pub admin_key_file: String,
     pub remote: bool,
+    #[serde(default = "default_session_ttl")]
+    pub session_ttl_seconds: u64,
+    // Signs the sign-in challenges and session tokens, shared by every replica. Required when
+    // there may be several replicas, i.e. when `remote` is set or several clusters are configured
+    pub auth_secret: Option<String>,
+    #[serde(default)]
+    pub admin_keys: Vec<AdminKey>,
+    #[serde(default)]
+    pub rate_limit: RateLimitConfig,
+    #[serde(default)]
+    pub regions: Regions,
+    #[serde(default)]
+    pub clusters: Vec<ClusterConfig>,
+    #[serde(default)]
+    pub stats: StatsConfig,
+    #[serde(default)]
+    pub telemetry: TelemetryConfig,
+}
+
+fn default_session_ttl() -> u64 {
+    3600
 }
 
 #[rocket::main]
@@ -31,6 +68,7 @@ async fn main() -> Result<()> {
     let rocket = rocket::build();
     let figment = rocket.figment();
     let config = figment.extract::<Config>().context("invalid config")?;
+    let telemetry = Telemetry::init("hydra-control-plane", &config.telemetry)?;
     let network: Network = env::var("NETWORK_ID")
         .map(|network_str| {
             network_str
@@ -41,36 +79,51 @@ async fn main() -> Result<()> {
         .inspect_err(|_| error!("Missing NETWORK_ID env var, defaulting to zero"))
         .unwrap_or_default()
         .into();
-    // This will start a reflector (aka: local cache) of the cluster state. The `try_default`
-    // initializer assumes that this process is running within the cluster or that the local kubeconfig
-    // context is set to the cluster. If you wanted to connect to a remote cluster, you can use the
-    // `ClusterState::remote` initializer.
-    let cluster = ClusterState::try_new(&config.admin_key_file, config.remote, network).await?;
-    let stats = StatsState::new(
-        refresh_stats()
-            .await
-            .expect("failed to fetch initial stats"),
-    );
+    let session_ttl = Duration::from_secs(config.session_ttl_seconds);
+    let auth = match &config.auth_secret {
+        Some(secret) => AuthState::new(secret.as_bytes(), session_ttl),
+        None if config.remote || config.clusters.len() > 1 => {
+            bail!("auth_secret must be set when remote is set or several clusters are configured")
+        }
+        None => {
+            warn!("no auth_secret is configured, sessions are only valid on this replica");
+            AuthState::ephemeral(session_ttl)
+        }
+    };
 
-    let bg_stats = stats.clone();
+    // This will start a reflector (aka: local cache) of the state of every configured cluster.
+    // Without `clusters` in the config, only the cluster this process runs in is watched, which
+    // assumes that this process is running within the cluster or that the local kubeconfig
+    // context is set to the cluster.
+    let cluster = ClusterState::try_new(
+        &config.admin_key_file,
+        config.remote,
+        network,
+        config.regions,
+        config.clusters,
+    )
+    .await?;
+    // Empty until the first refresh succeeds, the source being down doesn't keep the server from
+    // starting
+    let stats = StatsState::default();
+    tokio::spawn(run_stats_refresh(
+        config.stats.into_source(cluster.clone()),
+        stats.clone(),
+    ));
 
-    tokio::spawn(async move {
-        loop {
-            tokio::time::sleep(std::time::Duration::from_secs(5)).await;
-            let new_stats = refresh_stats().await;
-            match new_stats {
-                Ok(stats) => bg_stats.update(stats),
-                Err(err) => {
-                    println!("Failed to fetch stats: {:?}", err);
-                },
-            }
-        }
-    });
+    let leaderboards = Arc::new(Leaderboards::default());
+    tokio::spawn(run_leaderboard_refresh(
+        cluster.clone(),
+        leaderboards.clone(),
+    ));
+
+    let matchmaker = Arc::new(Matchmaker::new());
+    tokio::spawn(run_matchmaker(matchmaker.clone(), cluster.clone()));
 
     let cors = CorsOptions::default()
         .allowed_origins(AllowedOrigins::all())
         .allowed_methods(
-            vec![Method::Get, Method::Post, Method::Patch]
+            vec![Method::Get, Method::Post, Method::Patch, Method::Delete]
                 .into_iter()
                 .map(From::from)
                 .collect(),
@@ -80,6 +133,11 @@ async fn main() -> Result<()> {
     let _rocket = rocket::build()
         .manage(cluster)
         .manage(stats)
+        .manage(auth)
+        .manage(AdminState::new(config.admin_keys))
+        .manage(RateLimiter::new(config.rate_limit))
+        .manage(matchmaker)
+        .manage(leaderboards)
         .mount(
             "/",
             routes![
@@ -89,12 +147,33 @@ async fn main() -> Result<()> {
                 add_player,
                 sample_transactions,
                 global_stats,
+                stats_history,
+                leaderboard,
                 health,
+                challenge,
+                login,
+                sleep,
+                wake,
+                drain,
+                undrain,
+                end_game,
+                delete_head,
+                audit_log,
+                enqueue,
+                ticket_state,
+                cancel,
+                events,
+                v1::global,
+                v1::heads,
+                v1::head,
+                v1::openapi_json,
             ],
         )
+        .register("/", catchers![default_catcher])
         .attach(cors.to_cors().unwrap())
         .launch()
         .await?;
+    telemetry.shutdown();
 
     Ok(())
 }
@@ -0,0 +1,137 @@
+use std::{collections::VecDeque, sync::Mutex};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use subtle::ConstantTimeEq;
+use tracing::info;
+
+const AUDIT_LOG_SIZE: usize = 1000;
+
+/// Roles are ordered, each one is allowed everything the previous ones are.
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum AdminRole {
+    Operator,
+    Admin,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct AdminKey {
+    pub name: String,
+    pub key: String,
+    pub role: AdminRole,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct AuditEntry {
+    pub timestamp: String,
+    pub actor: String,
+    pub action: String,
+    pub head_id: String,
+    pub error: Option<String>,
+}
+
+pub struct AdminState {
+    keys: Vec<AdminKey>,
+    audit_log: Mutex<VecDeque<AuditEntry>>,
+}
+
+impl AdminState {
+    pub fn new(keys: Vec<AdminKey>) -> Self {
+        Self {
+            keys,
+            audit_log: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    /// Finds the admin a key belongs to. Keys are compared as digests in constant time, and every
+    /// key is checked, so that the time taken doesn't tell how close a guess was.
+    pub fn authenticate(&self, key: &str) -> Option<AdminKey> {
+        let digest = Sha256::digest(key.as_bytes());
+        self.keys.iter().fold(None, |found, admin| {
+            let matches: bool = Sha256::digest(admin.key.as_bytes())
+                .as_slice()
+                .ct_eq(digest.as_slice())
+                .into();
+            found.or_else(|| matches.then(|| admin.clone()))
+        })
+    }
+
+    /// Records an admin action, both in the in-memory audit log and in the `audit` tracing target.
+    pub fn record<T>(
+        &self,
+        actor: &AdminKey,
+        action: &str,
+        head_id: &str,
+        result: &anyhow::Result<T>,
+    ) {
+        let entry = AuditEntry {
+            timestamp: chrono::Utc::now().to_rfc3339(),
+            actor: actor.name.clone(),
+            action: action.to_string(),
+            head_id: head_id.to_string(),
+            error: result.as_ref().err().map(|err| format!("{:#}", err)),
+        };
+        info!(
+            target: "audit",
+            actor = entry.actor,
+            action = entry.action,
+            head_id = entry.head_id,
+            error = entry.error,
+            "admin action"
+        );
+
+        let mut audit_log = self.audit_log.lock().unwrap();
+        if audit_log.len() == AUDIT_LOG_SIZE {
+            audit_log.pop_front();
+        }
+        audit_log.push_back(entry);
+    }
+
+    pub fn audit_log(&self) -> Vec<AuditEntry> {
+        self.audit_log.lock().unwrap().iter().cloned().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_admin_keys() {
+        let state = AdminState::new(vec![
+            AdminKey {
+                name: "ops".to_string(),
+                key: "ops-key".to_string(),
+                role: AdminRole::Operator,
+            },
+            AdminKey {
+                name: "root".to_string(),
+                key: "root-key".to_string(),
+                role: AdminRole::Admin,
+            },
+        ]);
+
+        let ops = state.authenticate("ops-key").expect("known key");
+        assert!(ops.role < AdminRole::Admin);
+        assert!(state.authenticate("unknown-key").is_none());
+        assert!(state.authenticate("ops-ke").is_none());
+        assert_eq!(
+            state.authenticate("root-key").map(|admin| admin.name),
+            Some("root".to_string())
+        );
+
+        state.record(&ops, "sleep", "node-1", &Ok(()));
+        state.record(
+            &ops,
+            "wake",
+            "node-1",
+            &Err::<(), _>(anyhow::anyhow!("not found")),
+        );
+
+        let audit_log = state.audit_log();
+        assert_eq!(audit_log.len(), 2);
+        assert_eq!(audit_log[0].action, "sleep");
+        assert_eq!(audit_log[1].error.as_deref(), Some("not found"));
+    }
+}
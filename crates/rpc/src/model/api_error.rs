@@ -0,0 +1,242 @@
+use std::{fmt, time::Duration};
+
+use rocket::{
+    catch,
+    http::{Header, Status},
+    response::{self, Responder},
+    serde::json::Json,
+    Request, Response,
+};
+use serde::{Deserialize, Serialize};
+use tracing::error;
+
+use super::{game::contract::game_state::GameStateError, hydra::hydra_socket::TxRejected};
+
+/// Stable, machine readable error codes. Clients match on these, so existing codes must not be
+/// renamed.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ErrorCode {
+    NoWarmNode,
+    NodeNotFound,
+    NotFound,
+    HeadUnreachable,
+    TxRejected,
+    InvalidAddress,
+    LobbyFull,
+    InvalidGameState,
+    InvalidRequest,
+    Unauthorized,
+    Forbidden,
+    RateLimited,
+    Unavailable,
+    Internal,
+}
+
+impl ErrorCode {
+    pub fn status(&self) -> Status {
+        match self {
+            ErrorCode::NoWarmNode | ErrorCode::Unavailable => Status::ServiceUnavailable,
+            ErrorCode::NodeNotFound | ErrorCode::NotFound => Status::NotFound,
+            ErrorCode::HeadUnreachable => Status::BadGateway,
+            ErrorCode::TxRejected | ErrorCode::InvalidRequest | ErrorCode::InvalidAddress => {
+                Status::BadRequest
+            }
+            ErrorCode::LobbyFull | ErrorCode::InvalidGameState => Status::Conflict,
+            ErrorCode::Unauthorized => Status::Unauthorized,
+            ErrorCode::Forbidden => Status::Forbidden,
+            ErrorCode::RateLimited => Status::TooManyRequests,
+            ErrorCode::Internal => Status::InternalServerError,
+        }
+    }
+
+    fn from_status(status: Status) -> Self {
+        match status.code {
+            400 | 422 => ErrorCode::InvalidRequest,
+            401 => ErrorCode::Unauthorized,
+            403 => ErrorCode::Forbidden,
+            404 => ErrorCode::NotFound,
+            429 => ErrorCode::RateLimited,
+            502 => ErrorCode::HeadUnreachable,
+            503 => ErrorCode::Unavailable,
+            _ => ErrorCode::Internal,
+        }
+    }
+}
+
+/// The error returned by every route of both the rpc server and the metrics exporter, rendered
+/// as `{"code": ..., "message": ...}` with the status matching its code.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ApiError {
+    pub code: ErrorCode,
+    pub message: String,
+    #[serde(skip)]
+    pub retry_after: Option<Duration>,
+}
+
+pub type ApiResult<T> = Result<T, ApiError>;
+
+impl ApiError {
+    pub fn new(code: ErrorCode, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+            retry_after: None,
+        }
+    }
+
+    pub fn with_retry_after(mut self, retry_after: Duration) -> Self {
+        self.retry_after = Some(retry_after);
+        self
+    }
+
+    pub fn node_not_found(id: &str) -> Self {
+        Self::new(ErrorCode::NodeNotFound, format!("node {} not found", id))
+    }
+
+    pub fn invalid_address() -> Self {
+        Self::new(ErrorCode::InvalidAddress, "invalid address")
+    }
+
+    /// Turns an error response of another service speaking this format (i.e. the metrics
+    /// exporter) into the same error, so its code reaches the caller untouched.
+    pub fn from_body(status: u16, body: &[u8]) -> Self {
+        serde_json::from_slice(body).unwrap_or_else(|_| {
+            Self::new(
+                ErrorCode::HeadUnreachable,
+                format!("node responded with {}", status),
+            )
+        })
+    }
+}
+
+impl std::error::Error for ApiError {}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}: {}", self.code, self.message)
+    }
+}
+
+impl From<anyhow::Error> for ApiError {
+    fn from(err: anyhow::Error) -> Self {
+        if let Some(api_error) = err
+            .chain()
+            .find_map(|cause| cause.downcast_ref::<ApiError>())
+        {
+            return Self::new(api_error.code, api_error.message.clone());
+        }
+
+        let code = err
+            .chain()
+            .find_map(|cause| {
+                if let Some(err) = cause.downcast_ref::<GameStateError>() {
+                    Some(match err {
+                        GameStateError::LobbyFull => ErrorCode::LobbyFull,
+                        _ => ErrorCode::InvalidGameState,
+                    })
+                } else if cause.is::<TxRejected>() {
+                    Some(ErrorCode::TxRejected)
+                } else if cause.is::<reqwest::Error>() {
+                    Some(ErrorCode::HeadUnreachable)
+                } else {
+                    None
+                }
+            })
+            .unwrap_or(ErrorCode::Internal);
+
+        match code {
+            // The chain of these tells about the internals, e.g. the URLs of the nodes, so it's
+            // only logged
+            ErrorCode::HeadUnreachable => {
+                error!("{:#}", err);
+                Self::new(code, "the head could not be reached")
+            }
+            ErrorCode::Internal => {
+                error!("{:#}", err);
+                Self::new(code, "internal error")
+            }
+            _ => Self::new(code, format!("{:#}", err)),
+        }
+    }
+}
+
+impl<'r> Responder<'r, 'static> for ApiError {
+    fn respond_to(self, request: &'r Request<'_>) -> response::Result<'static> {
+        let mut response = Response::build_from(Json(&self).respond_to(request)?)
+            .status(self.code.status())
+            .finalize();
+
+        if let Some(retry_after) = self.retry_after {
+            // Retry-After is in whole seconds, so round up to not invite an early retry
+            let seconds = retry_after
+                .as_secs()
+                .saturating_add(u64::from(retry_after.subsec_nanos() > 0));
+            response.set_header(Header::new("Retry-After", seconds.to_string()));
+        }
+
+        Ok(response)
+    }
+}
+
+/// Renders the errors Rocket raises itself (failing guards, unmatched routes, ...) as `ApiError`s.
+#[catch(default)]
+pub fn default_catcher(status: Status, _request: &Request) -> ApiError {
+    ApiError::new(ErrorCode::from_status(status), status.reason_lossy())
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Context;
+
+    use super::*;
+
+    #[test]
+    fn test_error_classification() {
+        let err: ApiError = Err::<(), _>(GameStateError::LobbyFull)
+            .context("error adding player")
+            .unwrap_err()
+            .into();
+        assert_eq!(err.code, ErrorCode::LobbyFull);
+        assert_eq!(err.code.status(), Status::Conflict);
+        assert_eq!(err.message, "error adding player: lobby is full");
+
+        let err: ApiError = anyhow::Error::new(TxRejected {
+            tx_id: "abc".to_string(),
+            reason: "bad datum".to_string(),
+        })
+        .into();
+        assert_eq!(err.code, ErrorCode::TxRejected);
+
+        let err: ApiError = anyhow::anyhow!("something broke").into();
+        assert_eq!(err.code, ErrorCode::Internal);
+        assert_eq!(err.code.status(), Status::InternalServerError);
+        assert_eq!(err.message, "internal error");
+
+        let err: ApiError = Err::<(), _>(ApiError::node_not_found("node-1"))
+            .context("failed to reach node-1 at http://10.0.0.1:8000")
+            .unwrap_err()
+            .into();
+        assert_eq!(err.code, ErrorCode::NodeNotFound);
+        assert_eq!(err.message, "node node-1 not found");
+
+        assert_eq!(
+            ErrorCode::from_status(Status::NotFound),
+            ErrorCode::NotFound
+        );
+    }
+
+    #[test]
+    fn test_error_body() {
+        let err = ApiError::new(ErrorCode::NoWarmNode, "no available nodes found")
+            .with_retry_after(Duration::from_secs(5));
+        let body = serde_json::to_value(&err).unwrap();
+
+        assert_eq!(
+            body,
+            serde_json::json!({ "code": "NoWarmNode", "message": "no available nodes found" })
+        );
+        let parsed: ApiError = serde_json::from_value(body).unwrap();
+        assert_eq!(parsed.code, ErrorCode::NoWarmNode);
+        assert_eq!(parsed.retry_after, None);
+    }
+}
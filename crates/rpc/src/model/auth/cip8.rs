@@ -0,0 +1,259 @@
+use pallas::{
+    codec::minicbor::{data::Type, Decoder, Encoder},
+    crypto::{
+        hash::Hasher,
+        key::ed25519::{PublicKey, Signature},
+    },
+    ledger::addresses::{Address, ShelleyPaymentPart},
+};
+
+use super::AuthError;
+
+// COSE header labels, see RFC 8152 and CIP-8
+const ALG: i64 = 1;
+const EDDSA: i64 = -8;
+const OKP_X: i64 = -2;
+
+/// A COSE_Sign1 message, as returned by the `signData` method of a CIP-30 wallet.
+#[derive(Debug)]
+pub struct CoseSign1 {
+    protected: Vec<u8>,
+    payload: Vec<u8>,
+    signature: Vec<u8>,
+    address: Vec<u8>,
+}
+
+impl CoseSign1 {
+    pub fn decode(bytes: &[u8]) -> Result<Self, AuthError> {
+        let malformed = |_| AuthError::Malformed("COSE_Sign1");
+        let mut d = Decoder::new(bytes);
+
+        if d.datatype().map_err(malformed)? == Type::Tag {
+            d.tag().map_err(malformed)?;
+        }
+        if d.array().map_err(malformed)? != Some(4) {
+            return Err(AuthError::Malformed("COSE_Sign1"));
+        }
+
+        let protected = d.bytes().map_err(malformed)?.to_vec();
+
+        let mut hashed = false;
+        let entries = d.map().map_err(malformed)?.unwrap_or_default();
+        for _ in 0..entries {
+            match d.datatype().map_err(malformed)? {
+                Type::String => {
+                    let label = d.str().map_err(malformed)?;
+                    if label == "hashed" {
+                        hashed = d.bool().map_err(malformed)?;
+                    } else {
+                        d.skip().map_err(malformed)?;
+                    }
+                }
+                _ => {
+                    d.skip().map_err(malformed)?;
+                    d.skip().map_err(malformed)?;
+                }
+            }
+        }
+        if hashed {
+            return Err(AuthError::Malformed("hashed payloads are not supported"));
+        }
+
+        if d.datatype().map_err(malformed)? == Type::Null {
+            return Err(AuthError::Malformed("detached payloads are not supported"));
+        }
+        let payload = d.bytes().map_err(malformed)?.to_vec();
+        let signature = d.bytes().map_err(malformed)?.to_vec();
+
+        let (alg, address) = Self::decode_protected(&protected)?;
+        if alg != Some(EDDSA) {
+            return Err(AuthError::Malformed("unsupported signature algorithm"));
+        }
+
+        Ok(Self {
+            protected,
+            payload,
+            signature,
+            address: address.ok_or(AuthError::Malformed("missing address header"))?,
+        })
+    }
+
+    fn decode_protected(bytes: &[u8]) -> Result<(Option<i64>, Option<Vec<u8>>), AuthError> {
+        let malformed = |_| AuthError::Malformed("protected header");
+        let mut d = Decoder::new(bytes);
+
+        let mut alg = None;
+        let mut address = None;
+        let entries = d.map().map_err(malformed)?.unwrap_or_default();
+        for _ in 0..entries {
+            match d.datatype().map_err(malformed)? {
+                Type::String => {
+                    if d.str().map_err(malformed)? == "address" {
+                        address = Some(d.bytes().map_err(malformed)?.to_vec());
+                    } else {
+                        d.skip().map_err(malformed)?;
+                    }
+                }
+                _ => {
+                    if d.i64().map_err(malformed)? == ALG {
+                        alg = Some(d.i64().map_err(malformed)?);
+                    } else {
+                        d.skip().map_err(malformed)?;
+                    }
+                }
+            }
+        }
+
+        Ok((alg, address))
+    }
+
+    /// The bytes covered by the signature, the `Sig_structure` of RFC 8152.
+    fn sig_structure(&self) -> Vec<u8> {
+        let mut e = Encoder::new(Vec::new());
+        e.array(4)
+            .and_then(|e| e.str("Signature1"))
+            .and_then(|e| e.bytes(&self.protected))
+            .and_then(|e| e.bytes(&[]))
+            .and_then(|e| e.bytes(&self.payload))
+            .expect("encoding into a Vec can't fail");
+
+        e.into_writer()
+    }
+
+    /// Checks that the message signs `payload` on behalf of `address` with `key`, a CIP-30 COSE_Key.
+    pub fn verify(&self, address: &Address, payload: &[u8], key: &[u8]) -> Result<(), AuthError> {
+        if self.address != address.to_vec() {
+            return Err(AuthError::AddressMismatch);
+        }
+        if self.payload != payload {
+            return Err(AuthError::PayloadMismatch);
+        }
+
+        let public_key = decode_cose_key(key)?;
+        let key_hash = Hasher::<224>::hash(public_key.as_ref());
+        match address {
+            Address::Shelley(shelley) => match shelley.payment() {
+                ShelleyPaymentPart::Key(hash) if hash == &key_hash => {}
+                _ => return Err(AuthError::AddressMismatch),
+            },
+            _ => return Err(AuthError::AddressMismatch),
+        }
+
+        let signature: [u8; 64] = self
+            .signature
+            .as_slice()
+            .try_into()
+            .map_err(|_| AuthError::Malformed("signature"))?;
+        if !public_key.verify(self.sig_structure(), &Signature::from(signature)) {
+            return Err(AuthError::InvalidSignature);
+        }
+
+        Ok(())
+    }
+}
+
+fn decode_cose_key(bytes: &[u8]) -> Result<PublicKey, AuthError> {
+    let malformed = |_| AuthError::Malformed("COSE_Key");
+    let mut d = Decoder::new(bytes);
+
+    let mut x = None;
+    let entries = d.map().map_err(malformed)?.unwrap_or_default();
+    for _ in 0..entries {
+        let label = d.i64().map_err(malformed)?;
+        if label == OKP_X {
+            x = Some(d.bytes().map_err(malformed)?);
+        } else {
+            d.skip().map_err(malformed)?;
+        }
+    }
+
+    let x: [u8; 32] = x
+        .and_then(|x| x.try_into().ok())
+        .ok_or(AuthError::Malformed("COSE_Key"))?;
+
+    Ok(PublicKey::from(x))
+}
+
+#[cfg(test)]
+mod tests {
+    use pallas::crypto::key::ed25519::SecretKey;
+
+    use super::*;
+
+    fn address_for(key: &SecretKey) -> Address {
+        let mut bytes = Hasher::<224>::hash(key.public_key().as_ref()).to_vec();
+        bytes.insert(0, 0b01100000);
+        Address::from_bytes(&bytes).expect("valid address")
+    }
+
+    fn cose_key(key: &SecretKey) -> Vec<u8> {
+        let mut e = Encoder::new(Vec::new());
+        e.map(4)
+            .and_then(|e| e.i64(1)?.i64(1))
+            .and_then(|e| e.i64(ALG)?.i64(EDDSA))
+            .and_then(|e| e.i64(-1)?.i64(6))
+            .and_then(|e| e.i64(OKP_X)?.bytes(key.public_key().as_ref()))
+            .expect("valid key");
+        e.into_writer()
+    }
+
+    fn sign(key: &SecretKey, address: &Address, payload: &[u8]) -> Vec<u8> {
+        let mut protected = Encoder::new(Vec::new());
+        protected
+            .map(2)
+            .and_then(|e| e.i64(ALG)?.i64(EDDSA))
+            .and_then(|e| e.str("address")?.bytes(&address.to_vec()))
+            .expect("valid header");
+        let protected = protected.into_writer();
+
+        let unsigned = CoseSign1 {
+            protected: protected.clone(),
+            payload: payload.to_vec(),
+            signature: vec![],
+            address: address.to_vec(),
+        };
+        let signature = key.sign(unsigned.sig_structure());
+
+        let mut e = Encoder::new(Vec::new());
+        e.array(4)
+            .and_then(|e| e.bytes(&protected))
+            .and_then(|e| e.map(1)?.str("hashed")?.bool(false))
+            .and_then(|e| e.bytes(payload))
+            .and_then(|e| e.bytes(signature.as_ref()))
+            .expect("valid message");
+        e.into_writer()
+    }
+
+    #[test]
+    fn test_verify_cip8_signature() {
+        let key: SecretKey = [5; 32].into();
+        let address = address_for(&key);
+        let message = sign(&key, &address, b"challenge");
+
+        let cose = CoseSign1::decode(&message).expect("valid COSE_Sign1");
+        assert!(cose.verify(&address, b"challenge", &cose_key(&key)).is_ok());
+        assert_eq!(
+            cose.verify(&address, b"other challenge", &cose_key(&key)),
+            Err(AuthError::PayloadMismatch)
+        );
+    }
+
+    #[test]
+    fn test_reject_foreign_key() {
+        let key: SecretKey = [5; 32].into();
+        let other: SecretKey = [6; 32].into();
+        let address = address_for(&key);
+
+        // Signed by another key claiming the same address
+        let message = sign(&other, &address, b"challenge");
+        let cose = CoseSign1::decode(&message).expect("valid COSE_Sign1");
+        assert_eq!(
+            cose.verify(&address, b"challenge", &cose_key(&other)),
+            Err(AuthError::AddressMismatch)
+        );
+        assert_eq!(
+            cose.verify(&address, b"challenge", &cose_key(&key)),
+            Err(AuthError::InvalidSignature)
+        );
+    }
+}
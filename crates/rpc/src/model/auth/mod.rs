@@ -0,0 +1,30 @@
+use std::fmt;
+
+mod cip8;
+mod session;
+
+pub use cip8::*;
+pub use session::*;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum AuthError {
+    Malformed(&'static str),
+    UnknownChallenge,
+    AddressMismatch,
+    PayloadMismatch,
+    InvalidSignature,
+}
+
+impl fmt::Display for AuthError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AuthError::Malformed(what) => write!(f, "malformed {}", what),
+            AuthError::UnknownChallenge => write!(f, "unknown or expired challenge"),
+            AuthError::AddressMismatch => write!(f, "signature does not belong to the address"),
+            AuthError::PayloadMismatch => write!(f, "signed payload does not match the challenge"),
+            AuthError::InvalidSignature => write!(f, "invalid signature"),
+        }
+    }
+}
+
+impl std::error::Error for AuthError {}
@@ -0,0 +1,211 @@
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use hmac::{Hmac, Mac};
+use pallas::ledger::addresses::Address;
+use rand::RngCore;
+use sha2::Sha256;
+
+use super::{AuthError, CoseSign1};
+
+// Kept short, since answered challenges are only remembered by the replica that was signed in to.
+// Another replica would accept a captured signature until the challenge expires.
+const CHALLENGE_TTL: Duration = Duration::from_secs(60);
+// Answered challenges are remembered until they expire, so that a signature can't be replayed
+// against the same replica. Only successful logins are recorded, and the oldest are evicted past
+// this many.
+const MAX_ANSWERED: usize = 10_000;
+
+#[derive(Clone, Debug)]
+pub struct Session {
+    pub address: String,
+}
+
+/// Issues sign-in challenges and the session tokens handed out once a wallet signs them. Both are
+/// signed with a secret shared by every replica, `{address}.{expires_at}[.{nonce}].{mac}`, so any
+/// replica can check them without keeping them around.
+pub struct AuthState {
+    secret: Vec<u8>,
+    session_ttl: Duration,
+    answered: Mutex<HashMap<String, u64>>,
+}
+
+impl AuthState {
+    pub fn new(secret: &[u8], session_ttl: Duration) -> Self {
+        Self {
+            secret: secret.to_vec(),
+            session_ttl,
+            answered: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// A state with a random secret, whose challenges and sessions only this process accepts.
+    pub fn ephemeral(session_ttl: Duration) -> Self {
+        Self::new(random_hex().as_bytes(), session_ttl)
+    }
+
+    pub fn session_ttl(&self) -> Duration {
+        self.session_ttl
+    }
+
+    /// Creates a challenge for `address`, returning its nonce and the message the wallet must sign.
+    pub fn challenge(&self, address: &str) -> (String, String) {
+        let expires_at = unix_now() + CHALLENGE_TTL.as_secs();
+        let nonce = self.sign(
+            "challenge",
+            &[address, &expires_at.to_string(), &random_hex()],
+        );
+        let message = challenge_message(&nonce);
+
+        (nonce, message)
+    }
+
+    /// Verifies the COSE_Sign1 `signature` over the challenge and opens a session for its address.
+    /// Challenges can only be answered once.
+    pub fn login(&self, nonce: &str, signature: &[u8], key: &[u8]) -> Result<String, AuthError> {
+        let now = unix_now();
+        let (address, expires_at) = self
+            .verify("challenge", nonce, now)
+            .ok_or(AuthError::UnknownChallenge)?;
+
+        let parsed = Address::from_bech32(&address).map_err(|_| AuthError::Malformed("address"))?;
+        CoseSign1::decode(signature)?.verify(&parsed, challenge_message(nonce).as_bytes(), key)?;
+
+        {
+            let mut answered = self.answered.lock().unwrap();
+            answered.retain(|_, expires_at| *expires_at > now);
+            if answered.contains_key(nonce) {
+                return Err(AuthError::UnknownChallenge);
+            }
+            if answered.len() >= MAX_ANSWERED {
+                let oldest = answered
+                    .iter()
+                    .min_by_key(|(_, expires_at)| **expires_at)
+                    .map(|(nonce, _)| nonce.clone());
+                if let Some(oldest) = oldest {
+                    answered.remove(&oldest);
+                }
+            }
+            answered.insert(nonce.to_string(), expires_at);
+        }
+
+        let expires_at = now + self.session_ttl.as_secs();
+        Ok(self.sign("session", &[&address, &expires_at.to_string()]))
+    }
+
+    pub fn session(&self, token: &str) -> Option<Session> {
+        self.verify("session", token, unix_now())
+            .map(|(address, _)| Session { address })
+    }
+
+    fn mac(&self, kind: &str, payload: &str) -> Hmac<Sha256> {
+        let mut mac =
+            Hmac::<Sha256>::new_from_slice(&self.secret).expect("HMAC accepts keys of any size");
+        mac.update(kind.as_bytes());
+        mac.update(b".");
+        mac.update(payload.as_bytes());
+
+        mac
+    }
+
+    fn sign(&self, kind: &str, fields: &[&str]) -> String {
+        let payload = fields.join(".");
+        let mac = self.mac(kind, &payload).finalize().into_bytes();
+
+        format!("{}.{}", payload, hex::encode(mac))
+    }
+
+    /// The address and expiry of a token of the given kind, if it was signed with our secret and
+    /// hasn't expired.
+    fn verify(&self, kind: &str, token: &str, now: u64) -> Option<(String, u64)> {
+        let (payload, mac) = token.rsplit_once('.')?;
+        self.mac(kind, payload)
+            .verify_slice(&hex::decode(mac).ok()?)
+            .ok()?;
+
+        let mut fields = payload.split('.');
+        let address = fields.next()?;
+        let expires_at = fields.next()?.parse::<u64>().ok()?;
+
+        (expires_at > now).then(|| (address.to_string(), expires_at))
+    }
+}
+
+fn challenge_message(nonce: &str) -> String {
+    format!("Sign in to Hydra Doom with nonce {}", nonce)
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+fn random_hex() -> String {
+    let mut bytes = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
+
+    hex::encode(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ADDRESS: &str = "addr_test1vzdjnh24kw99aqj8whfsxu37s0sgmq7yhfeva2egg92t3gsws2hwn";
+
+    #[test]
+    fn test_challenge_is_signed() {
+        let auth = AuthState::new(b"secret", Duration::from_secs(60));
+        let (nonce, message) = auth.challenge(ADDRESS);
+        assert!(message.ends_with(&nonce));
+
+        // A valid challenge gets as far as the signature
+        assert!(matches!(
+            auth.login(&nonce, &[], &[]),
+            Err(AuthError::Malformed(_))
+        ));
+
+        let tampered = nonce.replacen(ADDRESS, "addr_test1vqxyz", 1);
+        assert_eq!(
+            auth.login(&tampered, &[], &[]),
+            Err(AuthError::UnknownChallenge)
+        );
+
+        // Another replica shares the secret, another deployment doesn't
+        let replica = AuthState::new(b"secret", Duration::from_secs(60));
+        assert!(matches!(
+            replica.login(&nonce, &[], &[]),
+            Err(AuthError::Malformed(_))
+        ));
+        let other = AuthState::new(b"other secret", Duration::from_secs(60));
+        assert_eq!(
+            other.login(&nonce, &[], &[]),
+            Err(AuthError::UnknownChallenge)
+        );
+    }
+
+    #[test]
+    fn test_session_tokens() {
+        let auth = AuthState::new(b"secret", Duration::from_secs(60));
+        let now = unix_now();
+
+        let token = auth.sign("session", &[ADDRESS, &(now + 60).to_string()]);
+        assert_eq!(
+            auth.session(&token).map(|session| session.address),
+            Some(ADDRESS.to_string())
+        );
+
+        let expired = auth.sign("session", &[ADDRESS, &(now - 1).to_string()]);
+        assert!(auth.session(&expired).is_none());
+
+        // Challenges aren't sessions
+        let (nonce, _) = auth.challenge(ADDRESS);
+        assert!(auth.session(&nonce).is_none());
+        assert!(auth.session("not a token").is_none());
+    }
+}
@@ -1,6 +1,7 @@
 use std::collections::BTreeMap;
 
-use k8s_openapi::apimachinery::pkg::api::resource::Quantity;
+use k8s_openapi::apimachinery::pkg::{api::resource::Quantity, apis::meta::v1::Time};
+use k8s_openapi::chrono::Utc;
 use kube::CustomResource;
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
@@ -51,6 +52,10 @@ pub struct HydraDoomNodeSpec {
     pub start_chain_from: Option<String>,
     pub asleep: Option<bool>,
     pub resources: Option<Resources>,
+    /// How many games the head may host at the same time. Defaults to a single game.
+    pub max_games: Option<u32>,
+    /// Draining heads finish their current games but aren't scheduled new ones.
+    pub draining: Option<bool>,
 }
 
 #[derive(Deserialize, Serialize, Clone, Default, Debug, JsonSchema)]
@@ -61,4 +66,35 @@ pub struct HydraDoomNodeStatus {
     pub node_state: String,
     pub game_state: String,
     pub transactions: i64,
+    #[serde(default)]
+    pub open_games: i64,
+}
+
+/// Annotation holding the claims control plane replicas put on a node while they create a game on
+/// it, as a JSON list of `NodeClaim`s.
+pub const CLAIMS_ANNOTATION: &str = "hydra.doom/claims";
+
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct NodeClaim {
+    pub claimed_by: String,
+    pub claimed_at: Time,
+    /// Set by the replica making the claim, after which the claim stops counting in case the game
+    /// it was made for was never created.
+    pub expires_at: Time,
+}
+
+impl HydraDoomNode {
+    /// The claims on this node that haven't expired yet.
+    pub fn live_claims(&self) -> Vec<NodeClaim> {
+        self.metadata
+            .annotations
+            .as_ref()
+            .and_then(|annotations| annotations.get(CLAIMS_ANNOTATION))
+            .and_then(|claims| serde_json::from_str::<Vec<NodeClaim>>(claims).ok())
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|claim| Utc::now() < claim.expires_at.0)
+            .collect()
+    }
 }
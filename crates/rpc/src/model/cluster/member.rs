@@ -0,0 +1,148 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use std::{future::ready, path::PathBuf};
+
+use anyhow::{bail, Context};
+use futures_util::StreamExt as _;
+use kube::config::{KubeConfigOptions, Kubeconfig};
+use kube::runtime::{reflector::ObjectRef, reflector::Store, watcher, WatchStreamExt};
+use serde::Deserialize;
+use tracing::{info, warn};
+
+use super::{define_namespace, HydraDoomNode, Regions};
+use crate::model::events::{EventHub, EventKind, HeadSummary};
+
+// How long a cluster has to list its nodes before it's considered unreachable.
+const READY_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// One of the clusters the control plane routes players to.
+#[derive(Deserialize, Clone, Debug)]
+pub struct ClusterConfig {
+    pub name: String,
+    /// Region of the heads in this cluster, unless their name says otherwise.
+    pub region: String,
+    /// Use the in-cluster (or default kubeconfig) credentials and reach the nodes directly,
+    /// instead of going through the cluster's API server.
+    #[serde(default)]
+    pub in_cluster: bool,
+    /// Defaults to `$KUBECONFIG` or `~/.kube/config`.
+    pub kubeconfig: Option<PathBuf>,
+    /// Defaults to the current context of the kubeconfig.
+    pub context: Option<String>,
+    pub namespace: Option<String>,
+}
+
+/// A cluster whose `HydraDoomNode`s are kept in a local cache by a reflector.
+pub struct MemberCluster {
+    pub name: String,
+    pub region: String,
+    pub namespace: String,
+    pub in_cluster: bool,
+    pub client: kube::Client,
+    pub store: Store<HydraDoomNode>,
+    _watcher_handle: tokio::task::JoinHandle<()>,
+}
+
+impl MemberCluster {
+    /// The cluster this process runs in, or the one the default kubeconfig points to.
+    pub fn local(region: &str) -> ClusterConfig {
+        ClusterConfig {
+            name: "local".to_string(),
+            region: region.to_string(),
+            in_cluster: true,
+            kubeconfig: None,
+            context: None,
+            namespace: None,
+        }
+    }
+
+    /// Connects to the cluster and starts watching its nodes, publishing their changes to
+    /// `events`. Fails unless the nodes could be listed within `READY_TIMEOUT`.
+    pub async fn try_new(
+        config: ClusterConfig,
+        regions: Regions,
+        events: Arc<EventHub>,
+    ) -> anyhow::Result<Self> {
+        let client = if config.in_cluster {
+            kube::Client::try_default().await?
+        } else {
+            let kubeconfig = match &config.kubeconfig {
+                Some(path) => Kubeconfig::read_from(path)?,
+                None => Kubeconfig::read()?,
+            };
+            let options = KubeConfigOptions {
+                context: config.context.clone(),
+                ..Default::default()
+            };
+            kube::Client::try_from(
+                kube::Config::from_custom_kubeconfig(kubeconfig, &options)
+                    .await
+                    .with_context(|| format!("invalid kubeconfig for cluster {}", config.name))?,
+            )?
+        };
+
+        let namespace = config.namespace.unwrap_or_else(define_namespace);
+        info!(cluster = config.name, namespace, "watching namespace");
+
+        let nodes: kube::Api<HydraDoomNode> = kube::Api::namespaced(client.clone(), &namespace);
+        let (store, writer) = kube::runtime::reflector::store();
+
+        // Create the infinite reflector stream, backing off while the cluster can't be reached
+        let rf = kube::runtime::reflector(
+            writer,
+            kube::runtime::watcher(nodes, kube::runtime::watcher::Config::default())
+                .default_backoff(),
+        );
+
+        let name = config.name.clone();
+        let region = config.region.clone();
+        let watcher_handle = tokio::spawn(async move {
+            // The operator updates the status every few seconds, only actual changes are pushed
+            let mut last_seen: HashMap<String, HeadSummary> = HashMap::new();
+            let infinite_watch = rf.for_each(|event| {
+                match event {
+                    Ok(watcher::Event::Apply(node) | watcher::Event::InitApply(node)) => {
+                        let name = node.metadata.name.clone().unwrap_or_default();
+                        let region = regions.region_of(&name).unwrap_or(region.as_str());
+                        let summary = HeadSummary::new(&node, region);
+                        if last_seen.get(&name) != Some(&summary) {
+                            last_seen.insert(name.clone(), summary.clone());
+                            events.publish(&name, EventKind::HeadUpdated { head: summary });
+                        }
+                    }
+                    Ok(watcher::Event::Delete(node)) => {
+                        let name = node.metadata.name.clone().unwrap_or_default();
+                        last_seen.remove(&name);
+                        events.publish(&name, EventKind::HeadRemoved);
+                    }
+                    Err(err) => warn!(cluster = name, "failed to watch nodes: {}", err),
+                    _ => {}
+                }
+                ready(())
+            });
+            infinite_watch.await;
+        });
+
+        let ready = tokio::time::timeout(READY_TIMEOUT, store.wait_until_ready()).await;
+        if !matches!(ready, Ok(Ok(()))) {
+            watcher_handle.abort();
+            bail!("failed to list the nodes of cluster {}", config.name);
+        }
+
+        Ok(Self {
+            name: config.name,
+            region: config.region,
+            namespace,
+            in_cluster: config.in_cluster,
+            client,
+            store,
+            _watcher_handle: watcher_handle,
+        })
+    }
+
+    pub fn get(&self, id: &str) -> Option<Arc<HydraDoomNode>> {
+        self.store
+            .get(&ObjectRef::<HydraDoomNode>::new(id).within(&self.namespace))
+    }
+}
@@ -1,47 +1,173 @@
-use std::collections::HashMap;
+use std::cmp::Reverse;
 use std::fs::File;
-use std::sync::Mutex;
-use std::{future::ready, sync::Arc};
+use std::sync::Arc;
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
 
-use anyhow::Context;
-use futures_util::StreamExt as _;
-use kube::runtime::{reflector::ObjectRef, WatchStreamExt as _};
+use anyhow::{bail, Context};
+use futures_util::future::join_all;
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::Time;
+use k8s_openapi::chrono::Utc;
+use kube::api::{DeleteParams, Patch, PatchParams};
 use pallas::crypto::key::ed25519::SecretKey;
 use pallas::ledger::addresses::Network;
 use rand::seq::IteratorRandom;
-use rand::thread_rng;
+use rand::{thread_rng, RngCore};
+use reqwest::Method;
 use serde::Deserialize;
+use serde_json::Value;
+
+use super::api_error::{ApiError, ApiResult, ErrorCode};
+use super::events::EventHub;
+use super::telemetry::trace_headers;
 
 mod crd;
+mod member;
 mod node;
+mod region;
 pub mod shared;
 
 pub use crd::*;
+pub use member::*;
 pub use node::*;
-use tracing::info;
+pub use region::*;
+use tracing::{info, instrument, warn};
 
 const DEFAULT_NAMESPACE: &str = "hydra-doom";
+// How often claiming a node is retried when other replicas keep changing it.
+const CLAIM_ATTEMPTS: usize = 3;
+// Claims stop counting after this long, in case the game they were made for was never created.
+// This comfortably covers the time it takes for a new game to show up in the node's status.
+const CLAIM_TTL: Duration = Duration::from_secs(30);
+// How often connecting to a member cluster is retried after it failed.
+const CLUSTER_RETRY_INTERVAL: Duration = Duration::from_secs(30);
+// Games are no longer counted against their creator after this long, even if the head still
+// reports open games.
+const MAX_GAME_DURATION: Duration = Duration::from_secs(30 * 60);
 
 fn define_namespace() -> String {
     std::env::var("KUBERNETES_NAMESPACE").unwrap_or_else(|_| DEFAULT_NAMESPACE.to_string())
 }
 
-#[allow(dead_code)]
+/// Identifies this replica in the claims it makes, the pod name when running in the cluster.
+fn replica_name() -> String {
+    std::env::var("HOSTNAME").unwrap_or_else(|_| "rpc".to_string())
+}
+
+fn random_hex() -> String {
+    let mut bytes = [0u8; 8];
+    thread_rng().fill_bytes(&mut bytes);
+
+    hex::encode(bytes)
+}
+
+/// Whether the node is ready for another game, counting the games other replicas are creating on
+/// it right now.
+fn has_room_for_game(node: &HydraDoomNode) -> bool {
+    if node.spec.draining.unwrap_or(false) {
+        return false;
+    }
+
+    node.status.as_ref().is_some_and(|status| {
+        let max_games = node.spec.max_games.unwrap_or(1);
+        status.node_state == "HeadIsOpen"
+            && status.open_games + (node.live_claims().len() as i64) < max_games as i64
+            && (max_games > 1 || status.game_state == "Waiting")
+    })
+}
+
+/// The claims on the node along with `claim`, or `None` when the node has no room for it, e.g.
+/// because another replica claimed it since it was last read.
+fn claims_with(node: &HydraDoomNode, claim: &NodeClaim) -> Option<Vec<NodeClaim>> {
+    if !has_room_for_game(node) {
+        return None;
+    }
+
+    let mut claims = node.live_claims();
+    claims.push(claim.clone());
+    Some(claims)
+}
+
+/// Replaces the claims on the node, as long as it didn't change since it was read.
+async fn patch_claims(
+    api: &kube::Api<HydraDoomNode>,
+    node: &HydraDoomNode,
+    claims: &[NodeClaim],
+) -> kube::Result<()> {
+    let mut annotations = serde_json::Map::new();
+    annotations.insert(
+        CLAIMS_ANNOTATION.to_string(),
+        Value::String(serde_json::to_string(claims).expect("claims are serializable")),
+    );
+    api.patch(
+        node.metadata.name.as_deref().unwrap_or_default(),
+        &PatchParams::default(),
+        &Patch::Merge(serde_json::json!({
+            "metadata": {
+                "resourceVersion": node.metadata.resource_version,
+                "annotations": annotations,
+            }
+        })),
+    )
+    .await?;
+
+    Ok(())
+}
+
+/// Keeps trying to connect to a member cluster that couldn't be reached at startup.
+async fn connect_later(
+    clusters: Arc<Vec<OnceLock<MemberCluster>>>,
+    index: usize,
+    config: ClusterConfig,
+    regions: Regions,
+    events: Arc<EventHub>,
+) {
+    loop {
+        tokio::time::sleep(CLUSTER_RETRY_INTERVAL).await;
+        match MemberCluster::try_new(config.clone(), regions.clone(), events.clone()).await {
+            Ok(cluster) => {
+                info!(cluster = config.name, "connected to cluster");
+                let _ = clusters[index].set(cluster);
+                return;
+            }
+            Err(err) => warn!(
+                cluster = config.name,
+                "failed to connect to cluster: {:#}", err
+            ),
+        }
+    }
+}
+
+struct TrackedGame {
+    address: String,
+    node_id: String,
+    created_at: Instant,
+}
+
 #[derive(Clone)]
 pub struct ClusterState {
-    recently_claimed: Arc<Mutex<HashMap<String, bool>>>,
-    store: kube::runtime::reflector::Store<HydraDoomNode>,
-    watcher_handle: Arc<tokio::task::JoinHandle<()>>,
+    games: Arc<Mutex<Vec<TrackedGame>>>,
+    // Member clusters that couldn't be reached yet stay empty until a retry connects to them
+    clusters: Arc<Vec<OnceLock<MemberCluster>>>,
+    regions: Regions,
+    pub events: Arc<EventHub>,
     pub admin_sk: SecretKey,
     pub remote: bool,
     pub network: Network,
 }
 
 impl ClusterState {
+    /// Watches every cluster in `clusters`, or only the local one when none are configured. Node
+    /// names must be unique across clusters, which the region prefix of the autoscaler ensures.
+    ///
+    /// Only failing to reach the cluster this process runs in is an error, the other clusters
+    /// are connected to in the background until they can be reached.
     pub async fn try_new(
         admin_key_file: &str,
         remote: bool,
         network: Network,
+        regions: Regions,
+        clusters: Vec<ClusterConfig>,
     ) -> anyhow::Result<Self> {
         let admin_key_envelope: KeyEnvelope = serde_json::from_reader(
             File::open(admin_key_file).context("unable to open key file")?,
@@ -51,92 +177,377 @@ impl ClusterState {
             .try_into()
             .context("Failed to get secret key from file")?;
 
-        let namespace = define_namespace();
-        info!(namespace, "running inside namespace");
+        let clusters = if clusters.is_empty() {
+            vec![MemberCluster::local(&regions.local)]
+        } else {
+            clusters
+        };
 
-        let client = kube::Client::try_default().await?;
-        let nodes: kube::Api<crd::HydraDoomNode> = kube::Api::namespaced(client, &namespace);
+        let events = Arc::new(EventHub::new(admin_sk.public_key().compute_hash(), network));
+        let connected =
+            join_all(clusters.iter().map(|config| {
+                MemberCluster::try_new(config.clone(), regions.clone(), events.clone())
+            }))
+            .await;
 
-        let (store, writer) = kube::runtime::reflector::store();
-
-        // Create the infinite reflector stream
-        let rf = kube::runtime::reflector(
-            writer,
-            kube::runtime::watcher(nodes, kube::runtime::watcher::Config::default()),
-        );
-
-        let claims_ = Arc::new(Mutex::new(HashMap::new()));
-        let claims = claims_.clone();
-
-        let watcher_handle = tokio::spawn(async move {
-            let infinite_watch = rf.applied_objects().for_each(|node| {
-                if let Ok(node) = node {
-                    if node
-                        .status
-                        .as_ref()
-                        .is_some_and(|n| n.game_state != "Waiting")
-                    {
-                        let id = node.metadata.name.as_ref().unwrap();
-                        let mut claims = claims.lock().unwrap();
-                        if claims.remove(id).is_some() {
-                            info!(namespace, "node {} is now available", id);
-                        }
-                    }
+        let mut slots = Vec::with_capacity(clusters.len());
+        let mut pending = Vec::new();
+        for (config, connected) in clusters.into_iter().zip(connected) {
+            let slot = OnceLock::new();
+            match connected {
+                Ok(cluster) => {
+                    let _ = slot.set(cluster);
                 }
-                ready(())
-            });
-            infinite_watch.await;
-        });
+                Err(err) if config.in_cluster => {
+                    return Err(
+                        err.context(format!("failed to connect to cluster {}", config.name))
+                    );
+                }
+                Err(err) => {
+                    warn!(
+                        cluster = config.name,
+                        "failed to connect to cluster: {:#}", err
+                    );
+                    pending.push((slots.len(), config));
+                }
+            }
+            slots.push(slot);
+        }
+        let clusters = Arc::new(slots);
+
+        for (index, config) in pending {
+            tokio::spawn(connect_later(
+                clusters.clone(),
+                index,
+                config,
+                regions.clone(),
+                events.clone(),
+            ));
+        }
 
         Ok(Self {
-            recently_claimed: claims_.clone(),
-            store,
-            watcher_handle: Arc::new(watcher_handle),
+            games: Arc::new(Mutex::new(Vec::new())),
+            clusters,
+            regions,
+            events,
             admin_sk,
             remote,
             network,
         })
     }
 
-    pub fn select_node_for_new_game(&self) -> anyhow::Result<Arc<HydraDoomNode>> {
-        let mut claimed = self.recently_claimed.lock().unwrap();
-        let node = self
-            .store
-            .state()
-            .iter()
+    /// The member clusters that could be connected to so far.
+    fn connected(&self) -> impl Iterator<Item = &MemberCluster> {
+        self.clusters.iter().filter_map(OnceLock::get)
+    }
+
+    fn cluster_of(&self, id: &str) -> Option<(&MemberCluster, Arc<HydraDoomNode>)> {
+        self.connected()
+            .find_map(|cluster| cluster.get(id).map(|node| (cluster, node)))
+    }
+
+    fn cluster_of_node(&self, node: &HydraDoomNode) -> Option<&MemberCluster> {
+        self.cluster_of(node.metadata.name.as_deref().unwrap_or_default())
+            .map(|(cluster, _)| cluster)
+    }
+
+    pub fn region_of(&self, node: &HydraDoomNode) -> &str {
+        self.regions
+            .region_of(node.metadata.name.as_deref().unwrap_or_default())
+            .or_else(|| {
+                self.cluster_of_node(node)
+                    .map(|cluster| cluster.region.as_str())
+            })
+            .unwrap_or(self.regions.local.as_str())
+    }
+
+    /// How to reach the node's hydra websocket. Nodes in other clusters are only reachable
+    /// through their external url.
+    pub fn connection_info(&self, node: &HydraDoomNode) -> anyhow::Result<ConnectionInfo> {
+        let status = node.status.as_ref().context("node has no status yet")?;
+        let (local, remote) = ConnectionInfo::from_resource(status)?;
+        let in_cluster = self
+            .cluster_of_node(node)
+            .is_some_and(|cluster| cluster.in_cluster);
+
+        Ok(if self.remote || !in_cluster {
+            remote
+        } else {
+            local
+        })
+    }
+
+    /// Calls an endpoint of the metrics exporter running next to the node, e.g.
+    /// `game/new_game?...` built with `exporter_path`, and returns the response body. The exporters of nodes in other
+    /// clusters are reached through the service proxy of their cluster's API server.
+    #[instrument(
+        skip_all,
+        fields(node = node.metadata.name.as_deref().unwrap_or_default(), path = path)
+    )]
+    pub async fn call_exporter(
+        &self,
+        node: &HydraDoomNode,
+        method: Method,
+        path: &str,
+    ) -> ApiResult<Vec<u8>> {
+        let id = node.metadata.name.as_deref().unwrap_or_default();
+        let cluster = self
+            .cluster_of_node(node)
+            .ok_or_else(|| ApiError::node_not_found(id))?;
+
+        let (status, body) = if cluster.in_cluster {
+            let local_url = node
+                .status
+                .as_ref()
+                .map(|status| {
+                    status
+                        .local_url
+                        .clone()
+                        .replace("ws://", "http://")
+                        .replace("4001", "8000")
+                })
+                .unwrap_or_default();
+            let response = trace_headers()
+                .into_iter()
+                .fold(
+                    reqwest::Client::new().request(method, format!("{local_url}/{path}")),
+                    |request, (name, value)| request.header(name, value),
+                )
+                .send()
+                .await
+                .context("failed to reach the metrics exporter")?;
+
+            (
+                response.status().as_u16(),
+                response
+                    .bytes()
+                    .await
+                    .context("failed to read the metrics exporter response")?
+                    .to_vec(),
+            )
+        } else {
+            let request = trace_headers()
+                .into_iter()
+                .fold(
+                    http::Request::builder()
+                        .method(method.as_str())
+                        .uri(format!(
+                            "/api/v1/namespaces/{}/services/hydra-doom-node-{}:metrics/proxy/{}",
+                            cluster.namespace, id, path
+                        )),
+                    |request, (name, value)| request.header(name, value),
+                )
+                .body(kube::client::Body::empty())
+                .context("invalid exporter request")?;
+            let response = cluster
+                .client
+                .send(request)
+                .await
+                .with_context(|| format!("failed to reach cluster {}", cluster.name))
+                .map_err(|err| ApiError::new(ErrorCode::HeadUnreachable, format!("{:#}", err)))?;
+
+            (
+                response.status().as_u16(),
+                response
+                    .into_body()
+                    .collect_bytes()
+                    .await
+                    .context("failed to read the metrics exporter response")?
+                    .to_vec(),
+            )
+        };
+
+        if (200..300).contains(&status) {
+            Ok(body)
+        } else {
+            Err(ApiError::from_body(status, &body))
+        }
+    }
+
+    /// Picks the available node that scores best for the player's region preference, preferring
+    /// the newest node among equally scored ones, and claims it. Returns the node and the id of the
+    /// claim, which should be released if no game ends up being created on the node.
+    pub async fn select_node_for_new_game(
+        &self,
+        preference: &RegionPreference,
+    ) -> anyhow::Result<(Arc<HydraDoomNode>, String)> {
+        let mut candidates: Vec<_> = self
+            .get_all_nodes()
+            .into_iter()
             .filter(|n| {
-                let id = n.metadata.name.as_ref().unwrap();
-                let recently_claimed = claimed.get(id).unwrap_or(&false);
                 info!(
-                    "checking node {}, recently claimed: {}, status: {}",
-                    id,
-                    recently_claimed,
+                    "checking node {}, claims: {}, status: {}",
+                    n.metadata.name.as_deref().unwrap_or_default(),
+                    n.live_claims().len(),
                     n.status
                         .as_ref()
                         .map(|s| s.game_state.as_str())
                         .unwrap_or("unknown")
                 );
-                if let Some(status) = n.status.as_ref() {
-                    !recently_claimed
-                        && status.node_state == "HeadIsOpen"
-                        && status.game_state == "Waiting"
-                } else {
-                    false
-                }
+                has_room_for_game(n)
             })
-            .max_by_key(|n| n.metadata.creation_timestamp.clone())
-            .cloned()
-            .ok_or(anyhow::anyhow!("no available nodes found"))?;
-        claimed
-            .entry(node.metadata.name.clone().expect("node without a name"))
-            .or_insert(true);
-        Ok(node)
+            .collect();
+        candidates.sort_by_key(|n| {
+            (
+                preference.score(self.region_of(n)),
+                Reverse(n.metadata.creation_timestamp.clone()),
+            )
+        });
+
+        for node in candidates {
+            let id = node.metadata.name.clone().expect("node without a name");
+            let claim_id = match self.claim_node(&node).await {
+                Ok(Some(claim_id)) => claim_id,
+                // Another replica got to it first
+                Ok(None) => continue,
+                Err(err) => {
+                    warn!(id, "failed to claim node: {:#}", err);
+                    continue;
+                }
+            };
+
+            if preference
+                .region
+                .as_deref()
+                .is_some_and(|region| region != self.region_of(&node))
+            {
+                info!(
+                    region = self.region_of(&node),
+                    "no warm node in the requested region, falling back"
+                );
+            }
+            return Ok((node, claim_id));
+        }
+
+        bail!("no available nodes found")
+    }
+
+    /// Adds a claim to the node's annotations, unless it has no room left. The patch is
+    /// conditional on the resource version, so when another replica claims the node at the same
+    /// time one of them fails, re-reads the node and checks again.
+    async fn claim_node(&self, node: &HydraDoomNode) -> anyhow::Result<Option<String>> {
+        let id = node.metadata.name.as_deref().unwrap_or_default();
+        let api = self.node_api(id)?;
+        let now = Utc::now();
+        let claim = NodeClaim {
+            claimed_by: format!("{}-{}", replica_name(), random_hex()),
+            claimed_at: Time(now),
+            expires_at: Time(now + CLAIM_TTL),
+        };
+
+        let mut node = node.clone();
+        for _ in 0..CLAIM_ATTEMPTS {
+            let Some(claims) = claims_with(&node, &claim) else {
+                return Ok(None);
+            };
+            match patch_claims(&api, &node, &claims).await {
+                Ok(()) => return Ok(Some(claim.claimed_by)),
+                Err(kube::Error::Api(err)) if err.code == 409 => {
+                    node = api.get(id).await.context("failed to re-read node")?;
+                }
+                Err(err) => return Err(err).context("failed to claim node"),
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Creates a game on a node claimed with `select_node_for_new_game`, calling the node's
+    /// exporter with `path`. The claim is released if anything goes wrong, it's left to expire
+    /// otherwise, once the game shows up in the node's status.
+    pub async fn create_game_on(
+        &self,
+        node: &HydraDoomNode,
+        claim_id: &str,
+        path: &str,
+    ) -> ApiResult<shared::NewGameLocalResponse> {
+        let node_id = node.metadata.name.as_deref().unwrap_or_default();
+        let created = self
+            .call_exporter(node, Method::GET, path)
+            .await
+            .and_then(|body| {
+                serde_json::from_slice::<shared::NewGameLocalResponse>(&body)
+                    .context("http error")
+                    .map_err(ApiError::from)
+            });
+        if created.is_err() {
+            self.release_claim(node_id, claim_id).await;
+        }
+
+        created
+    }
+
+    /// Removes a claim before it expires, e.g. when the game couldn't be created after all.
+    pub async fn release_claim(&self, node_id: &str, claim_id: &str) {
+        if let Err(err) = self.try_release_claim(node_id, claim_id).await {
+            warn!(node_id, claim_id, "claim left to expire: {:#}", err);
+        }
+    }
+
+    async fn try_release_claim(&self, node_id: &str, claim_id: &str) -> anyhow::Result<()> {
+        let api = self.node_api(node_id)?;
+        for _ in 0..CLAIM_ATTEMPTS {
+            let node = api.get(node_id).await.context("failed to read node")?;
+            let claims: Vec<_> = node
+                .live_claims()
+                .into_iter()
+                .filter(|claim| claim.claimed_by != claim_id)
+                .collect();
+            match patch_claims(&api, &node, &claims).await {
+                Err(kube::Error::Api(err)) if err.code == 409 => continue,
+                result => return result.context("failed to release claim"),
+            }
+        }
+
+        bail!("node kept changing")
+    }
+
+    /// Remembers a game created through this server, see `active_games_for`.
+    pub fn record_game(&self, address: &str, node_id: &str) {
+        self.games.lock().unwrap().push(TrackedGame {
+            address: address.to_string(),
+            node_id: node_id.to_string(),
+            created_at: Instant::now(),
+        });
+    }
+
+    /// Drops the tracked games that are no longer open on their node.
+    fn active_games(&self) -> std::sync::MutexGuard<'_, Vec<TrackedGame>> {
+        let mut games = self.games.lock().unwrap();
+        games.retain(|game| {
+            game.created_at.elapsed() < MAX_GAME_DURATION
+                && self
+                    .get_node_by_id(&game.node_id)
+                    .and_then(|node| node.status.clone())
+                    .is_some_and(|status| {
+                        status.open_games > 0
+                            || status.game_state == "Lobby"
+                            || status.game_state == "Running"
+                    })
+        });
+
+        games
+    }
+
+    /// Counts the games created by `address` that are still open on their node.
+    pub fn active_games_for(&self, address: &str) -> usize {
+        self.active_games()
+            .iter()
+            .filter(|game| game.address == address)
+            .count()
+    }
+
+    /// The games in the head of the node, as tracked by its exporter, whichever replica created
+    /// them.
+    pub async fn games_on(&self, node: &HydraDoomNode) -> ApiResult<Vec<shared::NodeGame>> {
+        let body = self.call_exporter(node, Method::GET, "game/games").await?;
+
+        Ok(serde_json::from_slice(&body).context("invalid games from the metrics exporter")?)
     }
 
     pub fn select_random_node_with_active_game(&self) -> anyhow::Result<Arc<HydraDoomNode>> {
         Ok(self
-            .store
-            .state()
+            .get_all_nodes()
             .iter()
             .filter(|n| {
                 n.status
@@ -149,14 +560,49 @@ impl ClusterState {
             .ok_or(anyhow::anyhow!("no available nodes found"))?)
     }
 
+    /// The nodes of every cluster.
     pub fn get_all_nodes(&self) -> Vec<Arc<crd::HydraDoomNode>> {
-        self.store.state().to_vec()
+        self.connected()
+            .flat_map(|cluster| cluster.store.state())
+            .collect()
     }
 
     pub fn get_node_by_id(&self, id: &str) -> Option<Arc<HydraDoomNode>> {
-        let ns = define_namespace();
-        self.store
-            .get(&ObjectRef::<HydraDoomNode>::new(id).within(&ns))
+        self.cluster_of(id).map(|(_, node)| node)
+    }
+
+    fn node_api(&self, id: &str) -> anyhow::Result<kube::Api<HydraDoomNode>> {
+        let (cluster, _) = self
+            .cluster_of(id)
+            .with_context(|| format!("node {} not found", id))?;
+
+        Ok(kube::Api::namespaced(
+            cluster.client.clone(),
+            &cluster.namespace,
+        ))
+    }
+
+    /// Merges `spec` into the spec of the node's custom resource.
+    pub async fn patch_node_spec(&self, id: &str, spec: Value) -> anyhow::Result<()> {
+        let api = self.node_api(id)?;
+        api.patch(
+            id,
+            &PatchParams::default(),
+            &Patch::Merge(serde_json::json!({ "spec": spec })),
+        )
+        .await
+        .context("failed to patch node")?;
+
+        Ok(())
+    }
+
+    pub async fn delete_node(&self, id: &str) -> anyhow::Result<()> {
+        let api = self.node_api(id)?;
+        api.delete(id, &DeleteParams::default())
+            .await
+            .context("failed to delete node")?;
+
+        Ok(())
     }
 }
 
@@ -165,3 +611,99 @@ impl ClusterState {
 struct Config {
     ttl_minutes: u64,
 }
+
+/// The path of an exporter endpoint for `call_exporter`, with the query parameters
+/// percent-encoded so that values sent by clients can't add parameters of their own.
+pub fn exporter_path(endpoint: &str, query: &[(&str, &str)]) -> String {
+    let mut url = reqwest::Url::parse("http://exporter").expect("valid url");
+    url.set_path(endpoint);
+    url.query_pairs_mut().extend_pairs(query);
+
+    format!(
+        "{}?{}",
+        url.path().trim_start_matches('/'),
+        url.query().unwrap_or_default()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::*;
+
+    fn claim(claimed_by: &str, expires_in: Duration) -> NodeClaim {
+        let now = Utc::now();
+        NodeClaim {
+            claimed_by: claimed_by.to_string(),
+            claimed_at: Time(now),
+            expires_at: Time(now + expires_in),
+        }
+    }
+
+    fn node(max_games: u32, claims: &[NodeClaim]) -> HydraDoomNode {
+        let mut node = HydraDoomNode::new(
+            "node1",
+            HydraDoomNodeSpec {
+                offline: None,
+                network_id: None,
+                snapshot: None,
+                start_chain_from: None,
+                asleep: None,
+                resources: None,
+                max_games: Some(max_games),
+                draining: None,
+            },
+        );
+        node.metadata.annotations = Some(BTreeMap::from([(
+            CLAIMS_ANNOTATION.to_string(),
+            serde_json::to_string(claims).unwrap(),
+        )]));
+        node.status = Some(HydraDoomNodeStatus {
+            node_state: "HeadIsOpen".to_string(),
+            game_state: "Waiting".to_string(),
+            ..Default::default()
+        });
+        node
+    }
+
+    #[test]
+    fn test_expired_claims_dont_count() {
+        let expired = claim("replica-a", Duration::ZERO);
+        let live = claim("replica-b", CLAIM_TTL);
+        let claimed = node(1, &[expired.clone(), live.clone()]);
+        assert_eq!(claimed.live_claims(), vec![live]);
+        assert!(!has_room_for_game(&claimed));
+
+        let unclaimed = node(1, &[expired]);
+        assert!(unclaimed.live_claims().is_empty());
+        assert!(has_room_for_game(&unclaimed));
+    }
+
+    #[test]
+    fn test_conflicting_claims() {
+        let mine = claim("replica-a", CLAIM_TTL);
+        let theirs = claim("replica-b", CLAIM_TTL);
+
+        // The node as first read had room, the claim is added to the live ones
+        let claims = claims_with(&node(2, &[theirs.clone()]), &mine).expect("room left");
+        assert_eq!(claims, vec![theirs.clone(), mine.clone()]);
+
+        // Re-read after a conflicting patch, the other replica took the last seat
+        assert!(claims_with(&node(1, &[theirs]), &mine).is_none());
+        // Unless its claim expired in the meantime
+        let expired = claim("replica-b", Duration::ZERO);
+        assert_eq!(claims_with(&node(1, &[expired]), &mine), Some(vec![mine]));
+    }
+
+    #[test]
+    fn test_exporter_path() {
+        assert_eq!(
+            exporter_path(
+                "game/add_player",
+                &[("game_id", "abcd&address=victim"), ("address", "addr1")]
+            ),
+            "game/add_player?game_id=abcd%26address%3Dvictim&address=addr1"
+        );
+    }
+}
@@ -1,11 +1,12 @@
 use anyhow::{Context, Result};
 use hex::FromHex;
 use pallas::{crypto::key::ed25519::SecretKey, ledger::addresses::Network};
+use prometheus::Histogram;
 use reqwest::Url;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use std::{collections::HashMap, time::Duration};
-use tracing::debug;
+use tracing::{debug, info_span, instrument, trace};
 
 use crate::model::{
     game::player::Player,
@@ -25,6 +26,9 @@ pub struct NodeClient {
 
     #[serde(skip)]
     pub tx_builder: TxBuilder,
+
+    #[serde(skip)]
+    confirmation_latency: Option<Histogram>,
 }
 
 #[derive(Clone, Serialize, Debug)]
@@ -65,50 +69,71 @@ impl NodeClient {
         Self {
             connection,
             tx_builder: TxBuilder::new(admin_key, network),
+            confirmation_latency: None,
         }
     }
 
+    pub fn with_stake(mut self, stake: u64) -> Self {
+        self.tx_builder = self.tx_builder.with_stake(stake);
+
+        self
+    }
+
+    /// Observes how long the head takes to confirm each submitted transaction.
+    pub fn with_confirmation_latency(mut self, histogram: Histogram) -> Self {
+        self.confirmation_latency = Some(histogram);
+
+        self
+    }
+
+    #[instrument(skip_all, fields(tx_id = tx.transaction.tx_id))]
+    async fn submit(&self, tx: NewTx, timeout: Duration) -> Result<()> {
+        let latency =
+            hydra_socket::submit_tx_roundtrip(&self.connection.to_websocket_url(), tx, timeout)
+                .await?;
+        if let Some(histogram) = &self.confirmation_latency {
+            histogram.observe(latency.as_secs_f64());
+        }
+
+        Ok(())
+    }
+
+    #[instrument(skip(self, player))]
     pub async fn new_game(
         &self,
         player: Player,
         player_count: u64,
         bot_count: u64,
-    ) -> Result<Vec<u8>> {
+    ) -> Result<(String, Vec<u8>)> {
         let utxos = self.fetch_utxos().await.context("failed to fetch UTxOs")?;
-        // Removing for now, to make iterative development easier
-        // if utxos
-        //     .iter()
-        //     .any(|utxo| GameState::try_from(utxo.datum.clone()).is_ok())
-        // {
-        //     bail!("game UTxO already exists")
-        // }
-
-        let new_game_tx = self
-            .tx_builder
-            .new_game(player, utxos, player_count, bot_count)
+
+        let (game_id, new_game_tx) = info_span!("build_tx")
+            .in_scope(|| {
+                self.tx_builder
+                    .new_game(player, utxos, player_count, bot_count)
+            })
             .context("failed to build transaction")?; // TODO: pass in network
         debug!("new game tx: {}", hex::encode(&new_game_tx.tx_bytes));
 
         let tx_hash = new_game_tx.tx_hash.0.to_vec();
         let newtx = NewTx::new(new_game_tx).context("failed to build new tx message")?;
 
-        hydra_socket::submit_tx_roundtrip(
-            &self.connection.to_websocket_url(),
+        self.submit(
             newtx,
             // TODO: make this configurable
             Duration::from_secs(10),
         )
         .await?;
 
-        Ok(tx_hash)
+        Ok((game_id, tx_hash))
     }
 
-    pub async fn start_game(&self) -> Result<Vec<u8>> {
+    #[instrument(skip(self))]
+    pub async fn start_game(&self, game_id: &str) -> Result<Vec<u8>> {
         let utxos = self.fetch_utxos().await.context("failed to fetch UTxOs")?;
 
-        let start_game_tx = self
-            .tx_builder
-            .start_game(utxos)
+        let start_game_tx = info_span!("build_tx")
+            .in_scope(|| self.tx_builder.start_game(game_id, utxos))
             .context("failed to build transaction")?;
 
         debug!("start game tx: {}", hex::encode(&start_game_tx.tx_bytes));
@@ -116,8 +141,7 @@ impl NodeClient {
         let tx_hash = start_game_tx.tx_hash.0.to_vec();
 
         let new_tx = NewTx::new(start_game_tx).context("failed to build NewTx message")?;
-        hydra_socket::submit_tx_roundtrip(
-            &self.connection.to_websocket_url(),
+        self.submit(
             new_tx, // TODO: make this configurable
             Duration::from_secs(30),
         )
@@ -127,12 +151,12 @@ impl NodeClient {
         Ok(tx_hash)
     }
 
-    pub async fn add_player(&self, player: Player) -> Result<Vec<u8>> {
+    #[instrument(skip(self, player))]
+    pub async fn add_player(&self, game_id: &str, player: Player) -> Result<Vec<u8>> {
         let utxos = self.fetch_utxos().await.context("failed to fetch UTxOs")?;
 
-        let add_player_tx = self
-            .tx_builder
-            .add_player(player, utxos)
+        let add_player_tx = info_span!("build_tx")
+            .in_scope(|| self.tx_builder.add_player(game_id, player, utxos))
             .context("failed to build transaction")?;
 
         debug!("add player tx: {}", hex::encode(&add_player_tx.tx_bytes));
@@ -141,8 +165,7 @@ impl NodeClient {
 
         let newtx = NewTx::new(add_player_tx).context("failed to construct newtx message")?;
 
-        hydra_socket::submit_tx_roundtrip(
-            &self.connection.to_websocket_url(),
+        self.submit(
             newtx,
             // TODO: make this configurable
             Duration::from_secs(30),
@@ -152,12 +175,12 @@ impl NodeClient {
         Ok(tx_hash)
     }
 
-    pub async fn cleanup_game(&self) -> Result<Vec<u8>> {
+    #[instrument(skip(self))]
+    pub async fn cleanup_game(&self, game_id: &str) -> Result<Vec<u8>> {
         let utxos = self.fetch_utxos().await.context("failed to fetch UTxOs")?;
 
-        let cleanup_tx = self
-            .tx_builder
-            .cleanup_game(utxos)
+        let cleanup_tx = info_span!("build_tx")
+            .in_scope(|| self.tx_builder.cleanup_game(game_id, utxos))
             .context("failed to build transaction")?;
 
         debug!("cleanup tx: {}", hex::encode(&cleanup_tx.tx_bytes));
@@ -165,8 +188,7 @@ impl NodeClient {
         let tx_hash = cleanup_tx.tx_hash.0.to_vec();
 
         let newtx = NewTx::new(cleanup_tx).context("failed to construct newtx message")?;
-        hydra_socket::submit_tx_roundtrip(
-            &self.connection.to_websocket_url(),
+        self.submit(
             newtx,
             // TODO: make this configurable
             Duration::from_secs(10),
@@ -176,14 +198,17 @@ impl NodeClient {
         Ok(tx_hash)
     }
 
-    // This is just used for testing for now, always aborting the game
-    // TODO: actually handle winner and losers
-    pub async fn end_game(&self) -> Result<Vec<u8>> {
+    // See `TxBuilder::end_game` for the meaning of `is_player_cheater`
+    #[instrument(skip(self, is_player_cheater))]
+    pub async fn end_game(
+        &self,
+        game_id: &str,
+        is_player_cheater: Option<(Player, bool)>,
+    ) -> Result<Vec<u8>> {
         let utxos = self.fetch_utxos().await.context("failed to fetch UTxOs")?;
 
-        let end_game_tx = self
-            .tx_builder
-            .end_game(None, utxos)
+        let end_game_tx = info_span!("build_tx")
+            .in_scope(|| self.tx_builder.end_game(game_id, is_player_cheater, utxos))
             .context("failed to build transaction")?;
 
         debug!("end_game_tx tx: {}", hex::encode(&end_game_tx.tx_bytes));
@@ -191,8 +216,7 @@ impl NodeClient {
         let tx_hash = end_game_tx.tx_hash.0.to_vec();
 
         let newtx = NewTx::new(end_game_tx).context("failed to construct newtx message")?;
-        hydra_socket::submit_tx_roundtrip(
-            &self.connection.to_websocket_url(),
+        self.submit(
             newtx,
             // TODO: make this configurable
             Duration::from_secs(10),
@@ -202,9 +226,10 @@ impl NodeClient {
         Ok(tx_hash)
     }
 
+    #[instrument(skip_all)]
     pub async fn fetch_utxos(&self) -> Result<Vec<UTxO>> {
         let request_url = self.connection.to_http_url() + "/snapshot/utxo";
-        println!("Getting UTxos from: {:?}", request_url);
+        debug!("fetching UTxOs from {}", request_url);
         let response = reqwest::get(&request_url).await.context("http error")?;
 
         let body = response
@@ -212,12 +237,10 @@ impl NodeClient {
             .await
             .context("http error")?;
 
-        println!("fetched utxos and converted to hashmap");
-
         let utxos = body
             .iter()
             .map(|(key, value)| {
-                println!("Trying to build UTxO from Value: {:?}", value);
+                trace!("decoding UTxO from {:?}", value);
                 UTxO::try_from_value(key, value)
             })
             .collect::<Result<Vec<UTxO>>>()
@@ -252,11 +275,7 @@ impl ConnectionInfo {
         let secure = url.scheme() == "https" || url.scheme() == "wss";
         let port = url.port().unwrap_or(if secure { 443 } else { 80 }) as u32;
 
-        Ok(ConnectionInfo {
-            host,
-            secure,
-            port,
-        })
+        Ok(ConnectionInfo { host, secure, port })
     }
 
     pub fn to_websocket_url(&self) -> String {
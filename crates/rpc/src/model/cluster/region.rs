@@ -0,0 +1,102 @@
+use std::collections::HashMap;
+
+use serde::Deserialize;
+
+// Latency assumed for regions the client didn't measure.
+const UNMEASURED_LATENCY_MS: u32 = 250;
+// Added to heads outside the requested region, so they're only picked when it has no warm heads.
+const OTHER_REGION_PENALTY_MS: u32 = 1000;
+
+/// Maps heads to the region they run in. The autoscaler prefixes the names of the heads it
+/// deploys with a per-region prefix, heads without a known prefix belong to the region of their
+/// cluster. `local` is the region of the cluster this server runs in.
+#[derive(Deserialize, Clone, Debug)]
+pub struct Regions {
+    pub local: String,
+    #[serde(default)]
+    pub prefixes: HashMap<String, String>,
+}
+
+impl Default for Regions {
+    fn default() -> Self {
+        Self {
+            local: "us-east-2".to_string(),
+            prefixes: HashMap::new(),
+        }
+    }
+}
+
+impl Regions {
+    pub fn region_of(&self, node_name: &str) -> Option<&str> {
+        self.prefixes
+            .iter()
+            .filter(|(prefix, _)| node_name.starts_with(prefix.as_str()))
+            .max_by_key(|(prefix, _)| prefix.len())
+            .map(|(_, region)| region.as_str())
+    }
+}
+
+/// Where a player would like to play: an explicit region, latencies they measured to each region
+/// in milliseconds, or both.
+#[derive(Clone, Debug, Default)]
+pub struct RegionPreference {
+    pub region: Option<String>,
+    pub latencies: HashMap<String, u32>,
+}
+
+impl RegionPreference {
+    /// Ranks a head in `region` for this player, lower is better. Heads outside the requested
+    /// region still get a score, so that a region without warm heads falls back to the next best.
+    pub fn score(&self, region: &str) -> u32 {
+        let latency = self.latencies.get(region).copied();
+        match &self.region {
+            Some(preferred) if preferred != region => latency
+                .unwrap_or(UNMEASURED_LATENCY_MS)
+                .saturating_add(OTHER_REGION_PENALTY_MS),
+            Some(_) => latency.unwrap_or(0),
+            None => latency.unwrap_or(UNMEASURED_LATENCY_MS),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_region_of() {
+        let regions = Regions {
+            local: "us-east-1".to_string(),
+            prefixes: HashMap::from([
+                ("euc".to_string(), "eu-central-1".to_string()),
+                ("euc2".to_string(), "eu-central-2".to_string()),
+            ]),
+        };
+
+        assert_eq!(regions.region_of("euc1quietfrog"), Some("eu-central-1"));
+        assert_eq!(regions.region_of("euc21quietfrog"), Some("eu-central-2"));
+        assert_eq!(regions.region_of("1quietfrog"), None);
+    }
+
+    #[test]
+    fn test_score() {
+        let preference = RegionPreference {
+            region: Some("eu-central-1".to_string()),
+            latencies: HashMap::from([
+                ("us-east-1".to_string(), 90),
+                ("sa-east-1".to_string(), 200),
+            ]),
+        };
+        assert!(preference.score("eu-central-1") < preference.score("us-east-1"));
+        // Falls back to the closest of the other regions
+        assert!(preference.score("us-east-1") < preference.score("sa-east-1"));
+        assert!(preference.score("sa-east-1") < preference.score("af-south-1"));
+
+        let latencies_only = RegionPreference {
+            region: None,
+            ..preference
+        };
+        assert!(latencies_only.score("us-east-1") < latencies_only.score("eu-central-1"));
+        assert_eq!(RegionPreference::default().score("us-east-1"), 250);
+    }
+}
@@ -1,13 +1,47 @@
+use std::collections::HashMap;
+
 use serde::{Deserialize, Serialize};
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct NewGameLocalResponse {
+    pub game_id: String,
     pub player_state: String,
     pub admin_pkh: String,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct AddPlayerLocalResponse {
+    pub game_id: String,
     pub player_state: String,
     pub admin_pkh: String,
 }
+
+/// A game held by the head of a node, as tracked by the exporter of the node.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct NodeGame {
+    pub game_id: String,
+    /// `Lobby`, `Running`, `Cheated`, `Finished` or `Aborted`.
+    pub state: String,
+    pub players: u64,
+    pub player_count: u64,
+    pub bot_count: u64,
+    /// Seconds until the lobby timer starts the game, once the lobby showed up in a snapshot.
+    #[serde(default)]
+    pub starts_in: Option<u64>,
+}
+
+/// A game that reached a terminal state, as reported by the exporter of its node. Players are
+/// identified by the hex encoded hash of their payment key.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct GameResult {
+    pub game_id: String,
+    /// `Finished`, `Cheated` or `Aborted`.
+    pub outcome: String,
+    pub players: Vec<String>,
+    pub winner: Option<String>,
+    /// Kills of the players the game server reported kills for.
+    #[serde(default)]
+    pub kills: HashMap<String, u64>,
+    /// Unix timestamp, in seconds.
+    pub finished_at: u64,
+}
@@ -0,0 +1,387 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use anyhow::Context;
+use async_tungstenite::tokio::connect_async;
+use futures_util::StreamExt;
+use pallas::{crypto::hash::Hash, ledger::addresses::Network};
+use serde::Serialize;
+use tokio::{sync::broadcast, task::JoinHandle};
+use tracing::{info, warn};
+
+use super::{
+    cluster::HydraDoomNode,
+    game::contract::{
+        game_state::{GameState, State},
+        game_token::GameToken,
+        validator::Validator,
+    },
+    hydra::{
+        hydra_message::{HydraEventMessage, HydraMessage},
+        utxo::UTxO,
+    },
+};
+
+// Slow subscribers skip the events they fell this far behind on.
+const CHANNEL_CAPACITY: usize = 1024;
+// How often the transaction throughput of a head is reported.
+const THROUGHPUT_INTERVAL: Duration = Duration::from_secs(5);
+// Delay before reconnecting to a head whose socket closed.
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+/// The parts of a node's state clients display, pushed whenever one of them changes.
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct HeadSummary {
+    pub id: String,
+    pub region: String,
+    pub node_state: String,
+    pub game_state: String,
+    pub open_games: i64,
+    pub draining: bool,
+}
+
+impl HeadSummary {
+    pub fn new(node: &HydraDoomNode, region: &str) -> Self {
+        let status = node.status.as_ref();
+        Self {
+            id: node.metadata.name.clone().unwrap_or_default(),
+            region: region.to_string(),
+            node_state: status
+                .map(|status| status.node_state.clone())
+                .unwrap_or_default(),
+            game_state: status
+                .map(|status| status.game_state.clone())
+                .unwrap_or_default(),
+            open_games: status.map(|status| status.open_games).unwrap_or_default(),
+            draining: node.spec.draining.unwrap_or(false),
+        }
+    }
+}
+
+#[derive(Serialize, Clone, Debug, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum EventKind {
+    HeadUpdated {
+        head: HeadSummary,
+    },
+    HeadRemoved,
+    PlayerJoined {
+        game_id: String,
+        players: u64,
+        player_count: u64,
+    },
+    GameStarted {
+        game_id: String,
+    },
+    GameEnded {
+        game_id: String,
+        outcome: String,
+    },
+    Throughput {
+        transactions: u64,
+        transactions_per_second: f64,
+    },
+}
+
+impl EventKind {
+    /// Whether the event is relayed from the head itself, rather than from its custom resource.
+    pub fn is_game_event(&self) -> bool {
+        !matches!(self, EventKind::HeadUpdated { .. } | EventKind::HeadRemoved)
+    }
+}
+
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct Event {
+    pub head_id: String,
+    #[serde(flatten)]
+    pub kind: EventKind,
+}
+
+struct Relay {
+    subscribers: usize,
+    handle: JoinHandle<()>,
+}
+
+/// Fans out head and game events to every connected client. Node changes come from the cluster
+/// reflectors, game events are relayed from the hydra socket of the heads clients subscribed to,
+/// for as long as at least one of them is subscribed.
+pub struct EventHub {
+    sender: broadcast::Sender<Event>,
+    relays: Mutex<HashMap<String, Relay>>,
+    admin_pkh: Hash<28>,
+    network: Network,
+}
+
+impl EventHub {
+    pub fn new(admin_pkh: Hash<28>, network: Network) -> Self {
+        Self {
+            sender: broadcast::channel(CHANNEL_CAPACITY).0,
+            relays: Mutex::new(HashMap::new()),
+            admin_pkh,
+            network,
+        }
+    }
+
+    pub fn publish(&self, head_id: &str, kind: EventKind) {
+        // Failing only means nobody is listening right now
+        let _ = self.sender.send(Event {
+            head_id: head_id.to_string(),
+            kind,
+        });
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<Event> {
+        self.sender.subscribe()
+    }
+
+    /// Relays the game events of the head until the returned guard, and those of every other
+    /// subscriber of the head, are dropped. `url` looks up the head's websocket url, again on every
+    /// reconnect, since the head may have moved in the meantime.
+    pub fn watch_head(
+        self: &Arc<Self>,
+        head_id: &str,
+        url: impl Fn() -> anyhow::Result<String> + Send + 'static,
+    ) -> HeadSubscription {
+        let mut relays = self.relays.lock().unwrap();
+        relays
+            .entry(head_id.to_string())
+            .and_modify(|relay| relay.subscribers += 1)
+            .or_insert_with(|| Relay {
+                subscribers: 1,
+                handle: tokio::spawn(relay_head(self.clone(), head_id.to_string(), url)),
+            });
+
+        HeadSubscription {
+            hub: self.clone(),
+            head_id: head_id.to_string(),
+        }
+    }
+
+    fn unwatch_head(&self, head_id: &str) {
+        let mut relays = self.relays.lock().unwrap();
+        if let Some(relay) = relays.get_mut(head_id) {
+            relay.subscribers -= 1;
+            if relay.subscribers == 0 {
+                relay.handle.abort();
+                relays.remove(head_id);
+            }
+        }
+    }
+}
+
+/// Keeps the game events of a head flowing while held.
+pub struct HeadSubscription {
+    hub: Arc<EventHub>,
+    head_id: String,
+}
+
+impl Drop for HeadSubscription {
+    fn drop(&mut self) {
+        self.hub.unwatch_head(&self.head_id);
+    }
+}
+
+/// Turns the game UTxOs of consecutive snapshots into game events.
+#[derive(Default)]
+pub struct GameTracker {
+    games: HashMap<String, (State, u64)>,
+}
+
+impl GameTracker {
+    pub fn observe(&mut self, games: Vec<(String, GameState)>) -> Vec<EventKind> {
+        let mut events = Vec::new();
+        let mut seen = HashMap::new();
+        for (game_id, game_state) in games {
+            let state = game_state.state();
+            let players = game_state.players.len() as u64;
+            let previous = self.games.get(&game_id).copied();
+
+            if previous.map_or(0, |(_, players)| players) < players {
+                events.push(EventKind::PlayerJoined {
+                    game_id: game_id.clone(),
+                    players,
+                    player_count: game_state.player_count(),
+                });
+            }
+            let was = previous.map(|(state, _)| state);
+            if state == State::Running && was != Some(State::Running) {
+                events.push(EventKind::GameStarted {
+                    game_id: game_id.clone(),
+                });
+            }
+            if state.is_terminal() && !was.is_some_and(|was| was.is_terminal()) {
+                events.push(EventKind::GameEnded {
+                    game_id: game_id.clone(),
+                    outcome: format!("{:?}", state),
+                });
+            }
+
+            seen.insert(game_id, (state, players));
+        }
+
+        // Games collected before their end was observed
+        for (game_id, (state, _)) in &self.games {
+            if !seen.contains_key(game_id) && !state.is_terminal() {
+                events.push(EventKind::GameEnded {
+                    game_id: game_id.clone(),
+                    outcome: "Collected".to_string(),
+                });
+            }
+        }
+        self.games = seen;
+
+        events
+    }
+}
+
+fn games_in(utxos: &[UTxO], admin_pkh: Hash<28>, network: Network) -> Vec<(String, GameState)> {
+    let script_address = Validator::address(network);
+    utxos
+        .iter()
+        .filter(|utxo| utxo.address == script_address)
+        .filter_map(|utxo| {
+            let token = GameToken::from_utxo(admin_pkh, utxo)?;
+            let game_state = GameState::try_from(utxo.datum.clone()).ok()?;
+            Some((token.game_id(), game_state))
+        })
+        .collect()
+}
+
+async fn relay_head(hub: Arc<EventHub>, head_id: String, url: impl Fn() -> anyhow::Result<String>) {
+    // Kept across reconnects, so that the games already seen aren't announced again
+    let mut tracker = GameTracker::default();
+    loop {
+        let relayed = match url() {
+            Ok(url) => relay_head_once(&hub, &head_id, &url, &mut tracker).await,
+            Err(err) => Err(err.context("failed to look up the head")),
+        };
+        if let Err(err) = relayed {
+            warn!(head_id, "relaying head events failed: {:#}", err);
+        }
+        tokio::time::sleep(RECONNECT_DELAY).await;
+    }
+}
+
+async fn relay_head_once(
+    hub: &EventHub,
+    head_id: &str,
+    url: &str,
+    tracker: &mut GameTracker,
+) -> anyhow::Result<()> {
+    let (ws_stream, _) = connect_async(url).await.context("failed to connect")?;
+    info!(head_id, "relaying head events");
+    let (_, mut receiver) = ws_stream.split();
+
+    let mut transactions = 0u64;
+    let mut interval_start = Instant::now();
+    let mut interval = tokio::time::interval(THROUGHPUT_INTERVAL);
+    loop {
+        tokio::select! {
+            next = receiver.next() => {
+                let Some(next) = next else {
+                    return Ok(());
+                };
+                let message = match HydraMessage::try_from(next.context("failed to receive")?) {
+                    Ok(HydraMessage::HydraEvent(message)) => message,
+                    _ => continue,
+                };
+                match message {
+                    HydraEventMessage::TxValid(_) => transactions += 1,
+                    HydraEventMessage::SnapshotConfirmed(snapshot) => {
+                        let games = games_in(&snapshot.utxo, hub.admin_pkh, hub.network);
+                        for kind in tracker.observe(games) {
+                            hub.publish(head_id, kind);
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            _ = interval.tick() => {
+                let elapsed = interval_start.elapsed().as_secs_f64();
+                if elapsed > 0.0 {
+                    hub.publish(head_id, EventKind::Throughput {
+                        transactions,
+                        transactions_per_second: transactions as f64 / elapsed,
+                    });
+                }
+                transactions = 0;
+                interval_start = Instant::now();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn player(byte: u8) -> Hash<28> {
+        Hash::new([byte; 28])
+    }
+
+    fn lobby(players: &[u8]) -> GameState {
+        players.iter().fold(
+            GameState::new(player(0).into(), 2, 0),
+            |game_state, byte| game_state.add_player(player(*byte).into()).unwrap(),
+        )
+    }
+
+    #[test]
+    fn test_game_events() {
+        let mut tracker = GameTracker::default();
+
+        assert_eq!(
+            tracker.observe(vec![("game1".to_string(), lobby(&[1]))]),
+            vec![EventKind::PlayerJoined {
+                game_id: "game1".to_string(),
+                players: 1,
+                player_count: 2,
+            }]
+        );
+        // Nothing changed since the last snapshot
+        assert!(tracker
+            .observe(vec![("game1".to_string(), lobby(&[1]))])
+            .is_empty());
+
+        let running = lobby(&[1, 2]).start().unwrap();
+        assert_eq!(
+            tracker.observe(vec![("game1".to_string(), running)]),
+            vec![
+                EventKind::PlayerJoined {
+                    game_id: "game1".to_string(),
+                    players: 2,
+                    player_count: 2,
+                },
+                EventKind::GameStarted {
+                    game_id: "game1".to_string(),
+                },
+            ]
+        );
+
+        assert_eq!(
+            tracker.observe(vec![]),
+            vec![EventKind::GameEnded {
+                game_id: "game1".to_string(),
+                outcome: "Collected".to_string(),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_event_body() {
+        let event = Event {
+            head_id: "head1".to_string(),
+            kind: EventKind::GameStarted {
+                game_id: "game1".to_string(),
+            },
+        };
+
+        assert_eq!(
+            serde_json::to_value(&event).unwrap(),
+            serde_json::json!({ "head_id": "head1", "type": "game_started", "game_id": "game1" })
+        );
+    }
+}
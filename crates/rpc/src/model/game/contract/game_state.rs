@@ -1,3 +1,5 @@
+use std::{error::Error, fmt};
+
 use anyhow::{anyhow, bail, Context};
 use pallas::crypto::hash::Hash;
 use pallas::ledger::{
@@ -11,10 +13,10 @@ use pallas::ledger::{
 use crate::model::game::player::Player;
 use crate::model::hydra::utxo::Datum;
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct PaymentCredential([u8; 28]);
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum State {
     Lobby,
     Running,
@@ -22,6 +24,13 @@ pub enum State {
     Finished,
     Aborted,
 }
+
+impl State {
+    pub fn is_terminal(&self) -> bool {
+        matches!(self, State::Cheated | State::Finished | State::Aborted)
+    }
+}
+
 #[derive(Debug)]
 pub struct GameState {
     referee: PaymentCredential,
@@ -33,6 +42,31 @@ pub struct GameState {
     cheater: Option<PaymentCredential>,
 }
 
+#[derive(Debug, PartialEq, Eq)]
+pub enum GameStateError {
+    LobbyFull,
+    AlreadyJoined,
+    NotAPlayer,
+    NoPlayers,
+    WrongState(State),
+}
+
+impl Error for GameStateError {}
+
+impl fmt::Display for GameStateError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GameStateError::LobbyFull => write!(f, "lobby is full"),
+            GameStateError::AlreadyJoined => write!(f, "player already joined this game"),
+            GameStateError::NotAPlayer => write!(f, "player is not part of this game"),
+            GameStateError::NoPlayers => write!(f, "game has no players"),
+            GameStateError::WrongState(state) => {
+                write!(f, "invalid transition from state {:?}", state)
+            }
+        }
+    }
+}
+
 impl GameState {
     pub fn new(referee: PaymentCredential, player_count: u64, bot_count: u64) -> Self {
         Self {
@@ -46,28 +80,121 @@ impl GameState {
         }
     }
 
-    pub fn add_player(mut self, player: PaymentCredential) -> Self {
+    pub fn state(&self) -> State {
+        self.state
+    }
+
+    pub fn player_count(&self) -> u64 {
+        self.player_count
+    }
+
+    pub fn bot_count(&self) -> u64 {
+        self.bot_count
+    }
+
+    pub fn winner(&self) -> Option<&PaymentCredential> {
+        self.winner.as_ref()
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.players.len() as u64 >= self.player_count
+    }
+
+    pub fn add_player(mut self, player: PaymentCredential) -> Result<Self, GameStateError> {
+        if self.state != State::Lobby {
+            return Err(GameStateError::WrongState(self.state));
+        }
+        if self.players.contains(&player) {
+            return Err(GameStateError::AlreadyJoined);
+        }
+        if self.is_full() {
+            return Err(GameStateError::LobbyFull);
+        }
         self.players.push(player);
 
-        self
+        Ok(self)
     }
 
-    pub fn set_winner(mut self, winner: PaymentCredential) -> Self {
+    pub fn start(mut self) -> Result<Self, GameStateError> {
+        if self.state != State::Lobby {
+            return Err(GameStateError::WrongState(self.state));
+        }
+        if self.players.is_empty() {
+            return Err(GameStateError::NoPlayers);
+        }
+        self.state = State::Running;
+
+        Ok(self)
+    }
+
+    pub fn finish(mut self, winner: PaymentCredential) -> Result<Self, GameStateError> {
+        if self.state != State::Running {
+            return Err(GameStateError::WrongState(self.state));
+        }
+        if !self.players.contains(&winner) {
+            return Err(GameStateError::NotAPlayer);
+        }
+        self.state = State::Finished;
         self.winner = Some(winner);
 
-        self
+        Ok(self)
     }
 
-    pub fn set_cheater(mut self, cheater: PaymentCredential) -> Self {
+    pub fn cheated(mut self, cheater: PaymentCredential) -> Result<Self, GameStateError> {
+        if self.state != State::Running {
+            return Err(GameStateError::WrongState(self.state));
+        }
+        if !self.players.contains(&cheater) {
+            return Err(GameStateError::NotAPlayer);
+        }
+        self.state = State::Cheated;
         self.cheater = Some(cheater);
 
-        self
+        Ok(self)
     }
 
-    pub fn set_state(mut self, state: State) -> Self {
-        self.state = state;
+    pub fn abort(mut self) -> Result<Self, GameStateError> {
+        if self.state.is_terminal() {
+            return Err(GameStateError::WrongState(self.state));
+        }
+        self.state = State::Aborted;
+
+        Ok(self)
+    }
+
+    /// A game can only be collected once it has reached one of the terminal states.
+    pub fn ensure_collectable(&self) -> Result<(), GameStateError> {
+        if !self.state.is_terminal() {
+            return Err(GameStateError::WrongState(self.state));
+        }
+
+        Ok(())
+    }
+
+    /// Splits the pot locked in the game UTxO according to the outcome of the game: the winner
+    /// takes everything, an aborted game refunds every player, and a cheater forfeits their
+    /// share to the other players. Whatever can't be split evenly is left for the admin.
+    pub fn payouts(&self, pot: u64) -> Result<Vec<(PaymentCredential, u64)>, GameStateError> {
+        let recipients: Vec<&PaymentCredential> = match self.state {
+            State::Finished => self.winner.iter().collect(),
+            State::Aborted => self.players.iter().collect(),
+            State::Cheated => self
+                .players
+                .iter()
+                .filter(|player| self.cheater.as_ref() != Some(*player))
+                .collect(),
+            State::Lobby | State::Running => return Err(GameStateError::WrongState(self.state)),
+        };
 
-        self
+        if recipients.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let share = pot / recipients.len() as u64;
+        Ok(recipients
+            .into_iter()
+            .map(|player| (player.clone(), share))
+            .collect())
     }
 }
 
@@ -177,7 +304,7 @@ impl TryFrom<PlutusData> for GameState {
 
                 let winner: Option<PaymentCredential> = match constr.fields[5].clone() {
                     PlutusData::Constr(constr) => {
-                        if Some(0) == constr.any_constructor {
+                        if constructor_index(&constr) == Some(0) {
                             if constr.fields.len() != 1 {
                                 bail!("invalid length for Just type");
                             }
@@ -189,7 +316,7 @@ impl TryFrom<PlutusData> for GameState {
                                 ),
                                 _ => bail!("invalid inner type for Just<PaymentCredential>"),
                             }
-                        } else if Some(1) == constr.any_constructor {
+                        } else if constructor_index(&constr) == Some(1) {
                             None
                         } else {
                             bail!("Invalid constructor for winner");
@@ -200,7 +327,7 @@ impl TryFrom<PlutusData> for GameState {
 
                 let cheater: Option<PaymentCredential> = match constr.fields[6].clone() {
                     PlutusData::Constr(constr) => {
-                        if Some(0) == constr.any_constructor {
+                        if constructor_index(&constr) == Some(0) {
                             if constr.fields.len() != 1 {
                                 bail!("invalid length for Just type");
                             }
@@ -212,7 +339,7 @@ impl TryFrom<PlutusData> for GameState {
                                 ),
                                 _ => bail!("invalid inner type for Just<PaymentCredential>"),
                             }
-                        } else if Some(1) == constr.any_constructor {
+                        } else if constructor_index(&constr) == Some(1) {
                             None
                         } else {
                             bail!("Invalid constructor tag for cheater");
@@ -236,6 +363,12 @@ impl TryFrom<PlutusData> for GameState {
     }
 }
 
+impl fmt::Display for PaymentCredential {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", hex::encode(self.0))
+    }
+}
+
 impl From<PaymentKeyHash> for PaymentCredential {
     fn from(value: PaymentKeyHash) -> Self {
         // We can do this unsafe, because we we know PaymentKeyHash is 28 bytes long
@@ -337,15 +470,164 @@ impl TryFrom<PlutusData> for State {
 
     fn try_from(value: PlutusData) -> Result<Self, Self::Error> {
         match value {
-            PlutusData::Constr(constr) => match constr.tag {
-                121 => Ok(State::Lobby),
-                122 => Ok(State::Running),
-                123 => Ok(State::Cheated),
-                124 => Ok(State::Finished),
-                125 => Ok(State::Aborted),
+            PlutusData::Constr(constr) => match constructor_index(&constr) {
+                Some(0) => Ok(State::Lobby),
+                Some(1) => Ok(State::Running),
+                Some(2) => Ok(State::Cheated),
+                Some(3) => Ok(State::Finished),
+                Some(4) => Ok(State::Aborted),
                 _ => bail!("Invalid constructor tag for State."),
             },
             _ => bail!("Invalid data type for State."),
         }
     }
 }
+
+// Datums decoded from the hydra-node JSON API carry the constructor index in `any_constructor`,
+// while datums decoded from CBOR only carry the compact tag.
+fn constructor_index(constr: &Constr<PlutusData>) -> Option<u64> {
+    constr.any_constructor.or(match constr.tag {
+        121..=127 => Some(constr.tag - 121),
+        _ => None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn credential(byte: u8) -> PaymentCredential {
+        PaymentCredential([byte; 28])
+    }
+
+    #[test]
+    fn test_add_player_validates_lobby() {
+        let game = GameState::new(credential(0), 2, 0)
+            .add_player(credential(1))
+            .expect("failed to add first player");
+
+        assert_eq!(
+            GameStateError::AlreadyJoined,
+            GameState::new(credential(0), 2, 0)
+                .add_player(credential(1))
+                .and_then(|game| game.add_player(credential(1)))
+                .unwrap_err()
+        );
+
+        let game = game
+            .add_player(credential(2))
+            .expect("failed to add second player");
+        assert!(game.is_full());
+        assert_eq!(
+            GameStateError::LobbyFull,
+            game.add_player(credential(3)).unwrap_err()
+        );
+    }
+
+    #[test]
+    fn test_transitions() {
+        let game = GameState::new(credential(0), 2, 1);
+        assert_eq!(GameStateError::NoPlayers, game.start().unwrap_err());
+
+        let game = GameState::new(credential(0), 2, 1)
+            .add_player(credential(1))
+            .and_then(|game| game.start())
+            .expect("failed to start game");
+        assert_eq!(State::Running, game.state());
+
+        let err = game
+            .add_player(credential(2))
+            .expect_err("joined a running game");
+        assert_eq!(GameStateError::WrongState(State::Running), err);
+
+        let game = GameState::new(credential(0), 2, 1)
+            .add_player(credential(1))
+            .and_then(|game| game.start())
+            .expect("failed to start game");
+        assert_eq!(
+            GameStateError::NotAPlayer,
+            GameState::new(credential(0), 2, 1)
+                .add_player(credential(1))
+                .and_then(|game| game.start())
+                .and_then(|game| game.finish(credential(2)))
+                .unwrap_err()
+        );
+        let game = game.finish(credential(1)).expect("failed to finish game");
+        assert_eq!(State::Finished, game.state());
+        assert!(game.ensure_collectable().is_ok());
+        assert_eq!(
+            GameStateError::WrongState(State::Finished),
+            game.abort().unwrap_err()
+        );
+    }
+
+    #[test]
+    fn test_abort_from_lobby() {
+        let game = GameState::new(credential(0), 2, 1);
+        assert_eq!(
+            GameStateError::WrongState(State::Lobby),
+            game.ensure_collectable().unwrap_err()
+        );
+        let game = game.abort().expect("failed to abort game");
+        assert_eq!(State::Aborted, game.state());
+    }
+
+    #[test]
+    fn test_payouts() {
+        let running = || {
+            GameState::new(credential(0), 3, 0)
+                .add_player(credential(1))
+                .and_then(|game| game.add_player(credential(2)))
+                .and_then(|game| game.add_player(credential(3)))
+                .and_then(|game| game.start())
+                .expect("failed to start game")
+        };
+
+        assert_eq!(
+            GameStateError::WrongState(State::Running),
+            running().payouts(30).unwrap_err()
+        );
+
+        let finished = running()
+            .finish(credential(2))
+            .expect("failed to finish game");
+        assert_eq!(
+            vec![(credential(2), 30)],
+            finished.payouts(30).expect("failed to compute payouts")
+        );
+
+        let aborted = running().abort().expect("failed to abort game");
+        assert_eq!(
+            vec![
+                (credential(1), 10),
+                (credential(2), 10),
+                (credential(3), 10)
+            ],
+            aborted.payouts(31).expect("failed to compute payouts")
+        );
+
+        let cheated = running()
+            .cheated(credential(1))
+            .expect("failed to mark cheater");
+        assert_eq!(
+            vec![(credential(2), 15), (credential(3), 15)],
+            cheated.payouts(30).expect("failed to compute payouts")
+        );
+    }
+
+    #[test]
+    fn test_roundtrip_plutus_data() {
+        let game = GameState::new(credential(0), 2, 1)
+            .add_player(credential(1))
+            .and_then(|game| game.start())
+            .expect("failed to start game");
+
+        let data: PlutusData = game.into();
+        let decoded = GameState::try_from(data).expect("failed to decode GameState");
+
+        assert_eq!(State::Running, decoded.state());
+        assert_eq!(vec![credential(1)], decoded.players);
+        assert_eq!(2, decoded.player_count());
+        assert_eq!(1, decoded.bot_count());
+    }
+}
@@ -0,0 +1,181 @@
+use anyhow::{Context, Result};
+use pallas::{
+    codec::minicbor::encode,
+    crypto::hash::{Hash, Hasher},
+    ledger::{
+        primitives::conway::{NativeScript, PlutusData},
+        traverse::ComputeHash,
+    },
+};
+
+use crate::model::hydra::utxo::{Datum, UTxO};
+
+/// Every game UTxO carries a unique token, minted under a native script policy owned by the admin
+/// key. The asset name is derived from the admin UTxO spent to create the game, so it is unique
+/// within the head and doubles as the game id.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GameToken {
+    admin: Hash<28>,
+    pub name: Vec<u8>,
+}
+
+impl GameToken {
+    pub fn from_seed(admin: Hash<28>, seed: &UTxO) -> Self {
+        let mut bytes = seed.hash.clone();
+        bytes.extend(seed.index.to_be_bytes());
+
+        Self {
+            admin,
+            name: Hasher::<256>::hash(&bytes).to_vec(),
+        }
+    }
+
+    pub fn from_game_id(admin: Hash<28>, game_id: &str) -> Result<Self> {
+        Ok(Self {
+            admin,
+            name: hex::decode(game_id).context("invalid game id")?,
+        })
+    }
+
+    /// Whether `game_id` could name a game token, i.e. is a hex encoded 32 byte asset name.
+    pub fn is_game_id(game_id: &str) -> bool {
+        game_id.len() == 64 && game_id.bytes().all(|byte| byte.is_ascii_hexdigit())
+    }
+
+    /// Finds the game token held by a UTxO, if any.
+    pub fn from_utxo(admin: Hash<28>, utxo: &UTxO) -> Option<Self> {
+        let policy_id = hex::encode(Self::policy(admin).compute_hash());
+        utxo.value
+            .iter()
+            .filter(|(_, quantity)| **quantity > 0)
+            .find_map(|(unit, _)| unit.strip_prefix(&policy_id))
+            .and_then(|game_id| Self::from_game_id(admin, game_id).ok())
+    }
+
+    fn policy(admin: Hash<28>) -> NativeScript {
+        NativeScript::ScriptPubkey(admin)
+    }
+
+    pub fn policy_script(&self) -> NativeScript {
+        Self::policy(self.admin)
+    }
+
+    pub fn policy_script_bytes(&self) -> Result<Vec<u8>> {
+        let mut bytes = Vec::new();
+        encode(&self.policy_script(), &mut bytes).context("failed to encode token policy")?;
+
+        Ok(bytes)
+    }
+
+    pub fn policy_id(&self) -> Hash<28> {
+        self.policy_script().compute_hash()
+    }
+
+    pub fn game_id(&self) -> String {
+        hex::encode(&self.name)
+    }
+
+    /// The key this token is stored under in a `UTxO` value.
+    pub fn unit(&self) -> String {
+        format!("{}{}", hex::encode(self.policy_id()), self.game_id())
+    }
+
+    pub fn is_held_by(&self, utxo: &UTxO) -> bool {
+        utxo.value
+            .get(&self.unit())
+            .is_some_and(|quantity| *quantity > 0)
+    }
+
+    /// The inline datum of the player outbound UTxOs created for this game. Players share their
+    /// outbound address across games, so collecting a game only spends the UTxOs tagged with it.
+    pub fn outbound_datum(&self) -> Result<Vec<u8>> {
+        let mut bytes = Vec::new();
+        encode(
+            &PlutusData::BoundedBytes(self.name.clone().into()),
+            &mut bytes,
+        )
+        .context("failed to encode outbound datum")?;
+
+        Ok(bytes)
+    }
+
+    pub fn is_tagged_by(&self, utxo: &UTxO) -> bool {
+        match &utxo.datum {
+            Datum::Inline(PlutusData::BoundedBytes(bytes)) => {
+                Vec::<u8>::from(bytes.clone()) == self.name
+            }
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use pallas::ledger::addresses::Address;
+
+    use super::*;
+
+    fn utxo(index: u64, value: HashMap<String, u64>) -> UTxO {
+        UTxO {
+            hash: vec![0; 32],
+            index,
+            address: Address::from_bech32(
+                "addr_test1vzdjnh24kw99aqj8whfsxu37s0sgmq7yhfeva2egg92t3gsws2hwn",
+            )
+            .expect("Failed to decode address"),
+            datum: Datum::None,
+            reference_script: None,
+            value,
+        }
+    }
+
+    #[test]
+    fn test_game_token_is_unique_per_seed() {
+        let admin: Hash<28> = [1; 28].into();
+        let first = GameToken::from_seed(admin, &utxo(0, HashMap::new()));
+        let second = GameToken::from_seed(admin, &utxo(1, HashMap::new()));
+
+        assert_ne!(first.game_id(), second.game_id());
+        assert_eq!(
+            first,
+            GameToken::from_game_id(admin, &first.game_id()).expect("invalid game id")
+        );
+    }
+
+    #[test]
+    fn test_game_token_from_utxo() {
+        let admin: Hash<28> = [1; 28].into();
+        let token = GameToken::from_seed(admin, &utxo(0, HashMap::new()));
+        let game_utxo = utxo(
+            2,
+            HashMap::from([("lovelace".to_string(), 0), (token.unit(), 1)]),
+        );
+
+        assert!(token.is_held_by(&game_utxo));
+        assert_eq!(Some(token), GameToken::from_utxo(admin, &game_utxo));
+        assert_eq!(
+            None,
+            GameToken::from_utxo(
+                admin,
+                &utxo(3, HashMap::from([("lovelace".to_string(), 0)]))
+            )
+        );
+    }
+
+    #[test]
+    fn test_outbound_utxos_are_tagged_per_game() {
+        let admin: Hash<28> = [1; 28].into();
+        let token = GameToken::from_seed(admin, &utxo(0, HashMap::new()));
+        let other = GameToken::from_seed(admin, &utxo(1, HashMap::new()));
+        let outbound = UTxO {
+            datum: Datum::Inline(PlutusData::BoundedBytes(token.name.clone().into())),
+            ..utxo(2, HashMap::new())
+        };
+
+        assert!(token.is_tagged_by(&outbound));
+        assert!(!other.is_tagged_by(&outbound));
+        assert!(!token.is_tagged_by(&utxo(3, HashMap::new())));
+    }
+}
@@ -1,3 +1,4 @@
 pub mod game_state;
+pub mod game_token;
 pub mod redeemer;
 pub mod validator;
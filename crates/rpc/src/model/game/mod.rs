@@ -1,2 +1,4 @@
 pub mod contract;
 pub mod player;
+pub mod referee;
+pub mod scoreboard;
@@ -0,0 +1,119 @@
+use std::fmt;
+
+use hex::FromHex;
+use pallas::crypto::{
+    hash::Hash,
+    key::ed25519::{PublicKey, Signature},
+};
+use serde::{Deserialize, Serialize};
+
+use super::player::Player;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum CheatReportError {
+    InvalidEncoding(&'static str),
+    InvalidSignature,
+}
+
+impl fmt::Display for CheatReportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CheatReportError::InvalidEncoding(field) => write!(f, "invalid {} encoding", field),
+            CheatReportError::InvalidSignature => write!(f, "invalid referee signature"),
+        }
+    }
+}
+
+impl std::error::Error for CheatReportError {}
+
+/// A cheating verdict issued by the referee. The signature covers the game id, the player's
+/// payment key hash and the evidence hash, concatenated as raw bytes.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CheatReport {
+    pub game_id: String,
+    pub player: String,
+    pub evidence_hash: String,
+    pub signature: String,
+}
+
+impl CheatReport {
+    pub fn message(&self) -> Result<Vec<u8>, CheatReportError> {
+        let mut message =
+            hex::decode(&self.game_id).map_err(|_| CheatReportError::InvalidEncoding("game id"))?;
+        message.extend(self.player_key_hash()?.as_ref());
+        message.extend(
+            <[u8; 32]>::from_hex(&self.evidence_hash)
+                .map_err(|_| CheatReportError::InvalidEncoding("evidence hash"))?,
+        );
+
+        Ok(message)
+    }
+
+    /// Checks the report against the referee key, returning the cheating player.
+    pub fn verify(&self, referee: &PublicKey) -> Result<Player, CheatReportError> {
+        let signature: Signature = <[u8; 64]>::from_hex(&self.signature)
+            .map_err(|_| CheatReportError::InvalidEncoding("signature"))?
+            .into();
+
+        if !referee.verify(self.message()?, &signature) {
+            return Err(CheatReportError::InvalidSignature);
+        }
+
+        Ok(self.player_key_hash()?.into())
+    }
+
+    fn player_key_hash(&self) -> Result<Hash<28>, CheatReportError> {
+        <[u8; 28]>::from_hex(&self.player)
+            .map(Hash::from)
+            .map_err(|_| CheatReportError::InvalidEncoding("player"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pallas::crypto::key::ed25519::SecretKey;
+
+    use super::*;
+
+    fn signed_report(referee: &SecretKey) -> CheatReport {
+        let mut report = CheatReport {
+            game_id: hex::encode([3; 32]),
+            player: hex::encode([1; 28]),
+            evidence_hash: hex::encode([2; 32]),
+            signature: String::new(),
+        };
+        let message = report.message().expect("valid report");
+        report.signature = hex::encode(referee.sign(message).as_ref());
+
+        report
+    }
+
+    #[test]
+    fn test_verify_cheat_report() {
+        let referee: SecretKey = [7; 32].into();
+        let report = signed_report(&referee);
+
+        let player = report
+            .verify(&referee.public_key())
+            .expect("valid signature");
+        assert_eq!(player.signing_key, Hash::from([1; 28]));
+    }
+
+    #[test]
+    fn test_reject_tampered_report() {
+        let referee: SecretKey = [7; 32].into();
+        let mut report = signed_report(&referee);
+        report.player = hex::encode([9; 28]);
+
+        assert_eq!(
+            report.verify(&referee.public_key()).err(),
+            Some(CheatReportError::InvalidSignature)
+        );
+
+        let impostor: SecretKey = [8; 32].into();
+        assert_eq!(
+            signed_report(&impostor).verify(&referee.public_key()).err(),
+            Some(CheatReportError::InvalidSignature)
+        );
+    }
+}
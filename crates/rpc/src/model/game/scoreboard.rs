@@ -0,0 +1,199 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+// Weapons reported under their own label, any other weapon is counted as `other`.
+const WEAPONS: [&str; 9] = [
+    "fist",
+    "chainsaw",
+    "pistol",
+    "shotgun",
+    "super_shotgun",
+    "chaingun",
+    "rocket_launcher",
+    "plasma_rifle",
+    "bfg",
+];
+
+/// What game servers post along with their game events. Players are identified by the hex
+/// encoded hash of their payment key.
+#[derive(Deserialize, Serialize, Clone, Debug, Default)]
+pub struct GameEvent {
+    pub game_id: String,
+    /// The player who joined, left or killed themselves.
+    pub player: Option<String>,
+    pub killer: Option<String>,
+    pub victim: Option<String>,
+    pub weapon: Option<String>,
+    /// When the event happened in the game, as a unix timestamp in milliseconds.
+    pub timestamp: Option<u64>,
+}
+
+impl GameEvent {
+    /// The players the event is about.
+    pub fn players(&self) -> impl Iterator<Item = &String> {
+        [&self.player, &self.killer, &self.victim]
+            .into_iter()
+            .filter_map(Option::as_ref)
+    }
+
+    /// The weapon of a kill, limited to the known weapons so it can be used as a metric label.
+    pub fn weapon_label(&self) -> &str {
+        self.weapon
+            .as_deref()
+            .filter(|weapon| WEAPONS.contains(weapon))
+            .unwrap_or("other")
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum GameEventKind {
+    Joined,
+    Left,
+    Killed,
+    Suicided,
+}
+
+#[derive(Serialize, Clone, Debug, Default, PartialEq)]
+pub struct PlayerScore {
+    pub kills: u64,
+    pub deaths: u64,
+    pub suicides: u64,
+    pub connected: bool,
+}
+
+/// The score of every player of a game, as reported by its game server.
+#[derive(Serialize, Clone, Debug, Default, PartialEq)]
+pub struct Scoreboard {
+    pub game_id: String,
+    pub players: BTreeMap<String, PlayerScore>,
+    /// Timestamp of the latest event, in milliseconds.
+    pub last_event_at: Option<u64>,
+}
+
+impl Scoreboard {
+    pub fn new(game_id: &str) -> Self {
+        Self {
+            game_id: game_id.to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn player(&mut self, player: &str) -> &mut PlayerScore {
+        self.players.entry(player.to_string()).or_default()
+    }
+
+    /// Applies an event to the scores. Returns the players whose score changed.
+    pub fn apply(&mut self, kind: GameEventKind, event: &GameEvent) -> Vec<String> {
+        if let Some(timestamp) = event.timestamp {
+            self.last_event_at = self.last_event_at.max(Some(timestamp));
+        }
+
+        match (kind, &event.player, &event.killer, &event.victim) {
+            (GameEventKind::Joined, Some(player), _, _) => {
+                self.player(player).connected = true;
+                vec![player.clone()]
+            }
+            (GameEventKind::Left, Some(player), _, _) => {
+                self.player(player).connected = false;
+                vec![player.clone()]
+            }
+            (GameEventKind::Suicided, Some(player), _, _) => {
+                let score = self.player(player);
+                score.suicides += 1;
+                score.deaths += 1;
+                vec![player.clone()]
+            }
+            (GameEventKind::Killed, _, killer, victim) => {
+                let mut changed = Vec::new();
+                if let Some(killer) = killer {
+                    self.player(killer).kills += 1;
+                    changed.push(killer.clone());
+                }
+                if let Some(victim) = victim {
+                    self.player(victim).deaths += 1;
+                    changed.push(victim.clone());
+                }
+                changed
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    /// The player with the most kills, net of suicides, with fewer deaths breaking ties. None
+    /// while nobody scored, or when the top players are tied.
+    pub fn leader(&self) -> Option<&str> {
+        let score = |(_, score): &(&String, &PlayerScore)| {
+            (
+                score.kills as i64 - score.suicides as i64,
+                -(score.deaths as i64),
+            )
+        };
+        let mut ranked: Vec<_> = self.players.iter().collect();
+        ranked.sort_by_key(|entry| std::cmp::Reverse(score(entry)));
+
+        match ranked.as_slice() {
+            [first, second, ..] if score(first) == score(second) => None,
+            [first, ..] if first.1.kills > 0 => Some(first.0.as_str()),
+            _ => None,
+        }
+    }
+
+    pub fn kills(&self) -> impl Iterator<Item = (&String, u64)> {
+        self.players
+            .iter()
+            .map(|(player, score)| (player, score.kills))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(player: Option<&str>, killer: Option<&str>, victim: Option<&str>) -> GameEvent {
+        GameEvent {
+            game_id: "game1".to_string(),
+            player: player.map(str::to_string),
+            killer: killer.map(str::to_string),
+            victim: victim.map(str::to_string),
+            weapon: Some("railgun".to_string()),
+            timestamp: None,
+        }
+    }
+
+    #[test]
+    fn test_scoreboard() {
+        let mut scoreboard = Scoreboard::new("game1");
+        scoreboard.apply(GameEventKind::Joined, &event(Some("a"), None, None));
+        scoreboard.apply(GameEventKind::Joined, &event(Some("b"), None, None));
+        assert_eq!(scoreboard.leader(), None);
+
+        assert_eq!(
+            scoreboard.apply(GameEventKind::Killed, &event(None, Some("a"), Some("b"))),
+            vec!["a".to_string(), "b".to_string()]
+        );
+        assert_eq!(scoreboard.leader(), Some("a"));
+
+        // Even on kills
+        scoreboard.apply(GameEventKind::Killed, &event(None, Some("b"), Some("a")));
+        assert_eq!(scoreboard.leader(), None);
+
+        // b's suicide cancels out their extra kill, and a died less
+        scoreboard.apply(GameEventKind::Killed, &event(None, Some("b"), None));
+        scoreboard.apply(GameEventKind::Suicided, &event(Some("b"), None, None));
+        assert_eq!(scoreboard.leader(), Some("a"));
+        scoreboard.apply(GameEventKind::Left, &event(Some("a"), None, None));
+
+        assert_eq!(
+            scoreboard.players["b"],
+            PlayerScore {
+                kills: 2,
+                deaths: 2,
+                suicides: 1,
+                connected: true,
+            }
+        );
+        assert!(!scoreboard.players["a"].connected);
+        assert_eq!(event(None, None, None).weapon_label(), "other");
+    }
+}
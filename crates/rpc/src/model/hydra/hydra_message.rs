@@ -8,7 +8,8 @@ use super::messages::{
     command_failed::CommandFailed, committed::Committed, greetings::Greetings,
     head_is_initializing::HeadIsInitializing, head_is_open::HeadIsOpen,
     invalid_input::InvalidInput, peer_connected::PeerConnected,
-    peer_disconnected::PeerDisconnected, snapshot_confirmed::SnapshotConfirmed, tx_valid::TxValid,
+    peer_disconnected::PeerDisconnected, snapshot_confirmed::SnapshotConfirmed,
+    tx_invalid::TxInvalid, tx_valid::TxValid,
 };
 
 #[derive(Debug)]
@@ -32,6 +33,7 @@ pub enum HydraData {
 pub enum HydraEventMessage {
     SnapshotConfirmed(SnapshotConfirmed),
     TxValid(TxValid),
+    TxInvalid(TxInvalid),
     PeerConnected(PeerConnected),
     PeerDisconnected(PeerDisconnected),
     HeadIsInitializing(HeadIsInitializing),
@@ -53,6 +55,7 @@ impl TryFrom<Value> for HydraEventMessage {
                 SnapshotConfirmed::try_from(value).map(HydraEventMessage::SnapshotConfirmed)
             }
             "TxValid" => TxValid::try_from(value).map(HydraEventMessage::TxValid),
+            "TxInvalid" => TxInvalid::try_from(value).map(HydraEventMessage::TxInvalid),
             "PeerConnected" => PeerConnected::try_from(value).map(HydraEventMessage::PeerConnected),
             "PeerDisconnected" => {
                 PeerDisconnected::try_from(value).map(HydraEventMessage::PeerDisconnected)
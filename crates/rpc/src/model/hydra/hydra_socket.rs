@@ -1,9 +1,10 @@
 use std::{
+    fmt,
     sync::{
-        atomic::{AtomicBool, Ordering},
+        atomic::{AtomicBool, AtomicU64, Ordering},
         Arc,
     },
-    time::Duration,
+    time::{Duration, Instant},
 };
 
 use anyhow::{anyhow, Context, Result};
@@ -23,7 +24,7 @@ use tokio::{
     task::{yield_now, JoinHandle},
 };
 use tokio_native_tls::TlsStream;
-use tracing::{debug, info, warn};
+use tracing::{debug, info, info_span, warn, Instrument};
 
 use crate::model::hydra::hydra_message::HydraEventMessage;
 
@@ -38,10 +39,13 @@ pub struct HydraSocket {
     url: String,
     identifier: String,
     pub online: Arc<AtomicBool>,
+    /// How many times the socket connected again after losing its connection.
+    pub reconnects: Arc<AtomicU64>,
     writer: UnboundedSender<HydraData>,
     sender: Arc<Mutex<Option<HydraSender>>>,
 
     suppress_noise: bool,
+    connected_before: bool,
 }
 
 pub type HydraSource = SplitStream<
@@ -63,10 +67,12 @@ impl HydraSocket {
             url: url.to_string(),
             identifier: identifier.to_string(),
             online: Arc::new(AtomicBool::new(false)),
+            reconnects: Arc::new(AtomicU64::new(0)),
             writer: writer.clone(),
             sender: Arc::new(Mutex::new(None)),
 
             suppress_noise: false,
+            connected_before: false,
         }
     }
 
@@ -109,9 +115,13 @@ impl HydraSocket {
 
     async fn connect_and_listen(&mut self) -> Result<()> {
         let (ws_stream, _) = connect_async(&self.url).await?;
-        println!("Succesfully connected to {}", &self.url);
+        info!("Succesfully connected to {}", &self.url);
         self.suppress_noise = false;
         self.online.store(true, Ordering::SeqCst);
+        if self.connected_before {
+            self.reconnects.fetch_add(1, Ordering::SeqCst);
+        }
+        self.connected_before = true;
         let (sender, receiver) = ws_stream.split();
         {
             let mut sender_lock = self.sender.lock().await;
@@ -196,7 +206,24 @@ pub async fn sample_txs(url: &str, count: usize, timeout: Duration) -> Result<Ve
     }
 }
 
-pub async fn submit_tx_roundtrip(url: &str, tx: NewTx, timeout: Duration) -> Result<()> {
+/// The head rejected a submitted transaction with `TxInvalid`.
+#[derive(Debug)]
+pub struct TxRejected {
+    pub tx_id: String,
+    pub reason: String,
+}
+
+impl std::error::Error for TxRejected {}
+
+impl fmt::Display for TxRejected {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "transaction {} rejected: {}", self.tx_id, self.reason)
+    }
+}
+
+/// Submits a transaction and waits for the head to confirm it. Returns how long the confirmation
+/// took, from sending `NewTx` to receiving `TxValid`.
+pub async fn submit_tx_roundtrip(url: &str, tx: NewTx, timeout: Duration) -> Result<Duration> {
     let request = url.into_client_request().unwrap();
     let (ws_stream, _) = connect_async(request).await.context("failed to connect")?;
 
@@ -205,20 +232,35 @@ pub async fn submit_tx_roundtrip(url: &str, tx: NewTx, timeout: Duration) -> Res
     let (mut sender, mut receiver) = ws_stream.split();
 
     let tx_id = tx.transaction.tx_id.clone();
-    let confirmation = tokio::spawn(async move {
-        loop {
-            let next = receiver.next().await.context("failed to receive")?;
-            let msg = HydraMessage::try_from(next?).context("failed to parse hydra message")?;
+    let confirmation_span = info_span!("confirmation", tx_id = tx_id.as_str());
+    let confirmation = tokio::spawn(
+        async move {
+            loop {
+                let next = receiver.next().await.context("failed to receive")?;
+                let msg = HydraMessage::try_from(next?).context("failed to parse hydra message")?;
 
-            if let HydraMessage::HydraEvent(HydraEventMessage::TxValid(x)) = msg {
-                if x.tx_id == tx_id {
-                    info!("Tx confirmed: {:?}", x);
-                    break anyhow::Result::Ok(());
+                match msg {
+                    HydraMessage::HydraEvent(HydraEventMessage::TxValid(x)) if x.tx_id == tx_id => {
+                        info!("Tx confirmed: {:?}", x);
+                        break anyhow::Result::Ok(());
+                    }
+                    HydraMessage::HydraEvent(HydraEventMessage::TxInvalid(x))
+                        if x.tx_id == tx_id =>
+                    {
+                        break Err(TxRejected {
+                            tx_id,
+                            reason: x.reason,
+                        }
+                        .into());
+                    }
+                    _ => {}
                 }
             }
         }
-    });
+        .instrument(confirmation_span),
+    );
 
+    let sent_at = Instant::now();
     sender
         .send(Message::Text(tx.into()))
         .await
@@ -228,7 +270,7 @@ pub async fn submit_tx_roundtrip(url: &str, tx: NewTx, timeout: Duration) -> Res
         // TODO: result.flatten https://github.com/rust-lang/rust/issues/70142
         join = confirmation => {
             match join {
-                Ok(result) => result,
+                Ok(result) => result.map(|()| sent_at.elapsed()),
                 Err(e) => Err(e.into()),
             }
         }
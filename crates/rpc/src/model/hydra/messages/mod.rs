@@ -1,6 +1,9 @@
 use anyhow::Context;
+use pallas::ledger::traverse::MultiEraTx;
 use serde_json::Value;
 
+use super::utxo::UTxO;
+
 pub mod command_failed;
 pub mod committed;
 pub mod greetings;
@@ -13,6 +16,7 @@ pub mod new_tx;
 pub mod peer_connected;
 pub mod peer_disconnected;
 pub mod snapshot_confirmed;
+pub mod tx_invalid;
 pub mod tx_valid;
 
 #[derive(Debug, Eq, PartialEq)]
@@ -23,6 +27,18 @@ pub struct Transaction {
     pub tx_type: String,
 }
 
+impl Transaction {
+    /// Decodes the outputs the transaction creates.
+    pub fn outputs(&self) -> anyhow::Result<Vec<UTxO>> {
+        let tx = MultiEraTx::decode(&self.cbor).context("failed to decode transaction")?;
+        tx.outputs()
+            .iter()
+            .enumerate()
+            .map(|(index, output)| UTxO::try_from_pallas(&self.tx_id, index as u64, output))
+            .collect()
+    }
+}
+
 impl TryFrom<&Value> for Transaction {
     type Error = anyhow::Error;
 
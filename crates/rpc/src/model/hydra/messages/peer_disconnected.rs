@@ -4,7 +4,7 @@ use serde_json::Value;
 #[allow(dead_code)]
 #[derive(Debug)]
 pub struct PeerDisconnected {
-    peer: String,
+    pub peer: String,
     timestamp: String,
     seq: u64,
 }
@@ -0,0 +1,126 @@
+use anyhow::Context;
+use serde_json::Value;
+
+#[derive(Debug, Eq, PartialEq)]
+pub struct TxInvalid {
+    pub head_id: String,
+    pub seq: u64,
+    pub tx_id: String,
+    pub reason: String,
+}
+
+impl TxInvalid {
+    /// A bounded label for the rejection reason, usable as a metric label. Ledger errors nest
+    /// generic wrappers like `ApplyTxError [UtxowFailure (UtxoFailure (...))]`, so this is the
+    /// first constructor that isn't one of them.
+    pub fn reason_label(&self) -> &str {
+        let mut rest = self.reason.as_str();
+        let mut label = "unknown";
+        loop {
+            rest = rest.trim_start_matches(|c: char| c.is_whitespace() || c == '[' || c == '(');
+            let end = rest
+                .find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+                .unwrap_or(rest.len());
+            let constructor = &rest[..end];
+            if !constructor.starts_with(|c: char| c.is_ascii_uppercase()) {
+                return label;
+            }
+            label = constructor;
+            if !(constructor.ends_with("Error") || constructor.ends_with("Failure")) {
+                return label;
+            }
+            rest = &rest[end..];
+        }
+    }
+}
+
+impl TryFrom<Value> for TxInvalid {
+    type Error = anyhow::Error;
+
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        let head_id = value
+            .get("headId")
+            .context("missing headId")?
+            .as_str()
+            .context("invalid headId")?
+            .to_owned();
+        let seq = value
+            .get("seq")
+            .context("missing seq")?
+            .as_u64()
+            .context("invalid seq")?;
+        let tx_id = value
+            .get("transaction")
+            .and_then(|transaction| transaction.get("txId"))
+            .context("missing txId")?
+            .as_str()
+            .context("invalid txId")?
+            .to_owned();
+        let reason = value
+            .get("validationError")
+            .and_then(|error| error.get("reason"))
+            .and_then(Value::as_str)
+            .unwrap_or("unknown reason")
+            .to_owned();
+
+        Ok(TxInvalid {
+            head_id,
+            seq,
+            tx_id,
+            reason,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_try_from_value() {
+        let tx_invalid = TxInvalid::try_from(json!({
+            "tag": "TxInvalid",
+            "headId": "84e657e3dd5241caac75b749195f78684023583736cc08b2896290ab",
+            "seq": 12,
+            "timestamp": "2024-10-08T13:05:51.294425524Z",
+            "transaction": {
+                "txId": "1ea4a2a5aa6b9e2e8b2ab7cbee7fcff83c9f16d0e10a9da1a9e1a3a8a4e4c8d9",
+                "type": "Tx ConwayEra"
+            },
+            "utxo": {},
+            "validationError": {
+                "reason": "ApplyTxError [ConwayUtxowFailure (UtxoFailure (ValueNotConservedUTxO (Mismatch {})))]"
+            }
+        }))
+        .expect("failed to parse TxInvalid");
+
+        assert_eq!(tx_invalid.seq, 12);
+        assert_eq!(
+            tx_invalid.tx_id,
+            "1ea4a2a5aa6b9e2e8b2ab7cbee7fcff83c9f16d0e10a9da1a9e1a3a8a4e4c8d9"
+        );
+        assert_eq!(tx_invalid.reason_label(), "ValueNotConservedUTxO");
+    }
+
+    #[test]
+    fn test_reason_label() {
+        let tx_invalid = |reason: &str| TxInvalid {
+            head_id: String::new(),
+            seq: 0,
+            tx_id: String::new(),
+            reason: reason.to_string(),
+        };
+
+        assert_eq!(tx_invalid("unknown reason").reason_label(), "unknown");
+        assert_eq!(tx_invalid("ApplyTxError []").reason_label(), "ApplyTxError");
+        assert_eq!(
+            tx_invalid(
+                "ApplyTxError [ConwayUtxowFailure (MissingVKeyWitnessesUTXOW (fromList []))]"
+            )
+            .reason_label(),
+            "MissingVKeyWitnessesUTXOW"
+        );
+    }
+}
@@ -81,9 +81,23 @@ impl UTxO {
             None
         };
 
+        // Native assets are nested by policy id, and flattened here into `{policy_id}{asset_name}`
+        // hex keys, next to the "lovelace" entry.
         let mut value_map = HashMap::new();
         for (key, value) in value["value"].as_object().context("Invalid value")? {
-            value_map.insert(key.to_string(), value.as_u64().context("Invalid value")?);
+            match value.as_object() {
+                Some(assets) => {
+                    for (asset_name, quantity) in assets {
+                        value_map.insert(
+                            format!("{}{}", key, asset_name),
+                            quantity.as_u64().context("Invalid asset quantity")?,
+                        );
+                    }
+                }
+                None => {
+                    value_map.insert(key.to_string(), value.as_u64().context("Invalid value")?);
+                }
+            }
         }
 
         Ok(UTxO {
@@ -130,7 +144,7 @@ impl UTxO {
             let policy_id_hex = hex::encode(multiassets.policy().as_ref());
             for asset in multiassets.assets().iter() {
                 value_map.insert(
-                    format!("{}#{}", policy_id_hex, hex::encode(asset.name())),
+                    format!("{}{}", policy_id_hex, hex::encode(asset.name())),
                     asset.output_coin().unwrap_or_default(),
                 );
             }
@@ -145,6 +159,10 @@ impl UTxO {
             value: value_map,
         })
     }
+
+    pub fn lovelace(&self) -> u64 {
+        *self.value.get("lovelace").unwrap_or(&0)
+    }
 }
 
 impl Display for UTxO {
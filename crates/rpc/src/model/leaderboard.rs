@@ -0,0 +1,256 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, RwLock},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use futures_util::future::join_all;
+use reqwest::Method;
+use rocket::FromFormField;
+use serde::Serialize;
+use tracing::warn;
+
+use super::cluster::{shared::GameResult, ClusterState};
+
+// How often the results are collected from the exporters.
+const REFRESH_INTERVAL: Duration = Duration::from_secs(30);
+// Players drop off the leaderboards after this long without playing. Rankings only live as long
+// as the replica though, so this only matters to replicas that stay up that long.
+const PLAYER_RETENTION: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+// Exporters keep results for an hour, counted games are remembered a while longer so they're
+// only counted once.
+const GAME_RETENTION: Duration = Duration::from_secs(24 * 60 * 60);
+/// The longest leaderboard served.
+pub const MAX_LEADERBOARD_SIZE: usize = 100;
+
+#[derive(FromFormField, Serialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum Ranking {
+    Kills,
+    Wins,
+    Games,
+}
+
+const RANKINGS: [Ranking; 3] = [Ranking::Kills, Ranking::Wins, Ranking::Games];
+
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct PlayerRecord {
+    pub player: String,
+    pub kills: u64,
+    pub wins: u64,
+    pub games: u64,
+    #[serde(skip)]
+    last_played: u64,
+}
+
+impl PlayerRecord {
+    fn score(&self, ranking: Ranking) -> u64 {
+        match ranking {
+            Ranking::Kills => self.kills,
+            Ranking::Wins => self.wins,
+            Ranking::Games => self.games,
+        }
+    }
+}
+
+#[derive(Default)]
+struct Inner {
+    players: HashMap<String, PlayerRecord>,
+    counted: HashMap<String, u64>,
+}
+
+/// Player rankings built from the results of finished games. The top of each ranking is cached,
+/// and refreshed whenever new results come in.
+///
+/// Nothing is persisted: every replica keeps its own rankings in memory, collected from the same
+/// exporters. They cover the games that finished since the replica started, plus the hour of
+/// results the exporters keep, and a restart or deploy starts them over.
+#[derive(Default)]
+pub struct Leaderboards {
+    inner: RwLock<Inner>,
+    cached: RwLock<HashMap<Ranking, Vec<PlayerRecord>>>,
+}
+
+impl Leaderboards {
+    /// Counts a game towards the leaderboards, unless it already was. Aborted games don't count.
+    pub fn record(&self, result: &GameResult) -> bool {
+        let mut inner = self.inner.write().unwrap();
+        if inner.counted.contains_key(&result.game_id) {
+            return false;
+        }
+        inner
+            .counted
+            .insert(result.game_id.clone(), result.finished_at);
+        if result.outcome == "Aborted" {
+            return false;
+        }
+
+        for player in &result.players {
+            let record = inner
+                .players
+                .entry(player.clone())
+                .or_insert_with(|| PlayerRecord {
+                    player: player.clone(),
+                    kills: 0,
+                    wins: 0,
+                    games: 0,
+                    last_played: 0,
+                });
+            record.games += 1;
+            record.kills += result.kills.get(player).copied().unwrap_or_default();
+            if result.winner.as_ref() == Some(player) {
+                record.wins += 1;
+            }
+            record.last_played = record.last_played.max(result.finished_at);
+        }
+
+        true
+    }
+
+    /// Forgets inactive players and old games, and recomputes the cached rankings.
+    pub fn refresh(&self, now: u64) {
+        let mut inner = self.inner.write().unwrap();
+        let player_cutoff = now.saturating_sub(PLAYER_RETENTION.as_secs());
+        inner
+            .players
+            .retain(|_, record| record.last_played >= player_cutoff);
+        let game_cutoff = now.saturating_sub(GAME_RETENTION.as_secs());
+        inner
+            .counted
+            .retain(|_, finished_at| *finished_at >= game_cutoff);
+
+        let cached = RANKINGS
+            .into_iter()
+            .map(|ranking| {
+                let mut records: Vec<_> = inner
+                    .players
+                    .values()
+                    .filter(|record| record.score(ranking) > 0)
+                    .cloned()
+                    .collect();
+                records.sort_by(|a, b| {
+                    b.score(ranking)
+                        .cmp(&a.score(ranking))
+                        .then_with(|| a.player.cmp(&b.player))
+                });
+                records.truncate(MAX_LEADERBOARD_SIZE);
+                (ranking, records)
+            })
+            .collect();
+        drop(inner);
+
+        *self.cached.write().unwrap() = cached;
+    }
+
+    pub fn top(&self, ranking: Ranking, limit: usize) -> Vec<PlayerRecord> {
+        self.cached
+            .read()
+            .unwrap()
+            .get(&ranking)
+            .map(|records| records.iter().take(limit).cloned().collect())
+            .unwrap_or_default()
+    }
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+/// Collects the results of finished games from the exporters of every node.
+pub async fn run_leaderboard_refresh(cluster: ClusterState, leaderboards: Arc<Leaderboards>) {
+    loop {
+        let nodes = cluster.get_all_nodes();
+        let responses = join_all(
+            nodes
+                .iter()
+                .map(|node| cluster.call_exporter(node, Method::GET, "game/results")),
+        )
+        .await;
+
+        for (node, response) in nodes.iter().zip(responses) {
+            let results = response.map_err(anyhow::Error::from).and_then(|body| {
+                serde_json::from_slice::<Vec<GameResult>>(&body).map_err(anyhow::Error::from)
+            });
+            match results {
+                Ok(results) => {
+                    for result in &results {
+                        leaderboards.record(result);
+                    }
+                }
+                Err(err) => warn!(
+                    node = node.metadata.name.as_deref().unwrap_or_default(),
+                    "failed to collect game results: {:#}", err
+                ),
+            }
+        }
+        leaderboards.refresh(unix_now());
+
+        tokio::time::sleep(REFRESH_INTERVAL).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn result(game_id: &str, outcome: &str, players: &[&str], winner: Option<&str>) -> GameResult {
+        GameResult {
+            game_id: game_id.to_string(),
+            outcome: outcome.to_string(),
+            players: players.iter().map(|player| player.to_string()).collect(),
+            winner: winner.map(str::to_string),
+            kills: players
+                .iter()
+                .map(|player| (player.to_string(), 2))
+                .collect(),
+            finished_at: 1000,
+        }
+    }
+
+    #[test]
+    fn test_rankings() {
+        let leaderboards = Leaderboards::default();
+        assert!(leaderboards.record(&result("game1", "Finished", &["a", "b"], Some("b"))));
+        assert!(leaderboards.record(&result("game2", "Finished", &["a", "c"], Some("a"))));
+        assert!(leaderboards.record(&result("game3", "Cheated", &["a", "b"], None)));
+        // Counted once, and aborted games don't count
+        assert!(!leaderboards.record(&result("game1", "Finished", &["a", "b"], Some("b"))));
+        assert!(!leaderboards.record(&result("game4", "Aborted", &["c"], None)));
+        leaderboards.refresh(1000);
+
+        let games: Vec<_> = leaderboards
+            .top(Ranking::Games, 10)
+            .into_iter()
+            .map(|record| (record.player, record.games))
+            .collect();
+        assert_eq!(
+            games,
+            vec![
+                ("a".to_string(), 3),
+                ("b".to_string(), 2),
+                ("c".to_string(), 1)
+            ]
+        );
+        let wins: Vec<_> = leaderboards
+            .top(Ranking::Wins, 10)
+            .into_iter()
+            .map(|record| record.player)
+            .collect();
+        assert_eq!(wins, vec!["a".to_string(), "b".to_string()]);
+        assert_eq!(leaderboards.top(Ranking::Kills, 1)[0].kills, 6);
+    }
+
+    #[test]
+    fn test_retention() {
+        let leaderboards = Leaderboards::default();
+        leaderboards.record(&result("game1", "Finished", &["a"], Some("a")));
+        leaderboards.refresh(1000 + PLAYER_RETENTION.as_secs() + 1);
+
+        assert!(leaderboards.top(Ranking::Games, 10).is_empty());
+        // Long forgotten games can't be counted again either, as the exporters don't keep them
+        assert!(leaderboards.inner.read().unwrap().counted.is_empty());
+    }
+}
@@ -0,0 +1,511 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use tokio::sync::Notify;
+
+use super::cluster::shared::NodeGame;
+
+// Players that couldn't be placed in a game after this long are told to try again later.
+const QUEUE_TIMEOUT: Duration = Duration::from_secs(5 * 60);
+// Lobbies stop taking players this long before the exporter's lobby timer starts the game, so that
+// the players placed last have the time to get in.
+const LOBBY_START_MARGIN: Duration = Duration::from_secs(15);
+// Lobbies created here are dropped if their node still doesn't report them after this long.
+const UNCONFIRMED_LOBBY_TTL: Duration = Duration::from_secs(30);
+// How long the outcome of a ticket can be fetched after it was decided.
+const RESULT_TTL: Duration = Duration::from_secs(5 * 60);
+
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct MatchPreferences {
+    pub region: Option<String>,
+    pub player_count: u64,
+    pub bot_count: u64,
+}
+
+impl MatchPreferences {
+    /// Whether a game set up for these preferences suits a player asking for `wanted`. Players
+    /// without a region preference play in any region.
+    fn suits(&self, wanted: &MatchPreferences) -> bool {
+        self.player_count == wanted.player_count
+            && self.bot_count == wanted.bot_count
+            && (wanted.region.is_none() || wanted.region == self.region)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Ticket {
+    pub id: String,
+    pub address: String,
+    pub preferences: MatchPreferences,
+    enqueued_at: Instant,
+}
+
+/// A game in its lobby that still has seats left.
+#[derive(Clone, Debug)]
+pub struct OpenLobby {
+    pub game_id: String,
+    pub node_id: String,
+    /// The preferences the game was set up with, in the region of its node.
+    pub preferences: MatchPreferences,
+    seats_left: u64,
+    created_at: Instant,
+    // Set once the node reports the lobby, along with the time its lobby timer starts the game
+    confirmed: bool,
+    closes_at: Option<Instant>,
+}
+
+impl OpenLobby {
+    /// A lobby for a game created here, whose creator already took the first seat.
+    pub fn new(game_id: String, node_id: String, preferences: MatchPreferences) -> Self {
+        Self {
+            seats_left: preferences.player_count.saturating_sub(1),
+            game_id,
+            node_id,
+            preferences,
+            created_at: Instant::now(),
+            confirmed: false,
+            closes_at: None,
+        }
+    }
+
+    /// A lobby reported by its node, whichever replica created the game.
+    pub fn reported(node_id: String, region: Option<String>, game: &NodeGame) -> Self {
+        let now = Instant::now();
+        Self {
+            game_id: game.game_id.clone(),
+            node_id,
+            preferences: MatchPreferences {
+                region,
+                player_count: game.player_count,
+                bot_count: game.bot_count,
+            },
+            seats_left: game.player_count.saturating_sub(game.players),
+            created_at: now,
+            confirmed: true,
+            closes_at: game.starts_in.map(|starts_in| {
+                now + Duration::from_secs(starts_in).saturating_sub(LOBBY_START_MARGIN)
+            }),
+        }
+    }
+
+    fn is_open(&self, now: Instant) -> bool {
+        !self.closes_at.is_some_and(|closes_at| now >= closes_at)
+            && (self.confirmed
+                || now.saturating_duration_since(self.created_at) < UNCONFIRMED_LOBBY_TTL)
+    }
+}
+
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct Assignment {
+    pub game_id: String,
+    pub node_id: String,
+    pub ip: String,
+    pub player_state: String,
+    pub admin_pkh: String,
+}
+
+#[derive(Serialize, Clone, Debug, PartialEq)]
+#[serde(tag = "state", rename_all = "lowercase")]
+pub enum TicketState {
+    Queued,
+    Assigned(Assignment),
+    Failed { reason: String },
+}
+
+/// What to do with the next ticket in the queue.
+#[derive(Debug)]
+pub enum Match {
+    /// Take a seat in a game that is still in its lobby.
+    Join(Ticket, OpenLobby),
+    /// No open lobby fits the ticket's preferences, so a new game has to be created for it.
+    Create(Ticket),
+}
+
+struct Inner {
+    queue: VecDeque<Ticket>,
+    lobbies: Vec<OpenLobby>,
+    results: HashMap<String, (TicketState, Instant)>,
+}
+
+/// Queues players that want to play and groups them into games, filling the open lobbies of
+/// earlier games before new ones are created.
+///
+/// The queue and the tickets only live in the memory of the replica that issued them, so clients
+/// must be pinned to a replica for the ticket routes, which the ingress does with a session cookie.
+/// Players queued on different replicas are still grouped through the lobbies the nodes report.
+pub struct Matchmaker {
+    inner: Mutex<Inner>,
+    updated: Notify,
+}
+
+impl Default for Matchmaker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Matchmaker {
+    pub fn new() -> Self {
+        Self {
+            inner: Mutex::new(Inner {
+                queue: VecDeque::new(),
+                lobbies: Vec::new(),
+                results: HashMap::new(),
+            }),
+            updated: Notify::new(),
+        }
+    }
+
+    /// Queues `address`, replacing the ticket it may already have in the queue.
+    pub fn enqueue(&self, address: &str, preferences: MatchPreferences) -> String {
+        let mut bytes = [0u8; 16];
+        rand::thread_rng().fill_bytes(&mut bytes);
+        let id = hex::encode(bytes);
+
+        let mut inner = self.inner.lock().unwrap();
+        inner.queue.retain(|ticket| ticket.address != address);
+        inner.queue.push_back(Ticket {
+            id: id.clone(),
+            address: address.to_string(),
+            preferences,
+            enqueued_at: Instant::now(),
+        });
+
+        id
+    }
+
+    pub fn has_queued(&self) -> bool {
+        !self.inner.lock().unwrap().queue.is_empty()
+    }
+
+    /// Removes a ticket that is still waiting in the queue. Returns false if it was already taken
+    /// out of the queue to be placed in a game.
+    pub fn cancel(&self, ticket_id: &str) -> bool {
+        let mut inner = self.inner.lock().unwrap();
+        let queued = inner.queue.len();
+        inner.queue.retain(|ticket| ticket.id != ticket_id);
+
+        inner.queue.len() < queued
+    }
+
+    pub fn state(&self, ticket_id: &str) -> Option<TicketState> {
+        let inner = self.inner.lock().unwrap();
+        if inner.queue.iter().any(|ticket| ticket.id == ticket_id) {
+            return Some(TicketState::Queued);
+        }
+
+        inner.results.get(ticket_id).map(|(state, _)| state.clone())
+    }
+
+    /// Waits up to `timeout` for the ticket to be decided and returns its state.
+    pub async fn wait(&self, ticket_id: &str, timeout: Duration) -> Option<TicketState> {
+        let deadline = tokio::time::Instant::now() + timeout;
+        loop {
+            // Registered before checking, so an update in between isn't missed
+            let updated = self.updated.notified();
+            match self.state(ticket_id) {
+                Some(TicketState::Queued) => {}
+                state => return state,
+            }
+            if tokio::time::timeout_at(deadline, updated).await.is_err() {
+                return self.state(ticket_id);
+            }
+        }
+    }
+
+    /// Takes the next ticket out of the queue and decides where it should play. Lobbies only
+    /// take players until shortly before their game starts, and while `is_joinable` holds for
+    /// them.
+    pub fn next_match(&self, is_joinable: impl Fn(&OpenLobby) -> bool) -> Option<Match> {
+        let mut inner = self.inner.lock().unwrap();
+        self.expire(&mut inner);
+        let now = Instant::now();
+        inner
+            .lobbies
+            .retain(|lobby| lobby.is_open(now) && is_joinable(lobby));
+
+        let ticket = inner.queue.pop_front()?;
+        // Still queued as far as the player is concerned, until the match is carried out
+        inner
+            .results
+            .insert(ticket.id.clone(), (TicketState::Queued, Instant::now()));
+        let lobby = inner
+            .lobbies
+            .iter_mut()
+            .find(|lobby| lobby.seats_left > 0 && lobby.preferences.suits(&ticket.preferences));
+
+        Some(match lobby {
+            Some(lobby) => {
+                lobby.seats_left -= 1;
+                Match::Join(ticket, lobby.clone())
+            }
+            None => Match::Create(ticket),
+        })
+    }
+
+    /// Puts a ticket that couldn't be placed right now back at the front of the queue.
+    pub fn requeue(&self, ticket: Ticket) {
+        self.inner.lock().unwrap().queue.push_front(ticket);
+    }
+
+    /// Makes a game created for a ticket available to the following ones.
+    pub fn open_lobby(&self, lobby: OpenLobby) {
+        let mut inner = self.inner.lock().unwrap();
+        if lobby.seats_left > 0 {
+            inner.lobbies.push(lobby);
+        }
+    }
+
+    /// Replaces the lobbies with the ones the nodes reported, so that games created through other
+    /// replicas get filled too. Lobbies created here that aren't reported yet are kept for a
+    /// while, their game may not have made it into a snapshot.
+    pub fn sync_lobbies(&self, reported: Vec<OpenLobby>) {
+        let mut inner = self.inner.lock().unwrap();
+        let mut lobbies: Vec<OpenLobby> = reported
+            .into_iter()
+            .map(|mut lobby| {
+                // Seats handed out since the node's last snapshot are still taken
+                if let Some(known) = inner
+                    .lobbies
+                    .iter()
+                    .find(|known| known.game_id == lobby.game_id)
+                {
+                    lobby.seats_left = lobby.seats_left.min(known.seats_left);
+                }
+                lobby
+            })
+            .collect();
+        let unconfirmed: Vec<OpenLobby> = inner
+            .lobbies
+            .drain(..)
+            .filter(|known| {
+                !known.confirmed && !lobbies.iter().any(|lobby| lobby.game_id == known.game_id)
+            })
+            .collect();
+        lobbies.extend(unconfirmed);
+        inner.lobbies = lobbies;
+    }
+
+    /// Stops filling a lobby, e.g. because its node refused a player.
+    pub fn close_lobby(&self, game_id: &str) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.lobbies.retain(|lobby| lobby.game_id != game_id);
+    }
+
+    pub fn assign(&self, ticket: &Ticket, assignment: Assignment) {
+        self.decide(ticket, TicketState::Assigned(assignment));
+    }
+
+    pub fn fail(&self, ticket: &Ticket, reason: String) {
+        self.decide(ticket, TicketState::Failed { reason });
+    }
+
+    fn decide(&self, ticket: &Ticket, state: TicketState) {
+        let mut inner = self.inner.lock().unwrap();
+        inner
+            .results
+            .insert(ticket.id.clone(), (state, Instant::now()));
+        drop(inner);
+
+        self.updated.notify_waiters();
+    }
+
+    fn expire(&self, inner: &mut Inner) {
+        inner
+            .results
+            .retain(|_, (_, decided_at)| decided_at.elapsed() < RESULT_TTL);
+
+        let (expired, queue) = inner
+            .queue
+            .drain(..)
+            .partition::<Vec<_>, _>(|ticket| ticket.enqueued_at.elapsed() >= QUEUE_TIMEOUT);
+        inner.queue = queue.into();
+        if expired.is_empty() {
+            return;
+        }
+
+        for ticket in expired {
+            inner.results.insert(
+                ticket.id,
+                (
+                    TicketState::Failed {
+                        reason: "no game could be found in time".to_string(),
+                    },
+                    Instant::now(),
+                ),
+            );
+        }
+        self.updated.notify_waiters();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn preferences(player_count: u64) -> MatchPreferences {
+        MatchPreferences {
+            region: None,
+            player_count,
+            bot_count: 0,
+        }
+    }
+
+    #[test]
+    fn test_fills_open_lobbies_first() {
+        let matchmaker = Matchmaker::new();
+        let first = matchmaker.enqueue("addr1", preferences(2));
+        let second = matchmaker.enqueue("addr2", preferences(2));
+        let third = matchmaker.enqueue("addr3", preferences(2));
+
+        let Some(Match::Create(ticket)) = matchmaker.next_match(|_| true) else {
+            panic!("expected a new game");
+        };
+        assert_eq!(ticket.id, first);
+        matchmaker.open_lobby(OpenLobby::new(
+            "game1".to_string(),
+            "node1".to_string(),
+            ticket.preferences.clone(),
+        ));
+
+        let Some(Match::Join(ticket, lobby)) = matchmaker.next_match(|_| true) else {
+            panic!("expected to join the open lobby");
+        };
+        assert_eq!(
+            (ticket.id.as_str(), lobby.game_id.as_str()),
+            (second.as_str(), "game1")
+        );
+
+        // The lobby is full now
+        let Some(Match::Create(ticket)) = matchmaker.next_match(|_| true) else {
+            panic!("expected a new game");
+        };
+        assert_eq!(ticket.id, third);
+        assert!(matchmaker.next_match(|_| true).is_none());
+    }
+
+    #[test]
+    fn test_respects_preferences_and_game_state() {
+        let matchmaker = Matchmaker::new();
+        matchmaker.open_lobby(OpenLobby::new(
+            "game1".to_string(),
+            "node1".to_string(),
+            preferences(4),
+        ));
+
+        matchmaker.enqueue("addr1", preferences(2));
+        assert!(matches!(
+            matchmaker.next_match(|_| true),
+            Some(Match::Create(_))
+        ));
+
+        // The game started, so its lobby doesn't take players anymore
+        let ticket = matchmaker.enqueue("addr2", preferences(4));
+        assert!(matches!(
+            matchmaker.next_match(|lobby| lobby.game_id != "game1"),
+            Some(Match::Create(_))
+        ));
+        assert_eq!(matchmaker.state(&ticket), Some(TicketState::Queued));
+    }
+
+    #[test]
+    fn test_ticket_states() {
+        let matchmaker = Matchmaker::new();
+        let replaced = matchmaker.enqueue("addr1", preferences(1));
+        let ticket = matchmaker.enqueue("addr1", preferences(1));
+        assert_eq!(matchmaker.state(&replaced), None);
+        assert_eq!(matchmaker.state(&ticket), Some(TicketState::Queued));
+
+        let Some(Match::Create(queued)) = matchmaker.next_match(|_| true) else {
+            panic!("expected a new game");
+        };
+        matchmaker.fail(&queued, "no warm node".to_string());
+        assert_eq!(
+            matchmaker.state(&ticket),
+            Some(TicketState::Failed {
+                reason: "no warm node".to_string()
+            })
+        );
+        assert!(!matchmaker.cancel(&ticket));
+    }
+
+    fn reported(game_id: &str, players: u64, starts_in: Option<u64>) -> OpenLobby {
+        OpenLobby::reported(
+            "node1".to_string(),
+            Some("us-east-2".to_string()),
+            &NodeGame {
+                game_id: game_id.to_string(),
+                state: "Lobby".to_string(),
+                players,
+                player_count: 2,
+                bot_count: 0,
+                starts_in,
+            },
+        )
+    }
+
+    #[test]
+    fn test_fills_reported_lobbies() {
+        let matchmaker = Matchmaker::new();
+        matchmaker.sync_lobbies(vec![
+            reported("full", 2, Some(50)),
+            reported("closing", 1, Some(10)),
+            reported("game1", 1, Some(50)),
+        ]);
+
+        matchmaker.enqueue("addr1", preferences(2));
+        let Some(Match::Join(_, lobby)) = matchmaker.next_match(|_| true) else {
+            panic!("expected to join the reported lobby");
+        };
+        assert_eq!(lobby.game_id, "game1");
+
+        // Another region's player doesn't get sent there
+        matchmaker.sync_lobbies(vec![reported("game2", 1, None)]);
+        matchmaker.enqueue(
+            "addr2",
+            MatchPreferences {
+                region: Some("eu-central-1".to_string()),
+                ..preferences(2)
+            },
+        );
+        assert!(matches!(
+            matchmaker.next_match(|_| true),
+            Some(Match::Create(_))
+        ));
+    }
+
+    #[test]
+    fn test_sync_keeps_taken_seats_and_unreported_lobbies() {
+        let matchmaker = Matchmaker::new();
+        matchmaker.open_lobby(OpenLobby::new(
+            "created".to_string(),
+            "node1".to_string(),
+            preferences(2),
+        ));
+        matchmaker.sync_lobbies(vec![reported("game1", 1, Some(50))]);
+
+        matchmaker.enqueue("addr1", preferences(2));
+        matchmaker.enqueue("addr2", preferences(2));
+        let joined: Vec<String> = std::iter::from_fn(|| match matchmaker.next_match(|_| true) {
+            Some(Match::Join(_, lobby)) => Some(lobby.game_id),
+            _ => None,
+        })
+        .collect();
+        assert_eq!(joined.len(), 2);
+        assert!(joined.contains(&"created".to_string()));
+        assert!(joined.contains(&"game1".to_string()));
+
+        // The node hasn't seen the new player yet, but the seat is taken
+        matchmaker.sync_lobbies(vec![reported("game1", 1, Some(50))]);
+        matchmaker.enqueue("addr3", preferences(2));
+        assert!(matches!(
+            matchmaker.next_match(|_| true),
+            Some(Match::Create(_))
+        ));
+    }
+}
@@ -1,8 +1,17 @@
 use std::fmt;
 
+pub mod admin;
+pub mod api_error;
+pub mod auth;
 pub mod cluster;
+pub mod events;
 pub mod game;
 pub mod hydra;
+pub mod leaderboard;
+pub mod matchmaking;
+pub mod rate_limit;
+pub mod stats;
+pub mod telemetry;
 pub mod tx_builder;
 
 pub fn format_hex<T: AsRef<[u8]>>(data: T, f: &mut fmt::Formatter) -> fmt::Result {
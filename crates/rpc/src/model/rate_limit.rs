@@ -0,0 +1,261 @@
+use std::{
+    collections::HashMap,
+    hash::Hash,
+    net::IpAddr,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use serde::{de, Deserialize, Deserializer};
+
+// Buckets that refilled completely carry no state, so they are dropped once a map grows past this.
+const MAX_TRACKED_BUCKETS: usize = 10_000;
+
+#[derive(Deserialize, Clone, Copy, Debug)]
+pub struct BucketConfig {
+    #[serde(deserialize_with = "positive")]
+    pub capacity: u32,
+    #[serde(deserialize_with = "positive")]
+    pub per_minute: u32,
+}
+
+// A bucket that never refills, or that can't hold a single token, would lock its callers out for
+// good
+fn positive<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u32, D::Error> {
+    match u32::deserialize(deserializer)? {
+        0 => Err(de::Error::invalid_value(
+            de::Unexpected::Unsigned(0),
+            &"a positive number",
+        )),
+        value => Ok(value),
+    }
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct RateLimitConfig {
+    pub per_address: BucketConfig,
+    pub per_ip: BucketConfig,
+    pub global: BucketConfig,
+    pub max_active_games_per_address: usize,
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        Self {
+            per_address: BucketConfig {
+                capacity: 3,
+                per_minute: 3,
+            },
+            per_ip: BucketConfig {
+                capacity: 10,
+                per_minute: 10,
+            },
+            global: BucketConfig {
+                capacity: 100,
+                per_minute: 300,
+            },
+            max_active_games_per_address: 1,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct TokenBucket {
+    config: BucketConfig,
+    tokens: f64,
+    updated_at: Instant,
+}
+
+impl TokenBucket {
+    fn new(config: BucketConfig, now: Instant) -> Self {
+        Self {
+            config,
+            tokens: config.capacity as f64,
+            updated_at: now,
+        }
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.updated_at).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.config.per_minute as f64 / 60.0)
+            .min(self.config.capacity as f64);
+        self.updated_at = now;
+    }
+
+    fn is_full(&self) -> bool {
+        self.tokens >= self.config.capacity as f64
+    }
+
+    /// How long until a token is available, or `None` if one is available now.
+    fn wait_time(&mut self, now: Instant) -> Option<Duration> {
+        self.refill(now);
+        if self.tokens >= 1.0 {
+            None
+        } else {
+            Some(Duration::from_secs_f64(
+                (1.0 - self.tokens) * 60.0 / self.config.per_minute.max(1) as f64,
+            ))
+        }
+    }
+
+    fn take(&mut self) {
+        self.tokens -= 1.0;
+    }
+}
+
+fn bucket<'a, K: Eq + Hash>(
+    buckets: &'a mut HashMap<K, TokenBucket>,
+    key: K,
+    config: BucketConfig,
+    now: Instant,
+) -> &'a mut TokenBucket {
+    if buckets.len() > MAX_TRACKED_BUCKETS {
+        buckets.retain(|_, bucket| {
+            bucket.refill(now);
+            !bucket.is_full()
+        });
+    }
+
+    buckets
+        .entry(key)
+        .or_insert_with(|| TokenBucket::new(config, now))
+}
+
+/// Token bucket rate limiting for game creation, per address, per IP and across all callers.
+pub struct RateLimiter {
+    config: RateLimitConfig,
+    buckets: Mutex<Buckets>,
+}
+
+struct Buckets {
+    global: TokenBucket,
+    per_ip: HashMap<IpAddr, TokenBucket>,
+    per_address: HashMap<String, TokenBucket>,
+}
+
+impl RateLimiter {
+    pub fn new(config: RateLimitConfig) -> Self {
+        let now = Instant::now();
+        Self {
+            buckets: Mutex::new(Buckets {
+                global: TokenBucket::new(config.global, now),
+                per_ip: HashMap::new(),
+                per_address: HashMap::new(),
+            }),
+            config,
+        }
+    }
+
+    pub fn max_active_games_per_address(&self) -> usize {
+        self.config.max_active_games_per_address
+    }
+
+    /// Takes a token from every bucket that applies to the request, or returns how long the caller
+    /// has to wait. No tokens are taken from any bucket when one of them is empty.
+    pub fn check(&self, ip: Option<IpAddr>, address: &str) -> Result<(), Duration> {
+        self.check_at(ip, address, Instant::now())
+    }
+
+    fn check_at(&self, ip: Option<IpAddr>, address: &str, now: Instant) -> Result<(), Duration> {
+        let mut guard = self.buckets.lock().unwrap();
+        let buckets = &mut *guard;
+
+        let mut limited: Vec<&mut TokenBucket> = vec![
+            &mut buckets.global,
+            bucket(
+                &mut buckets.per_address,
+                address.to_string(),
+                self.config.per_address,
+                now,
+            ),
+        ];
+        if let Some(ip) = ip {
+            limited.push(bucket(&mut buckets.per_ip, ip, self.config.per_ip, now));
+        }
+
+        let wait = limited
+            .iter_mut()
+            .filter_map(|bucket| bucket.wait_time(now))
+            .max();
+        if let Some(wait) = wait {
+            return Err(wait);
+        }
+
+        limited.iter_mut().for_each(|bucket| bucket.take());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limiter() -> RateLimiter {
+        RateLimiter::new(RateLimitConfig {
+            per_address: BucketConfig {
+                capacity: 2,
+                per_minute: 6,
+            },
+            per_ip: BucketConfig {
+                capacity: 3,
+                per_minute: 6,
+            },
+            global: BucketConfig {
+                capacity: 100,
+                per_minute: 100,
+            },
+            max_active_games_per_address: 1,
+        })
+    }
+
+    #[test]
+    fn test_per_address_bucket() {
+        let limiter = limiter();
+        let now = Instant::now();
+
+        assert!(limiter.check_at(None, "addr1", now).is_ok());
+        assert!(limiter.check_at(None, "addr1", now).is_ok());
+        assert_eq!(
+            limiter.check_at(None, "addr1", now),
+            Err(Duration::from_secs(10))
+        );
+        assert!(limiter.check_at(None, "addr2", now).is_ok());
+
+        // One token every 10 seconds
+        assert!(limiter
+            .check_at(None, "addr1", now + Duration::from_secs(10))
+            .is_ok());
+    }
+
+    #[test]
+    fn test_per_ip_bucket() {
+        let limiter = limiter();
+        let now = Instant::now();
+        let ip: IpAddr = "127.0.0.1".parse().unwrap();
+
+        for address in ["addr1", "addr2", "addr3"] {
+            assert!(limiter.check_at(Some(ip), address, now).is_ok());
+        }
+        assert!(limiter.check_at(Some(ip), "addr4", now).is_err());
+        // A rejected request doesn't use up the address' tokens
+        assert!(limiter.check_at(None, "addr4", now).is_ok());
+        assert!(limiter.check_at(None, "addr4", now).is_ok());
+    }
+
+    #[test]
+    fn test_reject_bucket_without_refill() {
+        assert!(
+            serde_json::from_str::<BucketConfig>(r#"{ "capacity": 3, "per_minute": 0 }"#).is_err()
+        );
+        assert!(
+            serde_json::from_str::<BucketConfig>(r#"{ "capacity": 3, "per_minute": 1 }"#).is_ok()
+        );
+    }
+
+    #[test]
+    fn test_reject_bucket_without_capacity() {
+        assert!(
+            serde_json::from_str::<BucketConfig>(r#"{ "capacity": 0, "per_minute": 3 }"#).is_err()
+        );
+    }
+}
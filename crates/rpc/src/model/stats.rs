@@ -0,0 +1,676 @@
+use std::{
+    collections::VecDeque,
+    str::FromStr,
+    sync::{Arc, Mutex, RwLock},
+    time::{self, Duration, Instant},
+};
+
+use anyhow::Context;
+use futures_util::{
+    future::{join_all, BoxFuture},
+    try_join, FutureExt,
+};
+use reqwest::Method;
+use rocket::FromFormField;
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use super::{
+    api_error::ApiResult,
+    cluster::{ClusterState, HydraDoomNode},
+};
+
+// How often the stats are refreshed.
+const REFRESH_INTERVAL: Duration = Duration::from_secs(5);
+// How often the stats are added to the history, and how long they're kept there.
+const HISTORY_INTERVAL: Duration = Duration::from_secs(60);
+const HISTORY_RETENTION: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct GlobalStats {
+    pub as_of: time::SystemTime,
+    pub total_txs: u64,
+    pub txs_per_second: f64,
+    pub peak_txs_per_second: f64,
+    pub total_bytes: u64,
+    pub bytes_per_second: f64,
+
+    pub total_games: u32,
+    pub active_games: u32,
+    pub total_players: u32,
+    pub active_players: u32,
+    pub total_bots: u32,
+    pub active_bots: u32,
+    pub total_kills: u32,
+    pub kills_per_minute: f32,
+    pub total_suicides: u32,
+    pub suicides_per_minute: f32,
+}
+
+/// Empty stats, dated at the epoch until the first refresh succeeds.
+impl Default for GlobalStats {
+    fn default() -> Self {
+        Self {
+            as_of: time::UNIX_EPOCH,
+            total_txs: 0,
+            txs_per_second: 0.0,
+            peak_txs_per_second: 0.0,
+            total_bytes: 0,
+            bytes_per_second: 0.0,
+            total_games: 0,
+            active_games: 0,
+            total_players: 0,
+            active_players: 0,
+            total_bots: 0,
+            active_bots: 0,
+            total_kills: 0,
+            kills_per_minute: 0.0,
+            total_suicides: 0,
+            suicides_per_minute: 0.0,
+        }
+    }
+}
+
+/// How far back a history goes. Longer ranges have wider buckets.
+#[derive(FromFormField, Clone, Copy, Debug, PartialEq)]
+pub enum HistoryRange {
+    Hour,
+    Day,
+    Week,
+}
+
+impl HistoryRange {
+    fn span_and_bucket(&self) -> (Duration, Duration) {
+        match self {
+            HistoryRange::Hour => (Duration::from_secs(60 * 60), Duration::from_secs(60)),
+            HistoryRange::Day => (
+                Duration::from_secs(24 * 60 * 60),
+                Duration::from_secs(15 * 60),
+            ),
+            HistoryRange::Week => (HISTORY_RETENTION, Duration::from_secs(2 * 60 * 60)),
+        }
+    }
+}
+
+/// The averages of the stats sampled within a bucket of a history.
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct HistoryBucket {
+    /// Unix timestamp of the start of the bucket, in seconds.
+    pub start: u64,
+    pub txs_per_second: f64,
+    pub active_games: f64,
+    pub active_players: f64,
+}
+
+/// The latest global stats and a history of them sampled every `HISTORY_INTERVAL`. The history is
+/// kept in memory by each replica, from the time it started, so replicas may serve different
+/// histories for the time before that.
+#[derive(Clone, Default)]
+pub struct StatsState {
+    latest_stats: Arc<RwLock<GlobalStats>>,
+    history: Arc<RwLock<VecDeque<GlobalStats>>>,
+}
+
+impl StatsState {
+    pub fn new(stats: GlobalStats) -> Self {
+        Self {
+            latest_stats: Arc::new(RwLock::new(stats)),
+            history: Default::default(),
+        }
+    }
+
+    /// Replaces the latest stats, and adds them to the history if the last sample in there is
+    /// old enough.
+    pub fn update(&self, stats: GlobalStats) {
+        let mut history = self.history.write().unwrap();
+        let due = match history.back() {
+            Some(last) => stats
+                .as_of
+                .duration_since(last.as_of)
+                .is_ok_and(|elapsed| elapsed >= HISTORY_INTERVAL),
+            None => true,
+        };
+        if due {
+            history.push_back(stats.clone());
+            while history.front().is_some_and(|first| {
+                stats
+                    .as_of
+                    .duration_since(first.as_of)
+                    .is_ok_and(|age| age > HISTORY_RETENTION)
+            }) {
+                history.pop_front();
+            }
+        }
+        drop(history);
+
+        let mut latest_stats = self.latest_stats.write().unwrap();
+        *latest_stats = stats;
+    }
+
+    pub fn latest(&self) -> GlobalStats {
+        self.latest_stats.read().unwrap().clone()
+    }
+
+    /// The history over `range` up to `now`, oldest bucket first. Buckets without samples are
+    /// left out.
+    pub fn history(&self, range: HistoryRange, now: time::SystemTime) -> Vec<HistoryBucket> {
+        let (span, bucket) = range.span_and_bucket();
+        let now = unix_seconds(now);
+        let bucket = bucket.as_secs();
+        // Aligned, so the buckets don't shift between requests
+        let first = now.saturating_sub(span.as_secs()) / bucket * bucket;
+
+        let mut buckets: Vec<(u64, Vec<&GlobalStats>)> = Vec::new();
+        let history = self.history.read().unwrap();
+        for stats in history.iter() {
+            let at = unix_seconds(stats.as_of);
+            if at < first || at > now {
+                continue;
+            }
+            let start = at / bucket * bucket;
+            match buckets.last_mut() {
+                Some((last, samples)) if *last == start => samples.push(stats),
+                _ => buckets.push((start, vec![stats])),
+            }
+        }
+
+        buckets
+            .into_iter()
+            .map(|(start, samples)| {
+                let average = |value: fn(&GlobalStats) -> f64| {
+                    samples.iter().map(|stats| value(stats)).sum::<f64>() / samples.len() as f64
+                };
+                HistoryBucket {
+                    start,
+                    txs_per_second: average(|stats| stats.txs_per_second),
+                    active_games: average(|stats| stats.active_games as f64),
+                    active_players: average(|stats| stats.active_players as f64),
+                }
+            })
+            .collect()
+    }
+}
+
+fn unix_seconds(time: time::SystemTime) -> u64 {
+    time.duration_since(time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+/// Where the global stats come from.
+pub trait StatsSource: Send + Sync {
+    fn fetch(&self) -> BoxFuture<'_, anyhow::Result<GlobalStats>>;
+}
+
+// Where the stats came from before the source could be configured.
+const DEFAULT_PROMETHEUS_URL: &str = "https://thanos.hydra-doom.sundae.fi";
+
+#[derive(Deserialize, Clone, Debug)]
+#[serde(tag = "source", rename_all = "snake_case")]
+pub enum StatsConfig {
+    /// Query a Prometheus compatible API, e.g. Thanos, that scrapes the node exporters.
+    Prometheus { url: String },
+    /// Scrape the exporters of the nodes currently in the clusters directly. Totals only cover
+    /// the nodes that are still around.
+    Exporters,
+    /// Always serve empty stats.
+    Static,
+}
+
+impl Default for StatsConfig {
+    fn default() -> Self {
+        StatsConfig::Prometheus {
+            url: DEFAULT_PROMETHEUS_URL.to_string(),
+        }
+    }
+}
+
+impl StatsConfig {
+    pub fn into_source(self, cluster: ClusterState) -> Arc<dyn StatsSource> {
+        match self {
+            StatsConfig::Prometheus { url } => Arc::new(PrometheusSource::new(url)),
+            StatsConfig::Exporters => Arc::new(ExporterSource::new(cluster)),
+            StatsConfig::Static => Arc::new(StaticSource(GlobalStats::default())),
+        }
+    }
+}
+
+/// Keeps `state` up to date. Failed refreshes are logged and the previous stats stay in place,
+/// so the server starts even when the source is down.
+pub async fn run_stats_refresh(source: Arc<dyn StatsSource>, state: StatsState) {
+    loop {
+        match source.fetch().await {
+            Ok(stats) => state.update(stats),
+            Err(err) => warn!("failed to fetch stats: {:#}", err),
+        }
+        tokio::time::sleep(REFRESH_INTERVAL).await;
+    }
+}
+
+/// Serves the same stats forever.
+pub struct StaticSource(pub GlobalStats);
+
+impl StatsSource for StaticSource {
+    fn fetch(&self) -> BoxFuture<'_, anyhow::Result<GlobalStats>> {
+        let stats = GlobalStats {
+            as_of: time::SystemTime::now(),
+            ..self.0.clone()
+        };
+        async move { Ok(stats) }.boxed()
+    }
+}
+
+const TOTAL_TRANSACTIONS: &str = "sum(last_over_time(hydra_doom_node_transactions[1y]))";
+const TRANSACTIONS_PER_SECOND: &str = "sum(irate(hydra_doom_node_transactions[1m])>0)";
+const PEAK_TRANSACTIONS_PER_SECOND: &str =
+    "max_over_time(sum(irate(hydra_doom_node_transactions[1m]))[1w:])";
+const TOTAL_BYTES: &str = "sum(last_over_time(hydra_doom_node_bytes[1y]))";
+const BYTES_PER_SECOND: &str = "sum(irate(hydra_doom_node_bytes[1m])>0)";
+const TOTAL_GAMES: &str = "sum(last_over_time(hydra_doom_games_seconds_count[1y]))";
+const ACTIVE_GAMES: &str = "sum(hydra_doom_games_current)";
+const TOTAL_PLAYERS: &str = "sum(last_over_time(hydra_doom_players_total[1y]))";
+const ACTIVE_PLAYERS: &str = "sum(hydra_doom_players_current)";
+const TOTAL_BOTS: &str = "sum(last_over_time(hydra_doom_bots_total[1y]))";
+const ACTIVE_BOTS: &str = "sum(hydra_doom_bots_current)";
+const TOTAL_KILLS: &str = "sum(last_over_time(hydra_doom_kills[1y]))";
+const KILLS_PER_MINUTE: &str = "sum(irate(hydra_doom_kills[10m]) * 60)";
+const TOTAL_SUICIDES: &str = "sum(last_over_time(hydra_doom_suicides[1y]))";
+const SUICIDES_PER_MINUTE: &str = "sum(irate(hydra_doom_suicides[10m]) * 60)";
+
+#[derive(Deserialize, Debug)]
+struct PrometheusResult {
+    pub value: (f32, String),
+}
+
+#[derive(Deserialize, Debug)]
+struct PrometheusData {
+    pub result: Vec<PrometheusResult>,
+}
+
+#[derive(Deserialize, Debug)]
+struct PrometheusResponse {
+    pub data: PrometheusData,
+}
+
+/// Queries the stats from a Prometheus compatible HTTP API.
+pub struct PrometheusSource {
+    url: String,
+    client: reqwest::Client,
+}
+
+impl PrometheusSource {
+    pub fn new(url: String) -> Self {
+        Self {
+            url: url.trim_end_matches('/').to_string(),
+            client: reqwest::Client::new(),
+        }
+    }
+
+    async fn fetch_metric<T: FromStr + Default>(&self, query: &str) -> anyhow::Result<T> {
+        let body = self
+            .client
+            .get(format!("{}/api/v1/query", self.url))
+            .query(&[("query", query)])
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<PrometheusResponse>()
+            .await
+            .with_context(|| format!("invalid response for {}", query))?;
+
+        Ok(parse_result(query, &body))
+    }
+
+    async fn fetch_all(&self) -> anyhow::Result<GlobalStats> {
+        let (
+            total_txs,
+            txs_per_second,
+            peak_txs_per_second,
+            total_bytes,
+            bytes_per_second,
+            total_games,
+            active_games,
+            total_players,
+            active_players,
+            total_bots,
+            active_bots,
+            total_kills,
+            kills_per_minute,
+            total_suicides,
+            suicides_per_minute,
+        ) = try_join!(
+            self.fetch_metric(TOTAL_TRANSACTIONS),
+            self.fetch_metric(TRANSACTIONS_PER_SECOND),
+            self.fetch_metric(PEAK_TRANSACTIONS_PER_SECOND),
+            self.fetch_metric(TOTAL_BYTES),
+            self.fetch_metric(BYTES_PER_SECOND),
+            self.fetch_metric(TOTAL_GAMES),
+            self.fetch_metric(ACTIVE_GAMES),
+            self.fetch_metric(TOTAL_PLAYERS),
+            self.fetch_metric(ACTIVE_PLAYERS),
+            self.fetch_metric(TOTAL_BOTS),
+            self.fetch_metric(ACTIVE_BOTS),
+            self.fetch_metric(TOTAL_KILLS),
+            self.fetch_metric(KILLS_PER_MINUTE),
+            self.fetch_metric(TOTAL_SUICIDES),
+            self.fetch_metric(SUICIDES_PER_MINUTE),
+        )?;
+
+        Ok(GlobalStats {
+            as_of: time::SystemTime::now(),
+            total_txs,
+            txs_per_second,
+            peak_txs_per_second,
+            total_bytes,
+            bytes_per_second,
+            total_games,
+            active_games,
+            total_players,
+            active_players,
+            total_bots,
+            active_bots,
+            total_kills,
+            kills_per_minute,
+            total_suicides,
+            suicides_per_minute,
+        })
+    }
+}
+
+impl StatsSource for PrometheusSource {
+    fn fetch(&self) -> BoxFuture<'_, anyhow::Result<GlobalStats>> {
+        self.fetch_all().boxed()
+    }
+}
+
+/// The value of an instant query, or the default when it has no result or isn't a `T`.
+fn parse_result<T: FromStr + Default>(query: &str, body: &PrometheusResponse) -> T {
+    let Some(result) = body.data.result.first() else {
+        return T::default();
+    };
+
+    result.value.1.parse::<T>().unwrap_or_else(|_| {
+        warn!("invalid stats value for {}: {}", query, result.value.1);
+        T::default()
+    })
+}
+
+/// Sums of the exporter metrics over every node.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Totals {
+    pub transactions: f64,
+    pub bytes: f64,
+    pub games: f64,
+    pub games_current: f64,
+    pub players: f64,
+    pub players_current: f64,
+    pub bots: f64,
+    pub bots_current: f64,
+    pub kills: f64,
+    pub suicides: f64,
+}
+
+impl Totals {
+    fn add(&mut self, metrics: &str) {
+        for (name, value) in parse_metrics(metrics) {
+            match name {
+                "hydra_doom_node_transactions" => self.transactions += value,
+                "hydra_doom_node_bytes" => self.bytes += value,
+                "hydra_doom_games_seconds_count" => self.games += value,
+                "hydra_doom_games_current" => self.games_current += value,
+                "hydra_doom_players_total" => self.players += value,
+                "hydra_doom_players_current" => self.players_current += value,
+                "hydra_doom_bots_total" => self.bots += value,
+                "hydra_doom_bots_current" => self.bots_current += value,
+                "hydra_doom_kills" => self.kills += value,
+                "hydra_doom_suicides" => self.suicides += value,
+                _ => {}
+            }
+        }
+    }
+}
+
+/// The totals of a single node, from the metrics of its exporter.
+pub async fn node_totals(cluster: &ClusterState, node: &HydraDoomNode) -> ApiResult<Totals> {
+    let body = cluster.call_exporter(node, Method::GET, "metrics").await?;
+    let mut totals = Totals::default();
+    totals.add(&String::from_utf8_lossy(&body));
+
+    Ok(totals)
+}
+
+/// The samples of a Prometheus text exposition, without their labels.
+fn parse_metrics(metrics: &str) -> impl Iterator<Item = (&str, f64)> {
+    metrics
+        .lines()
+        .filter(|line| !line.starts_with('#'))
+        .filter_map(|line| {
+            let (name, rest) = line.split_once(|c: char| c == '{' || c == ' ')?;
+            let value = rest.rsplit(' ').next()?.parse().ok()?;
+            Some((name, value))
+        })
+}
+
+/// Turns consecutive totals into stats, computing the rates between them.
+#[derive(Default)]
+struct Rates {
+    previous: Mutex<Option<(Instant, Totals)>>,
+    peak_txs_per_second: Mutex<f64>,
+}
+
+/// Aggregates the stats from the exporters of the nodes in the clusters.
+pub struct ExporterSource {
+    cluster: ClusterState,
+    rates: Rates,
+}
+
+impl ExporterSource {
+    pub fn new(cluster: ClusterState) -> Self {
+        Self {
+            cluster,
+            rates: Rates::default(),
+        }
+    }
+
+    async fn scrape(&self) -> Totals {
+        let nodes = self.cluster.get_all_nodes();
+        let responses = join_all(
+            nodes
+                .iter()
+                .map(|node| self.cluster.call_exporter(node, Method::GET, "metrics")),
+        )
+        .await;
+
+        let mut totals = Totals::default();
+        for (node, response) in nodes.iter().zip(responses) {
+            match response {
+                Ok(body) => totals.add(&String::from_utf8_lossy(&body)),
+                Err(err) => warn!(
+                    node = node.metadata.name.as_deref().unwrap_or_default(),
+                    "failed to scrape exporter: {}", err
+                ),
+            }
+        }
+
+        totals
+    }
+}
+
+impl Rates {
+    fn aggregate(&self, totals: Totals, now: Instant) -> GlobalStats {
+        let previous = self.previous.lock().unwrap().replace((now, totals.clone()));
+        let (previous, per_second) = match previous {
+            Some((at, previous)) if now > at => {
+                (Some(previous), 1.0 / now.duration_since(at).as_secs_f64())
+            }
+            _ => (None, 0.0),
+        };
+        // Totals shrink when nodes go away, which isn't negative throughput
+        let rate = |total: fn(&Totals) -> f64| {
+            previous.as_ref().map_or(0.0, |previous| {
+                (total(&totals) - total(previous)).max(0.0) * per_second
+            })
+        };
+
+        let txs_per_second = rate(|totals| totals.transactions);
+        let mut peak = self.peak_txs_per_second.lock().unwrap();
+        *peak = peak.max(txs_per_second);
+
+        GlobalStats {
+            as_of: time::SystemTime::now(),
+            total_txs: totals.transactions as u64,
+            txs_per_second,
+            peak_txs_per_second: *peak,
+            total_bytes: totals.bytes as u64,
+            bytes_per_second: rate(|totals| totals.bytes),
+            total_games: totals.games as u32,
+            active_games: totals.games_current as u32,
+            total_players: totals.players as u32,
+            active_players: totals.players_current as u32,
+            total_bots: totals.bots as u32,
+            active_bots: totals.bots_current as u32,
+            total_kills: totals.kills as u32,
+            kills_per_minute: (rate(|totals| totals.kills) * 60.0) as f32,
+            total_suicides: totals.suicides as u32,
+            suicides_per_minute: (rate(|totals| totals.suicides) * 60.0) as f32,
+        }
+    }
+}
+
+impl StatsSource for ExporterSource {
+    fn fetch(&self) -> BoxFuture<'_, anyhow::Result<GlobalStats>> {
+        async move {
+            let totals = self.scrape().await;
+            Ok(self.rates.aggregate(totals, Instant::now()))
+        }
+        .boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_history() {
+        let state = StatsState::default();
+        let start = time::UNIX_EPOCH + Duration::from_secs(1_000_000 * 60);
+        for (minute, active_games) in [(0, 2), (1, 4), (2, 6), (20, 1)] {
+            let as_of = start + Duration::from_secs(minute * 60);
+            state.update(GlobalStats {
+                as_of,
+                active_games,
+                ..Default::default()
+            });
+            // Too soon to be sampled again
+            state.update(GlobalStats {
+                as_of: as_of + Duration::from_secs(5),
+                active_games: 100,
+                ..Default::default()
+            });
+        }
+        assert_eq!(state.latest().active_games, 100);
+
+        let now = start + Duration::from_secs(30 * 60);
+        let hour: Vec<_> = state
+            .history(HistoryRange::Hour, now)
+            .into_iter()
+            .map(|bucket| bucket.active_games)
+            .collect();
+        assert_eq!(hour, vec![2.0, 4.0, 6.0, 1.0]);
+
+        let day = state.history(HistoryRange::Day, now);
+        assert_eq!(day.len(), 2);
+        assert_eq!(day[0].active_games, 4.0);
+        assert_eq!(day[0].start % (15 * 60), 0);
+    }
+
+    #[test]
+    fn test_parse_metrics() {
+        let mut totals = Totals::default();
+        totals.add(
+            "# HELP hydra_doom_kills Number of kills in the game.\n\
+             # TYPE hydra_doom_kills counter\n\
+             hydra_doom_kills 7\n\
+             hydra_doom_games_seconds_bucket{le=\"60\"} 1\n\
+             hydra_doom_games_seconds_count 2\n",
+        );
+        totals.add("hydra_doom_kills 3\nhydra_doom_players_current 2\nhydra_doom_bots_current 1\n");
+
+        assert_eq!(
+            totals,
+            Totals {
+                kills: 10.0,
+                games: 2.0,
+                players_current: 2.0,
+                bots_current: 1.0,
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn test_rates() {
+        let rates = Rates::default();
+        let start = Instant::now();
+        let stats = rates.aggregate(
+            Totals {
+                transactions: 100.0,
+                kills: 1.0,
+                ..Default::default()
+            },
+            start,
+        );
+        assert_eq!((stats.total_txs, stats.txs_per_second), (100, 0.0));
+
+        let stats = rates.aggregate(
+            Totals {
+                transactions: 150.0,
+                kills: 2.0,
+                suicides: 1.0,
+                ..Default::default()
+            },
+            start + Duration::from_secs(5),
+        );
+        assert_eq!(stats.txs_per_second, 10.0);
+        assert_eq!(stats.kills_per_minute, 12.0);
+        assert_eq!((stats.total_suicides, stats.suicides_per_minute), (1, 12.0));
+
+        // A node went away, which doesn't make the rate negative or lower the peak
+        let stats = rates.aggregate(Totals::default(), start + Duration::from_secs(10));
+        assert_eq!(
+            (stats.txs_per_second, stats.peak_txs_per_second),
+            (0.0, 10.0)
+        );
+    }
+
+    #[test]
+    fn test_prometheus_result() {
+        let body: PrometheusResponse = serde_json::from_value(serde_json::json!({
+            "status": "success",
+            "data": {
+                "resultType": "vector",
+                "result": [{ "metric": {}, "value": [1727000000.0, "42"] }]
+            }
+        }))
+        .unwrap();
+        assert_eq!(parse_result::<u64>(TOTAL_KILLS, &body), 42);
+        assert_eq!(parse_result::<f64>(TOTAL_KILLS, &body), 42.0);
+
+        let empty: PrometheusResponse =
+            serde_json::from_value(serde_json::json!({ "data": { "result": [] } })).unwrap();
+        assert_eq!(parse_result::<u64>(TOTAL_KILLS, &empty), 0);
+    }
+
+    #[tokio::test]
+    async fn test_static_source() {
+        let stats = GlobalStats {
+            total_games: 3,
+            ..Default::default()
+        };
+        let fetched = StaticSource(stats).fetch().await.unwrap();
+
+        assert_eq!(fetched.total_games, 3);
+        assert!(fetched.as_of > time::UNIX_EPOCH);
+    }
+}
@@ -0,0 +1,122 @@
+use std::collections::HashMap;
+
+use anyhow::Context as _;
+use opentelemetry::{global, trace::TracerProvider as _, Context, KeyValue};
+use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_sdk::{
+    propagation::TraceContextPropagator, runtime, trace::TracerProvider, Resource,
+};
+use rocket::{
+    request::{FromRequest, Outcome},
+    Request,
+};
+use serde::Deserialize;
+use tracing::{warn, Span};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+use tracing_subscriber::{filter::LevelFilter, layer::SubscriberExt, util::SubscriberInitExt};
+
+/// Where traces are exported. Without an endpoint nothing is exported, and spans only show up in
+/// the logs.
+#[derive(Deserialize, Clone, Debug, Default)]
+pub struct TelemetryConfig {
+    /// OTLP gRPC endpoint of the collector, e.g. `http://otel-collector:4317`.
+    pub otlp_endpoint: Option<String>,
+}
+
+/// Keeps the trace exporter running, call `shutdown` before exiting to flush pending spans.
+pub struct Telemetry {
+    provider: Option<TracerProvider>,
+}
+
+impl Telemetry {
+    /// Installs the global tracing subscriber, logging to stdout and exporting spans when
+    /// configured to.
+    pub fn init(service_name: &'static str, config: &TelemetryConfig) -> anyhow::Result<Self> {
+        global::set_text_map_propagator(TraceContextPropagator::new());
+
+        let provider = config
+            .otlp_endpoint
+            .as_ref()
+            .map(|endpoint| {
+                let exporter = opentelemetry_otlp::SpanExporter::builder()
+                    .with_tonic()
+                    .with_endpoint(endpoint)
+                    .build()
+                    .context("failed to build the OTLP exporter")?;
+
+                anyhow::Ok(
+                    TracerProvider::builder()
+                        .with_batch_exporter(exporter, runtime::Tokio)
+                        .with_resource(Resource::new([KeyValue::new("service.name", service_name)]))
+                        .build(),
+                )
+            })
+            .transpose()?;
+        let otel_layer = provider.as_ref().map(|provider| {
+            tracing_opentelemetry::layer().with_tracer(provider.tracer(service_name))
+        });
+
+        tracing_subscriber::registry()
+            .with(LevelFilter::INFO)
+            .with(tracing_subscriber::fmt::layer())
+            .with(otel_layer)
+            .try_init()
+            .context("failed to install the tracing subscriber")?;
+        if let Some(provider) = &provider {
+            global::set_tracer_provider(provider.clone());
+        }
+
+        Ok(Self { provider })
+    }
+
+    pub fn shutdown(self) {
+        if let Some(provider) = self.provider {
+            if let Err(err) = provider.shutdown() {
+                warn!("failed to flush traces: {}", err);
+            }
+        }
+    }
+}
+
+/// The trace context of the current span as HTTP headers, to continue the trace in the service
+/// being called.
+pub fn trace_headers() -> HashMap<String, String> {
+    let mut headers = HashMap::new();
+    global::get_text_map_propagator(|propagator| {
+        propagator.inject_context(&Span::current().context(), &mut headers)
+    });
+
+    headers
+}
+
+/// The trace context propagated by the caller of a route, if any.
+pub struct TraceParent(Context);
+
+impl TraceParent {
+    /// Makes the current span a child of the caller's span.
+    pub fn adopt(self) {
+        Span::current().set_parent(self.0);
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for TraceParent {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, ()> {
+        let headers: HashMap<String, String> = request
+            .headers()
+            .iter()
+            .map(|header| {
+                (
+                    header.name().as_str().to_ascii_lowercase(),
+                    header.value().to_string(),
+                )
+            })
+            .collect();
+
+        Outcome::Success(TraceParent(global::get_text_map_propagator(|propagator| {
+            propagator.extract(&headers)
+        })))
+    }
+}
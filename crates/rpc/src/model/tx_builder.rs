@@ -11,16 +11,13 @@ use pallas::{
 };
 
 use crate::model::{
-    game::contract::{
-        game_state::State,
-        redeemer::{Redeemer, SpendAction},
-    },
+    game::contract::redeemer::{Redeemer, SpendAction},
     hydra::utxo::Datum,
 };
 
 use super::{
     game::{
-        contract::{game_state::GameState, validator::Validator},
+        contract::{game_state::GameState, game_token::GameToken, validator::Validator},
         player::Player,
     },
     hydra::utxo::UTxO,
@@ -31,6 +28,9 @@ pub struct TxBuilder {
     admin_key: SecretKey,
     pub admin_pkh: Hash<28>,
     network: Network,
+    // Lovelace collected into the game UTxO from every player that joins. It is paid out of a
+    // deposit the player committed to their outbound address.
+    stake: u64,
 }
 
 impl TxBuilder {
@@ -40,16 +40,24 @@ impl TxBuilder {
             admin_key,
             admin_pkh,
             network,
+            stake: 0,
         }
     }
 
+    pub fn with_stake(mut self, stake: u64) -> Self {
+        self.stake = stake;
+
+        self
+    }
+
     pub fn new_game(
         &self,
         player: Player,
         utxos: Vec<UTxO>,
         player_count: u64,
         bot_count: u64,
-    ) -> Result<BuiltTransaction> {
+    ) -> Result<(String, BuiltTransaction)> {
+        let deposit = self.find_deposit(&player, &utxos)?;
         let admin_utxos = self.find_admin_utxos(utxos);
 
         if admin_utxos.is_empty() {
@@ -57,6 +65,7 @@ impl TxBuilder {
         };
 
         let input_utxo = admin_utxos.first().unwrap();
+        let token = GameToken::from_seed(self.admin_pkh, input_utxo);
 
         let script_address = Validator::address(self.network);
         let player_outbound_address = player
@@ -76,42 +85,56 @@ impl TxBuilder {
         let admin_address = Address::from_bytes(admin_address_bytes.as_slice())?;
 
         let game_state: PlutusData = GameState::new(self.admin_pkh.into(), player_count, bot_count)
-            .add_player(player.signing_key.into())
+            .add_player(player.signing_key.into())?
             .into();
         let mut datum: Vec<u8> = Vec::new();
         encode(&game_state, &mut datum)?;
 
         let tx_builder = StagingTransaction::new()
             .input(input_utxo.clone().into())
+            // Game token
+            .mint_asset(token.policy_id(), token.name.clone(), 1)
+            .context("failed to mint game token")?
+            .script(ScriptKind::Native, token.policy_script_bytes()?)
             // GameState Datum
-            .output(Output::new(script_address, 0).set_inline_datum(datum))
+            .output(
+                Output::new(script_address, self.stake)
+                    .set_inline_datum(datum)
+                    .add_asset(token.policy_id(), token.name.clone(), 1)?,
+            )
             // Player Output
-            .output(Output::new(player_outbound_address, 0))
+            .output(
+                Output::new(player_outbound_address, 0).set_inline_datum(token.outbound_datum()?),
+            )
             //Server UTxO
             .output(Output::new(admin_address, 0))
             // Maintain Initial UTxO
             .output(Output::new(
                 input_utxo.address.clone(),
-                input_utxo.value.get("lovelace").unwrap_or(&0).to_owned(),
+                input_utxo.lovelace(),
             ))
             .fee(0);
+        let tx_builder = self.pay_stake(tx_builder, &player, deposit)?;
 
         let tx = tx_builder.build_conway_raw()?;
         let signed_tx = tx
             .sign(self.admin_key.clone().into())
             .context("failed to sign tx")?;
-        Ok(signed_tx)
+        Ok((token.game_id(), signed_tx))
     }
 
-    pub fn add_player(&self, player: Player, utxos: Vec<UTxO>) -> Result<BuiltTransaction> {
-        let game_state_utxo = utxos
-            .clone()
-            .into_iter()
-            .find(|utxo| utxo.address == Validator::address(self.network))
-            .ok_or_else(|| anyhow!("game state UTxO not found"))?;
+    pub fn add_player(
+        &self,
+        game_id: &str,
+        player: Player,
+        utxos: Vec<UTxO>,
+    ) -> Result<BuiltTransaction> {
+        let token = self.game_token(game_id)?;
+        let game_state_utxo = self.find_game_utxo(&token, &utxos)?;
+        let deposit = self.find_deposit(&player, &utxos)?;
 
         let game_state: PlutusData = GameState::try_from(game_state_utxo.datum.clone())?
-            .add_player(player.signing_key.into())
+            .add_player(player.signing_key.into())?
             .into();
 
         let mut datum: Vec<u8> = Vec::new();
@@ -136,9 +159,18 @@ impl TxBuilder {
             .input(game_state_utxo.clone().into())
             .collateral_input(collateral_utxo.clone().into())
             // GameState Output
-            .output(Output::new(script_address, 0).set_inline_datum(datum))
+            .output(
+                Output::new(script_address, game_state_utxo.lovelace() + self.stake)
+                    .set_inline_datum(datum)
+                    .add_asset(token.policy_id(), token.name.clone(), 1)?,
+            )
             // Player Output
-            .output(Output::new(outbound_player_address, 0))
+            .output(
+                Output::new(outbound_player_address, 0).set_inline_datum(token.outbound_datum()?),
+            );
+
+        let tx_builder = self
+            .pay_stake(tx_builder, &player, deposit)?
             .add_spend_redeemer(
                 game_state_utxo.into(),
                 redeemer_bytes,
@@ -182,15 +214,12 @@ impl TxBuilder {
         Ok(signed_tx)
     }
 
-    pub fn start_game(&self, utxos: Vec<UTxO>) -> Result<BuiltTransaction> {
-        let game_state_utxo = utxos
-            .clone()
-            .into_iter()
-            .find(|utxo| utxo.address == Validator::address(self.network))
-            .ok_or_else(|| anyhow!("game state UTxO not found"))?;
+    pub fn start_game(&self, game_id: &str, utxos: Vec<UTxO>) -> Result<BuiltTransaction> {
+        let token = self.game_token(game_id)?;
+        let game_state_utxo = self.find_game_utxo(&token, &utxos)?;
 
         let game_state: PlutusData = GameState::try_from(game_state_utxo.datum.clone())?
-            .set_state(State::Running)
+            .start()?
             .into();
 
         let mut datum = Vec::new();
@@ -210,7 +239,11 @@ impl TxBuilder {
         let tx_builder = StagingTransaction::new()
             .input(game_state_utxo.clone().into())
             .collateral_input(collateral_utxo.clone().into())
-            .output(Output::new(script_address, 0).set_inline_datum(datum))
+            .output(
+                Output::new(script_address, game_state_utxo.lovelace())
+                    .set_inline_datum(datum)
+                    .add_asset(token.policy_id(), token.name.clone(), 1)?,
+            )
             .add_spend_redeemer(
                 game_state_utxo.into(),
                 redeemer_bytes,
@@ -257,6 +290,7 @@ impl TxBuilder {
 
     pub fn end_game(
         &self,
+        game_id: &str,
         // This feels clunky, but we need to know if we are aborting the game, if they are a cheater, or a winner.
         // If this is None, we are aborting,
         // if this is Some(_, true), we are marking as cheated
@@ -264,34 +298,20 @@ impl TxBuilder {
         is_player_cheater: Option<(Player, bool)>,
         utxos: Vec<UTxO>,
     ) -> Result<BuiltTransaction> {
-        let game_state_utxo = utxos
-            .clone()
-            .into_iter()
-            .find(|utxo| utxo.address == Validator::address(self.network))
-            .ok_or_else(|| anyhow!("game state UTxO not found"))?;
+        let token = self.game_token(game_id)?;
+        let game_state_utxo = self.find_game_utxo(&token, &utxos)?;
 
-        let mut game_state: GameState = match game_state_utxo.datum.clone() {
+        let game_state: GameState = match game_state_utxo.datum.clone() {
             Datum::Hash(_) => bail!("Unexpected datum hash in game utxo"),
             Datum::Inline(data) => data.try_into()?,
             Datum::None => bail!("No datum in game utxo"),
         };
 
-        match is_player_cheater {
-            None => {
-                game_state = game_state.set_state(State::Aborted);
-            }
-            Some((player, is_cheater)) => {
-                if is_cheater {
-                    game_state = game_state
-                        .set_state(State::Cheated)
-                        .set_cheater(player.into());
-                } else {
-                    game_state = game_state
-                        .set_state(State::Finished)
-                        .set_winner(player.into());
-                };
-            }
-        }
+        let game_state = match is_player_cheater {
+            None => game_state.abort()?,
+            Some((player, true)) => game_state.cheated(player.into())?,
+            Some((player, false)) => game_state.finish(player.into())?,
+        };
 
         let game_state: PlutusData = game_state.into();
         let mut datum: Vec<u8> = Vec::new();
@@ -311,7 +331,11 @@ impl TxBuilder {
             .input(game_state_utxo.clone().into())
             .collateral_input(collateral_utxo.clone().into())
             // GameState Output
-            .output(Output::new(Validator::address(self.network), 0).set_inline_datum(datum))
+            .output(
+                Output::new(Validator::address(self.network), game_state_utxo.lovelace())
+                    .set_inline_datum(datum)
+                    .add_asset(token.policy_id(), token.name.clone(), 1)?,
+            )
             .add_spend_redeemer(
                 game_state_utxo.into(),
                 redeemer_bytes,
@@ -357,12 +381,9 @@ impl TxBuilder {
     }
 
     //TODO: sooo many clones here. Let's improve that if possible
-    pub fn cleanup_game(&self, utxos: Vec<UTxO>) -> Result<BuiltTransaction> {
-        let game_state_utxo = utxos
-            .clone()
-            .into_iter()
-            .find(|utxo| utxo.address == Validator::address(self.network))
-            .ok_or_else(|| anyhow!("game state UTxO not found"))?;
+    pub fn cleanup_game(&self, game_id: &str, utxos: Vec<UTxO>) -> Result<BuiltTransaction> {
+        let token = self.game_token(game_id)?;
+        let game_state_utxo = self.find_game_utxo(&token, &utxos)?;
 
         let game_state: GameState = match game_state_utxo.datum.clone() {
             Datum::Hash(_) => bail!("Unexpected datum hash in game utxo"),
@@ -371,6 +392,9 @@ impl TxBuilder {
                 .context("failed to convert data to GameState")?,
             Datum::None => bail!("No datum in game utxo"),
         };
+        let pot = game_state_utxo.lovelace();
+        let payouts = game_state.payouts(pot)?;
+        let paid_out: u64 = payouts.iter().map(|(_, amount)| amount).sum();
 
         let admin_utxos = self.find_admin_utxos(utxos.clone());
 
@@ -387,12 +411,10 @@ impl TxBuilder {
             StagingTransaction::new()
                 .input(game_state_utxo.clone().into())
                 .collateral_input(initial_state_utxo.clone().into())
-                .output(
-                    initial_state_utxo
-                        .clone()
-                        .try_into()
-                        .context("failed to build target output from utxo object")?,
-                )
+                // Burn the game token
+                .mint_asset(token.policy_id(), token.name.clone(), -1)
+                .context("failed to burn game token")?
+                .script(ScriptKind::Native, token.policy_script_bytes()?)
                 .add_spend_redeemer(
                     game_state_utxo.into(),
                     redeemer_bytes,
@@ -430,8 +452,13 @@ impl TxBuilder {
                 .fee(0),
         );
 
-        // Cleanup the player state utxos
+        // Cleanup the player state utxos of this game, paying out each player's share of the pot
         for player in game_state.players {
+            let payout = payouts
+                .iter()
+                .find(|(recipient, _)| recipient == &player)
+                .map(|(_, amount)| *amount)
+                .unwrap_or_default();
             let player: Player = player.into();
             let outbound_address = player
                 .outbound_address(self.admin_pkh, self.network)
@@ -443,8 +470,9 @@ impl TxBuilder {
             let player_utxos: Vec<_> = utxos
                 .clone()
                 .into_iter()
-                .filter(|utxo| utxo.address == outbound_address)
+                .filter(|utxo| utxo.address == outbound_address && token.is_tagged_by(utxo))
                 .collect();
+            let balance: u64 = player_utxos.iter().map(|utxo| utxo.lovelace()).sum();
             for utxo in player_utxos {
                 if let Some(builder) = tx_builder {
                     tx_builder = Some(
@@ -454,12 +482,29 @@ impl TxBuilder {
                     )
                 }
             }
+            if balance + payout > 0 {
+                if let Some(builder) = tx_builder {
+                    tx_builder =
+                        Some(builder.output(Output::new(outbound_address, balance + payout)));
+                }
+            }
+        }
+
+        // Whatever is left of the pot after the payouts goes back to the admin
+        if pot > paid_out {
+            if let Some(builder) = tx_builder {
+                tx_builder = Some(builder.output(Output::new(
+                    initial_state_utxo.address.clone(),
+                    pot - paid_out,
+                )));
+            }
         }
 
-        // clean up any extraneous admin utxos
-        for utxo in admin_utxos {
+        // clean up any extraneous admin utxos. Funded ones are left alone, since other games on
+        // the head may be spending them concurrently.
+        for utxo in admin_utxos.iter().filter(|utxo| utxo.lovelace() == 0) {
             if let Some(builder) = tx_builder {
-                tx_builder = Some(builder.input(utxo.into()));
+                tx_builder = Some(builder.input(utxo.clone().into()));
             }
         }
 
@@ -480,6 +525,67 @@ impl TxBuilder {
         Ok(signed_tx)
     }
 
+    fn game_token(&self, game_id: &str) -> Result<GameToken> {
+        GameToken::from_game_id(self.admin_pkh, game_id)
+    }
+
+    fn find_game_utxo(&self, token: &GameToken, utxos: &[UTxO]) -> Result<UTxO> {
+        let script_address = Validator::address(self.network);
+        utxos
+            .iter()
+            .find(|utxo| utxo.address == script_address && token.is_held_by(utxo))
+            .cloned()
+            .ok_or_else(|| anyhow!("game state UTxO not found"))
+    }
+
+    /// The deposit a player's stake is paid from: an untagged UTxO the player committed to their
+    /// outbound address. `None` when games have no stake.
+    fn find_deposit(&self, player: &Player, utxos: &[UTxO]) -> Result<Option<UTxO>> {
+        if self.stake == 0 {
+            return Ok(None);
+        }
+
+        let outbound_address = player
+            .outbound_address(self.admin_pkh, self.network)
+            .context("failed to build player multisig outbound address")?;
+        utxos
+            .iter()
+            .find(|utxo| {
+                utxo.address == outbound_address
+                    && utxo.datum == Datum::None
+                    && utxo.lovelace() >= self.stake
+            })
+            .cloned()
+            .map(Some)
+            .ok_or_else(|| anyhow!("No deposit from the player covers the stake"))
+    }
+
+    /// Spends the player's deposit, which the outbound script lets the admin do on their behalf.
+    /// The stake goes to the game output and the change back to the player's outbound address.
+    fn pay_stake(
+        &self,
+        tx_builder: StagingTransaction,
+        player: &Player,
+        deposit: Option<UTxO>,
+    ) -> Result<StagingTransaction> {
+        let Some(deposit) = deposit else {
+            return Ok(tx_builder);
+        };
+
+        let mut outbound_bytes = Vec::new();
+        encode(&player.outbound_script(self.admin_pkh), &mut outbound_bytes)
+            .context("Failed to cbor encode outbound script")?;
+
+        Ok(tx_builder
+            .input(deposit.clone().into())
+            .script(ScriptKind::Native, outbound_bytes)
+            // Player change
+            .output(Output::new(
+                deposit.address.clone(),
+                deposit.lovelace() - self.stake,
+            )))
+    }
+
     fn find_admin_utxos(&self, utxos: Vec<UTxO>) -> Vec<UTxO> {
         let admin_key = self.admin_key.public_key();
         let admin_kh = admin_key.compute_hash();
@@ -501,10 +607,23 @@ impl TxBuilder {
 mod tests {
     use tracing::debug;
 
+    use pallas::ledger::traverse::MultiEraTx;
+
     use super::*;
-    use crate::model::cluster::KeyEnvelope;
+    use crate::model::{cluster::KeyEnvelope, hydra::messages::Transaction};
     use std::{collections::HashMap, fs::File};
 
+    fn utxo(index: u64, address: Address, lovelace: u64) -> UTxO {
+        UTxO {
+            hash: vec![0; 32],
+            index,
+            address,
+            datum: Datum::None,
+            reference_script: None,
+            value: HashMap::from([("lovelace".to_string(), lovelace)]),
+        }
+    }
+
     // TODO write an actual test with an assertion
     // I did this just to confirm the transaction is built as I expected manually
     #[test]
@@ -543,10 +662,65 @@ mod tests {
             value,
         }];
 
-        let tx = tx_builder
+        let (_, tx) = tx_builder
             .new_game(player.into(), utxos, 1, 3)
             .expect("Failed to build tx");
 
         debug!("{}", hex::encode(tx.tx_bytes));
     }
+
+    #[test]
+    fn test_stake_paid_by_player() {
+        let tx_builder =
+            TxBuilder::new(SecretKey::from([7; 32]), Network::Testnet).with_stake(3_000_000);
+        let player = Hash::<28>::from([1; 28]);
+        let outbound_address = Player::from(player)
+            .outbound_address(tx_builder.admin_pkh, Network::Testnet)
+            .expect("failed to build outbound address");
+        let mut admin_address = tx_builder.admin_pkh.to_vec();
+        admin_address.insert(0, 0b0110_0000);
+        let admin_address = Address::from_bytes(&admin_address).expect("valid address");
+
+        let admin_utxo = utxo(0, admin_address.clone(), 10_000_000);
+        let deposit = utxo(1, outbound_address.clone(), 5_000_000);
+        let (_, tx) = tx_builder
+            .new_game(player.into(), vec![admin_utxo.clone(), deposit], 2, 0)
+            .expect("failed to build tx");
+
+        let inputs: Vec<u64> = MultiEraTx::decode(tx.tx_bytes.as_ref())
+            .expect("failed to decode tx")
+            .inputs()
+            .iter()
+            .map(|input| input.index())
+            .collect();
+        assert!(inputs.contains(&1));
+
+        let outputs = Transaction {
+            cbor: tx.tx_bytes.as_ref().to_vec(),
+            description: String::new(),
+            tx_id: hex::encode([1; 32]),
+            tx_type: "Tx ConwayEra".to_string(),
+        }
+        .outputs()
+        .expect("failed to decode outputs");
+        let lovelace_at = |address: &Address| -> u64 {
+            outputs
+                .iter()
+                .filter(|output| &output.address == address)
+                .map(|output| output.lovelace())
+                .sum()
+        };
+        assert_eq!(
+            lovelace_at(&Validator::address(Network::Testnet)),
+            3_000_000
+        );
+        assert_eq!(lovelace_at(&outbound_address), 2_000_000);
+        // The house doesn't pay any of the stake
+        assert_eq!(lovelace_at(&admin_address), 10_000_000);
+
+        // A player without a deposit covering the stake can't join
+        assert!(tx_builder
+            .new_game(player.into(), vec![admin_utxo], 2, 0)
+            .is_err());
+    }
 }
@@ -1,47 +1,64 @@
-use rocket::{get, http::Status, serde::json::Json, State};
+use anyhow::Context;
+use reqwest::Method;
+use rocket::{get, serde::json::Json, State};
 use serde::Serialize;
+use tracing::instrument;
 
-use crate::model::cluster::{shared::AddPlayerLocalResponse, ClusterState};
+use crate::model::{
+    api_error::{ApiError, ApiResult, ErrorCode},
+    cluster::{exporter_path, shared::AddPlayerLocalResponse, ClusterState},
+    game::contract::game_token::GameToken,
+};
+
+use super::auth::Authenticated;
 
 #[derive(Serialize)]
 pub struct AddPlayerResponse {
+    game_id: String,
     ip: String,
     player_state: String,
     admin_pkh: String,
 }
 
-#[get("/add_player?<address>&<id>")]
+#[get("/add_player?<address>&<id>&<game_id>")]
+#[instrument(skip_all, fields(address = address, game_id = game_id))]
 pub async fn add_player(
     address: &str,
     id: &str,
+    game_id: &str,
+    session: Authenticated,
     state: &State<ClusterState>,
-) -> Result<Json<AddPlayerResponse>, Status> {
-    let node = state.get_node_by_id(id).ok_or(Status::NotFound)?;
+) -> ApiResult<Json<AddPlayerResponse>> {
+    if session.0.address != address {
+        return Err(ApiError::new(
+            ErrorCode::Forbidden,
+            "address does not match the session",
+        ));
+    }
+
+    if !GameToken::is_game_id(game_id) {
+        return Err(ApiError::new(ErrorCode::InvalidRequest, "invalid game id"));
+    }
 
-    let (external_url, local_url): (String, String) = node
+    let node = state
+        .get_node_by_id(id)
+        .ok_or_else(|| ApiError::node_not_found(id))?;
+
+    let external_url = node
         .status
         .as_ref()
-        .map(|status| {
-            (
-                status.external_url.clone(),
-                status
-                    .local_url
-                    .clone()
-                    .replace("ws://", "http://")
-                    .replace("4001", "8000"),
-            )
-        })
+        .map(|status| status.external_url.clone())
         .unwrap_or_default();
 
-    let url = local_url + "/game/add_player?address=" + address;
-    let response = reqwest::get(url).await.map_err(|_| Status::BadGateway)?;
-
-    let body = response
-        .json::<AddPlayerLocalResponse>()
-        .await
-        .map_err(|_| Status::InternalServerError)?;
+    let path = exporter_path(
+        "game/add_player",
+        &[("game_id", game_id), ("address", address)],
+    );
+    let body = state.call_exporter(&node, Method::GET, &path).await?;
+    let body: AddPlayerLocalResponse = serde_json::from_slice(&body).context("http error")?;
 
     Ok(Json(AddPlayerResponse {
+        game_id: body.game_id,
         ip: external_url,
         player_state: body.player_state,
         admin_pkh: body.admin_pkh,
@@ -0,0 +1,201 @@
+use reqwest::Method;
+use rocket::{
+    delete, get,
+    http::Status,
+    post,
+    request::{FromRequest, Outcome},
+    serde::json::Json,
+    Request, State,
+};
+use serde_json::{json, Value};
+
+use crate::model::{
+    admin::{AdminKey, AdminRole, AdminState, AuditEntry},
+    api_error::{ApiError, ApiResult, ErrorCode},
+    cluster::{exporter_path, ClusterState},
+    game::contract::game_token::GameToken,
+};
+
+/// Request guard for the admin routes, authenticated with the `X-Api-Key` header.
+pub struct Admin(AdminKey);
+
+impl Admin {
+    fn require(&self, role: AdminRole) -> ApiResult<()> {
+        if self.0.role >= role {
+            Ok(())
+        } else {
+            Err(ApiError::new(
+                ErrorCode::Forbidden,
+                format!("requires the {:?} role", role),
+            ))
+        }
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Admin {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let key = request.rocket().state::<AdminState>().and_then(|admin| {
+            request
+                .headers()
+                .get_one("X-Api-Key")
+                .and_then(|key| admin.authenticate(key))
+        });
+
+        match key {
+            Some(key) => Outcome::Success(Admin(key)),
+            None => Outcome::Error((Status::Unauthorized, ())),
+        }
+    }
+}
+
+async fn patch_spec(
+    admin: Admin,
+    action: &str,
+    id: &str,
+    spec: Value,
+    cluster: &ClusterState,
+    admin_state: &AdminState,
+) -> ApiResult<()> {
+    admin.require(AdminRole::Operator)?;
+    cluster
+        .get_node_by_id(id)
+        .ok_or_else(|| ApiError::node_not_found(id))?;
+
+    let result = cluster.patch_node_spec(id, spec).await;
+    admin_state.record(&admin.0, action, id, &result);
+
+    Ok(result?)
+}
+
+#[post("/admin/heads/<id>/sleep")]
+pub async fn sleep(
+    id: &str,
+    admin: Admin,
+    cluster: &State<ClusterState>,
+    admin_state: &State<AdminState>,
+) -> ApiResult<()> {
+    patch_spec(
+        admin,
+        "sleep",
+        id,
+        json!({ "asleep": true }),
+        cluster,
+        admin_state,
+    )
+    .await
+}
+
+#[post("/admin/heads/<id>/wake")]
+pub async fn wake(
+    id: &str,
+    admin: Admin,
+    cluster: &State<ClusterState>,
+    admin_state: &State<AdminState>,
+) -> ApiResult<()> {
+    patch_spec(
+        admin,
+        "wake",
+        id,
+        json!({ "asleep": false }),
+        cluster,
+        admin_state,
+    )
+    .await
+}
+
+#[post("/admin/heads/<id>/drain")]
+pub async fn drain(
+    id: &str,
+    admin: Admin,
+    cluster: &State<ClusterState>,
+    admin_state: &State<AdminState>,
+) -> ApiResult<()> {
+    patch_spec(
+        admin,
+        "drain",
+        id,
+        json!({ "draining": true }),
+        cluster,
+        admin_state,
+    )
+    .await
+}
+
+#[post("/admin/heads/<id>/undrain")]
+pub async fn undrain(
+    id: &str,
+    admin: Admin,
+    cluster: &State<ClusterState>,
+    admin_state: &State<AdminState>,
+) -> ApiResult<()> {
+    patch_spec(
+        admin,
+        "undrain",
+        id,
+        json!({ "draining": false }),
+        cluster,
+        admin_state,
+    )
+    .await
+}
+
+#[post("/admin/heads/<id>/end_game?<game_id>")]
+pub async fn end_game(
+    id: &str,
+    game_id: &str,
+    admin: Admin,
+    cluster: &State<ClusterState>,
+    admin_state: &State<AdminState>,
+) -> ApiResult<()> {
+    admin.require(AdminRole::Operator)?;
+    if !GameToken::is_game_id(game_id) {
+        return Err(ApiError::new(ErrorCode::InvalidRequest, "invalid game id"));
+    }
+    let node = cluster
+        .get_node_by_id(id)
+        .ok_or_else(|| ApiError::node_not_found(id))?;
+
+    let result = cluster
+        .call_exporter(
+            &node,
+            Method::POST,
+            &exporter_path("game/end_game", &[("game_id", game_id)]),
+        )
+        .await
+        .map(|_| ())
+        .map_err(anyhow::Error::from);
+    admin_state.record(&admin.0, &format!("end_game {}", game_id), id, &result);
+
+    Ok(result?)
+}
+
+#[delete("/admin/heads/<id>")]
+pub async fn delete_head(
+    id: &str,
+    admin: Admin,
+    cluster: &State<ClusterState>,
+    admin_state: &State<AdminState>,
+) -> ApiResult<()> {
+    admin.require(AdminRole::Admin)?;
+    cluster
+        .get_node_by_id(id)
+        .ok_or_else(|| ApiError::node_not_found(id))?;
+
+    let result = cluster.delete_node(id).await;
+    admin_state.record(&admin.0, "delete", id, &result);
+
+    Ok(result?)
+}
+
+#[get("/admin/audit")]
+pub async fn audit_log(
+    admin: Admin,
+    admin_state: &State<AdminState>,
+) -> ApiResult<Json<Vec<AuditEntry>>> {
+    admin.require(AdminRole::Admin)?;
+
+    Ok(Json(admin_state.audit_log()))
+}
@@ -0,0 +1,92 @@
+use pallas::ledger::addresses::Address;
+use rocket::{
+    get,
+    http::Status,
+    post,
+    request::{FromRequest, Outcome},
+    serde::json::Json,
+    Request, State,
+};
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::model::{
+    api_error::{ApiError, ApiResult, ErrorCode},
+    auth::{AuthState, Session},
+};
+
+#[derive(Serialize)]
+pub struct ChallengeResponse {
+    nonce: String,
+    message: String,
+}
+
+#[derive(Deserialize)]
+pub struct LoginRequest {
+    nonce: String,
+    // Hex encoded COSE_Sign1 and COSE_Key, as returned by the wallet's `signData`
+    signature: String,
+    key: String,
+}
+
+#[derive(Serialize)]
+pub struct LoginResponse {
+    token: String,
+    expires_in: u64,
+}
+
+#[get("/auth/challenge?<address>")]
+pub async fn challenge(
+    address: &str,
+    auth: &State<AuthState>,
+) -> ApiResult<Json<ChallengeResponse>> {
+    Address::from_bech32(address).map_err(|_| ApiError::invalid_address())?;
+
+    let (nonce, message) = auth.challenge(address);
+
+    Ok(Json(ChallengeResponse { nonce, message }))
+}
+
+#[post("/auth/session", data = "<request>")]
+pub async fn login(
+    request: Json<LoginRequest>,
+    auth: &State<AuthState>,
+) -> ApiResult<Json<LoginResponse>> {
+    let signature = hex::decode(&request.signature)
+        .map_err(|_| ApiError::new(ErrorCode::InvalidRequest, "signature is not valid hex"))?;
+    let key = hex::decode(&request.key)
+        .map_err(|_| ApiError::new(ErrorCode::InvalidRequest, "key is not valid hex"))?;
+
+    let token = auth
+        .login(&request.nonce, &signature, &key)
+        .inspect_err(|err| warn!("rejected login: {}", err))
+        .map_err(|err| ApiError::new(ErrorCode::Unauthorized, err.to_string()))?;
+
+    Ok(Json(LoginResponse {
+        token,
+        expires_in: auth.session_ttl().as_secs(),
+    }))
+}
+
+/// Request guard for routes that require a wallet session, passed as `Authorization: Bearer`.
+pub struct Authenticated(pub Session);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Authenticated {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let session = request.rocket().state::<AuthState>().and_then(|auth| {
+            request
+                .headers()
+                .get_one("Authorization")
+                .and_then(|header| header.strip_prefix("Bearer "))
+                .and_then(|token| auth.session(token))
+        });
+
+        match session {
+            Some(session) => Outcome::Success(Authenticated(session)),
+            None => Outcome::Error((Status::Unauthorized, ())),
+        }
+    }
+}
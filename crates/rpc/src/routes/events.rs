@@ -0,0 +1,88 @@
+use anyhow::Context;
+use rocket::{
+    get,
+    response::stream::{Event as SseEvent, EventStream},
+    Shutdown, State,
+};
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::model::{
+    cluster::ClusterState,
+    events::{Event, EventKind, HeadSummary},
+};
+
+/// Streams head updates as server-sent events, starting with the current state of every head.
+/// Passing `head` (repeatably) limits the stream to those heads and adds their game events, i.e.
+/// players joining, games starting and ending, and the head's transaction throughput.
+#[get("/events?<head>")]
+pub fn events(
+    head: Vec<String>,
+    state: &State<ClusterState>,
+    mut shutdown: Shutdown,
+) -> EventStream![] {
+    let hub = state.events.clone();
+    // Subscribed before taking the snapshot, so no change falls in between
+    let mut receiver = hub.subscribe();
+    let snapshot: Vec<Event> = state
+        .get_all_nodes()
+        .iter()
+        .map(|node| Event {
+            head_id: node.metadata.name.clone().unwrap_or_default(),
+            kind: EventKind::HeadUpdated {
+                head: HeadSummary::new(node, state.region_of(node)),
+            },
+        })
+        .filter(|event| head.is_empty() || head.contains(&event.head_id))
+        .collect();
+    let subscriptions: Vec<_> = head
+        .iter()
+        .filter(|id| state.get_node_by_id(id).is_some())
+        .map(|id| {
+            let cluster = state.inner().clone();
+            let head_id = id.clone();
+            hub.watch_head(id, move || {
+                let node = cluster
+                    .get_node_by_id(&head_id)
+                    .context("head no longer exists")?;
+                Ok(cluster.connection_info(&node)?.to_websocket_url())
+            })
+        })
+        .collect();
+
+    EventStream! {
+        // Relaying stops once the last client watching the head disconnects
+        let _subscriptions = subscriptions;
+        for event in snapshot {
+            yield to_sse(&event);
+        }
+
+        loop {
+            let event = tokio::select! {
+                event = receiver.recv() => match event {
+                    Ok(event) => event,
+                    // The client fell behind, carry on with the latest events
+                    Err(RecvError::Lagged(_)) => continue,
+                    Err(RecvError::Closed) => break,
+                },
+                _ = &mut shutdown => break,
+            };
+            let subscribed = head.contains(&event.head_id);
+            if subscribed || (head.is_empty() && !event.kind.is_game_event()) {
+                yield to_sse(&event);
+            }
+        }
+    }
+}
+
+fn to_sse(event: &Event) -> SseEvent {
+    let name = match event.kind {
+        EventKind::HeadUpdated { .. } => "head_updated",
+        EventKind::HeadRemoved => "head_removed",
+        EventKind::PlayerJoined { .. } => "player_joined",
+        EventKind::GameStarted { .. } => "game_started",
+        EventKind::GameEnded { .. } => "game_ended",
+        EventKind::Throughput { .. } => "throughput",
+    };
+
+    SseEvent::json(event).event(name)
+}
@@ -1,16 +1,19 @@
-use rocket::{get, http::Status, serde::json::Json, State};
+use rocket::{get, serde::json::Json, State};
 
-use crate::model::cluster::{ClusterState, HydraDoomNodeSpec};
+use crate::model::{
+    api_error::{ApiError, ApiResult},
+    cluster::{ClusterState, HydraDoomNodeSpec},
+};
 
 #[get("/heads/<head_id>")]
 pub async fn head(
     state: &State<ClusterState>,
     head_id: &str,
-) -> Result<Json<Vec<HydraDoomNodeSpec>>, Status> {
+) -> ApiResult<Json<Vec<HydraDoomNodeSpec>>> {
     let node = state
         .get_node_by_id(head_id)
         .map(|x| x.spec.clone())
-        .ok_or(Status::NotFound)?;
+        .ok_or_else(|| ApiError::node_not_found(head_id))?;
 
     Ok(Json(vec![node]))
 }
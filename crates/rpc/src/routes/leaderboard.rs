@@ -0,0 +1,18 @@
+use std::sync::Arc;
+
+use rocket::{get, serde::json::Json, State};
+
+use crate::model::leaderboard::{Leaderboards, PlayerRecord, Ranking, MAX_LEADERBOARD_SIZE};
+
+/// The players with the most kills, wins or games played in the games this replica saw finish:
+/// those since it started, and in the hour before. Restarts reset the rankings.
+#[get("/leaderboard?<by>&<limit>")]
+pub async fn leaderboard(
+    by: Ranking,
+    limit: Option<usize>,
+    leaderboards: &State<Arc<Leaderboards>>,
+) -> Json<Vec<PlayerRecord>> {
+    let limit = limit.unwrap_or(10).min(MAX_LEADERBOARD_SIZE);
+
+    Json(leaderboards.top(by, limit))
+}
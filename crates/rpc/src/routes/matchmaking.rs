@@ -0,0 +1,265 @@
+use std::{net::IpAddr, sync::Arc, time::Duration};
+
+use anyhow::Context;
+use futures_util::future::join_all;
+use reqwest::Method;
+use rocket::{delete, get, post, serde::json::Json, State};
+use serde::Serialize;
+use tracing::{info, warn};
+
+use crate::model::{
+    api_error::{ApiError, ApiResult, ErrorCode},
+    cluster::{
+        exporter_path, shared::AddPlayerLocalResponse, ClusterState, HydraDoomNode,
+        RegionPreference,
+    },
+    matchmaking::{
+        Assignment, Match, MatchPreferences, Matchmaker, OpenLobby, Ticket, TicketState,
+    },
+    rate_limit::RateLimiter,
+};
+
+use super::{auth::Authenticated, new_game::ACTIVE_GAMES_RETRY_AFTER};
+
+// Long-polls are capped, so that proxies in between don't time out the request.
+const MAX_WAIT: Duration = Duration::from_secs(30);
+
+#[derive(Serialize)]
+pub struct EnqueueResponse {
+    ticket: String,
+}
+
+#[post("/matchmaking?<address>", data = "<preferences>")]
+pub async fn enqueue(
+    address: &str,
+    preferences: Json<MatchPreferences>,
+    session: Authenticated,
+    ip: Option<IpAddr>,
+    limiter: &State<RateLimiter>,
+    cluster: &State<ClusterState>,
+    matchmaker: &State<Arc<Matchmaker>>,
+) -> ApiResult<Json<EnqueueResponse>> {
+    if session.0.address != address {
+        return Err(ApiError::new(
+            ErrorCode::Forbidden,
+            "address does not match the session",
+        ));
+    }
+    if !(1..=4).contains(&preferences.player_count) || preferences.bot_count > 4 {
+        return Err(ApiError::new(
+            ErrorCode::InvalidRequest,
+            "can request between 1 and 4 players and at most 4 bots",
+        ));
+    }
+    if preferences.player_count + preferences.bot_count > 4 {
+        return Err(ApiError::new(
+            ErrorCode::InvalidRequest,
+            "cannot have more than 4 players and bots",
+        ));
+    }
+
+    // Every ticket may end up creating a game, so they count against the same limits
+    limiter.check(ip, address).map_err(|wait| {
+        ApiError::new(ErrorCode::RateLimited, "rate limit exceeded").with_retry_after(wait)
+    })?;
+    if cluster.active_games_for(address) >= limiter.max_active_games_per_address() {
+        return Err(
+            ApiError::new(ErrorCode::RateLimited, "too many active games")
+                .with_retry_after(ACTIVE_GAMES_RETRY_AFTER),
+        );
+    }
+
+    let ticket = matchmaker.enqueue(address, preferences.into_inner());
+    info!(ticket = ticket.as_str(), address, "queued for matchmaking");
+
+    Ok(Json(EnqueueResponse { ticket }))
+}
+
+/// Returns the state of the ticket once it's assigned a game, or after `wait` seconds at most.
+#[get("/matchmaking/<ticket>?<wait>")]
+pub async fn ticket_state(
+    ticket: &str,
+    wait: Option<u64>,
+    matchmaker: &State<Arc<Matchmaker>>,
+) -> ApiResult<Json<TicketState>> {
+    let wait = Duration::from_secs(wait.unwrap_or(0)).min(MAX_WAIT);
+
+    matchmaker
+        .wait(ticket, wait)
+        .await
+        .map(Json)
+        .ok_or_else(|| {
+            ApiError::new(
+                ErrorCode::InvalidRequest,
+                "unknown ticket, tickets are only known to the replica that issued them",
+            )
+        })
+}
+
+#[delete("/matchmaking/<ticket>")]
+pub async fn cancel(ticket: &str, matchmaker: &State<Arc<Matchmaker>>) -> ApiResult<()> {
+    if matchmaker.cancel(ticket) {
+        Ok(())
+    } else {
+        Err(ApiError::new(
+            ErrorCode::InvalidGameState,
+            "ticket is no longer queued",
+        ))
+    }
+}
+
+fn external_url(node: &HydraDoomNode) -> String {
+    node.status
+        .as_ref()
+        .map(|status| status.external_url.clone())
+        .unwrap_or_default()
+}
+
+/// Draining nodes don't take more players, not even in the lobbies they already have.
+fn is_joinable(cluster: &ClusterState, lobby: &OpenLobby) -> bool {
+    cluster
+        .get_node_by_id(&lobby.node_id)
+        .is_some_and(|node| !node.spec.draining.unwrap_or(false))
+}
+
+/// The lobbies of every node with open games, as reported by their exporters.
+async fn reported_lobbies(cluster: &ClusterState) -> Vec<OpenLobby> {
+    let nodes: Vec<_> = cluster
+        .get_all_nodes()
+        .into_iter()
+        .filter(|node| {
+            node.status
+                .as_ref()
+                .is_some_and(|status| status.open_games > 0)
+        })
+        .collect();
+    let responses = join_all(nodes.iter().map(|node| cluster.games_on(node))).await;
+
+    nodes
+        .iter()
+        .zip(responses)
+        .flat_map(|(node, games)| {
+            let node_id = node.metadata.name.clone().unwrap_or_default();
+            let games = games
+                .inspect_err(|err| warn!(node_id, "failed to list games: {}", err))
+                .unwrap_or_default();
+            let region = Some(cluster.region_of(node).to_string());
+            games
+                .into_iter()
+                .filter(|game| game.state == "Lobby")
+                .map(move |game| OpenLobby::reported(node_id.clone(), region.clone(), &game))
+        })
+        .collect()
+}
+
+async fn create_game(cluster: &ClusterState, ticket: &Ticket) -> Result<Assignment, ApiError> {
+    let (node, claim_id) = cluster
+        .select_node_for_new_game(&RegionPreference {
+            region: ticket.preferences.region.clone(),
+            ..Default::default()
+        })
+        .await
+        .map_err(|err| ApiError::new(ErrorCode::NoWarmNode, err.to_string()))?;
+    let node_id = node.metadata.name.clone().expect("node without a name");
+    let path = exporter_path(
+        "game/new_game",
+        &[
+            ("address", &ticket.address),
+            ("player_count", &ticket.preferences.player_count.to_string()),
+            ("bot_count", &ticket.preferences.bot_count.to_string()),
+        ],
+    );
+    let body = cluster.create_game_on(&node, &claim_id, &path).await?;
+
+    cluster.record_game(&ticket.address, &node_id);
+
+    Ok(Assignment {
+        game_id: body.game_id,
+        node_id,
+        ip: external_url(&node),
+        player_state: body.player_state,
+        admin_pkh: body.admin_pkh,
+    })
+}
+
+async fn join_game(
+    cluster: &ClusterState,
+    ticket: &Ticket,
+    lobby: &OpenLobby,
+) -> Result<Assignment, ApiError> {
+    let node = cluster
+        .get_node_by_id(&lobby.node_id)
+        .ok_or_else(|| ApiError::node_not_found(&lobby.node_id))?;
+    let path = exporter_path(
+        "game/add_player",
+        &[("game_id", &lobby.game_id), ("address", &ticket.address)],
+    );
+    let body = cluster.call_exporter(&node, Method::GET, &path).await?;
+    let body: AddPlayerLocalResponse = serde_json::from_slice(&body).context("http error")?;
+
+    cluster.record_game(&ticket.address, &lobby.node_id);
+
+    Ok(Assignment {
+        game_id: body.game_id,
+        node_id: lobby.node_id.clone(),
+        ip: external_url(&node),
+        player_state: body.player_state,
+        admin_pkh: body.admin_pkh,
+    })
+}
+
+/// Places queued players in games, one at a time.
+pub async fn run_matchmaker(matchmaker: Arc<Matchmaker>, cluster: ClusterState) {
+    loop {
+        if matchmaker.has_queued() {
+            matchmaker.sync_lobbies(reported_lobbies(&cluster).await);
+        }
+
+        while let Some(next) = matchmaker.next_match(|lobby| is_joinable(&cluster, lobby)) {
+            match next {
+                Match::Join(ticket, lobby) => match join_game(&cluster, &ticket, &lobby).await {
+                    Ok(assignment) => matchmaker.assign(&ticket, assignment),
+                    Err(err) => {
+                        // The lobby filled up or started behind our back, try the next one
+                        warn!(
+                            game_id = lobby.game_id.as_str(),
+                            "failed to join lobby: {}", err
+                        );
+                        matchmaker.close_lobby(&lobby.game_id);
+                        matchmaker.requeue(ticket);
+                    }
+                },
+                Match::Create(ticket) => match create_game(&cluster, &ticket).await {
+                    Ok(assignment) => {
+                        let region = cluster
+                            .get_node_by_id(&assignment.node_id)
+                            .map(|node| cluster.region_of(&node).to_string());
+                        matchmaker.open_lobby(OpenLobby::new(
+                            assignment.game_id.clone(),
+                            assignment.node_id.clone(),
+                            MatchPreferences {
+                                region,
+                                ..ticket.preferences.clone()
+                            },
+                        ));
+                        matchmaker.assign(&ticket, assignment);
+                    }
+                    Err(err) if err.code == ErrorCode::NoWarmNode => {
+                        // Wait for the autoscaler to bring up more heads
+                        matchmaker.requeue(ticket);
+                        break;
+                    }
+                    Err(err) => {
+                        warn!(
+                            ticket = ticket.id.as_str(),
+                            "failed to create game: {}", err
+                        );
+                        matchmaker.fail(&ticket, err.message);
+                    }
+                },
+            }
+        }
+
+        tokio::time::sleep(Duration::from_secs(1)).await;
+    }
+}
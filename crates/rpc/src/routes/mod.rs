@@ -1,7 +1,13 @@
 pub mod add_player;
+pub mod admin;
+pub mod auth;
+pub mod events;
 pub mod head;
 pub mod heads;
 pub mod health;
+pub mod leaderboard;
+pub mod matchmaking;
 pub mod new_game;
 pub mod sample_transactions;
 pub mod stats;
+pub mod v1;
@@ -1,76 +1,118 @@
-use anyhow::{anyhow, Context};
+use std::{collections::HashMap, net::IpAddr, time::Duration};
+
 use rocket::{get, serde::json::Json, State};
-use rocket_errors::anyhow::Result;
 use serde::Serialize;
-use tracing::info;
+use tracing::{info, instrument};
+
+use crate::model::{
+    api_error::{ApiError, ApiResult, ErrorCode},
+    cluster::{exporter_path, ClusterState, RegionPreference},
+    rate_limit::RateLimiter,
+};
 
-use crate::model::cluster::{shared::NewGameLocalResponse, ClusterState};
+use super::auth::Authenticated;
 
 #[derive(Serialize)]
 pub struct NewGameResponse {
     game_id: String,
+    node_id: String,
+    region: String,
     ip: String,
     player_state: String,
     admin_pkh: String,
 }
 
-#[get("/new_game?<address>&<player_count>&<bot_count>")]
+// How long to wait before retrying when the caller already has too many active games
+pub const ACTIVE_GAMES_RETRY_AFTER: Duration = Duration::from_secs(30);
+
+/// `region` is the region the player would like to play in and `latency` the round trip times in
+/// milliseconds they measured to each region, e.g. `latency[us-east-1]=80`. Both are optional.
+#[get("/new_game?<address>&<player_count>&<bot_count>&<region>&<latency>")]
+#[allow(clippy::too_many_arguments)]
+#[instrument(skip_all, fields(address = address))]
 pub async fn new_game(
     address: &str,
     player_count: Option<u64>,
     bot_count: Option<u64>,
+    region: Option<String>,
+    latency: Option<HashMap<String, u32>>,
+    session: Authenticated,
+    ip: Option<IpAddr>,
+    limiter: &State<RateLimiter>,
     state: &State<ClusterState>,
-) -> Result<Json<NewGameResponse>> {
+) -> ApiResult<Json<NewGameResponse>> {
     info!("Creating a new game for {}", address);
+    if session.0.address != address {
+        return Err(ApiError::new(
+            ErrorCode::Forbidden,
+            "address does not match the session",
+        ));
+    }
+
     if player_count.is_some_and(|c| c > 4) {
-        return Result::Err(anyhow!("Can request a maximum of 4 players").into());
+        return Err(ApiError::new(
+            ErrorCode::InvalidRequest,
+            "Can request a maximum of 4 players",
+        ));
     }
 
     if bot_count.is_some_and(|c| c > 4) {
-        return Result::Err(anyhow!("Can request a maximum of 4 bots").into());
+        return Err(ApiError::new(
+            ErrorCode::InvalidRequest,
+            "Can request a maximum of 4 bots",
+        ));
     }
 
     if player_count.is_some_and(|c| bot_count.is_some_and(|b| c + b > 4)) {
-        return Result::Err(anyhow!("cannot have more than 4 players and bots").into());
+        return Err(ApiError::new(
+            ErrorCode::InvalidRequest,
+            "cannot have more than 4 players and bots",
+        ));
+    }
+
+    limiter.check(ip, address).map_err(|wait| {
+        ApiError::new(ErrorCode::RateLimited, "rate limit exceeded").with_retry_after(wait)
+    })?;
+    if state.active_games_for(address) >= limiter.max_active_games_per_address() {
+        return Err(
+            ApiError::new(ErrorCode::RateLimited, "too many active games")
+                .with_retry_after(ACTIVE_GAMES_RETRY_AFTER),
+        );
     }
-    let node = state
-        .select_node_for_new_game()
-        .context("error getting warm node")?;
+
+    let (node, claim_id) = state
+        .select_node_for_new_game(&RegionPreference {
+            region,
+            latencies: latency.unwrap_or_default(),
+        })
+        .await
+        .map_err(|err| ApiError::new(ErrorCode::NoWarmNode, err.to_string()))?;
     let node_id = node.metadata.name.clone().expect("node without a name");
-    info!(id = node_id, "select node for new game");
+    let region = state.region_of(&node).to_string();
+    info!(id = node_id, region, "select node for new game");
 
-    let (external_url, local_url): (String, String) = node
+    let external_url = node
         .status
         .as_ref()
-        .map(|status| {
-            (
-                status.external_url.clone(),
-                status
-                    .local_url
-                    .clone()
-                    .replace("ws://", "http://")
-                    .replace("4001", "8000"),
-            )
-        })
+        .map(|status| status.external_url.clone())
         .unwrap_or_default();
 
-    let url = format!(
-        "{local_url}/game/new_game?address={address}&player_count={}&bot_count={}",
-        player_count.unwrap_or(1),
-        bot_count.unwrap_or(2)
+    let path = exporter_path(
+        "game/new_game",
+        &[
+            ("address", address),
+            ("player_count", &player_count.unwrap_or(1).to_string()),
+            ("bot_count", &bot_count.unwrap_or(2).to_string()),
+        ],
     );
+    let body = state.create_game_on(&node, &claim_id, &path).await?;
 
-    let response = reqwest::get(url)
-        .await
-        .context("failed to hit new_game metrics server endpoint")?;
-
-    let body = response
-        .json::<NewGameLocalResponse>()
-        .await
-        .context("http error")?;
+    state.record_game(address, &node_id);
 
     Ok(Json(NewGameResponse {
-        game_id: node_id,
+        game_id: body.game_id,
+        node_id,
+        region,
         ip: external_url,
         player_state: body.player_state,
         admin_pkh: body.admin_pkh,
@@ -1,12 +1,12 @@
 use rand::seq::SliceRandom;
 
 use crate::model::{
-    cluster::{ClusterState, ConnectionInfo, NodeClient},
+    api_error::{ApiError, ApiResult, ErrorCode},
+    cluster::{ClusterState, NodeClient},
     hydra::messages::Transaction,
 };
 use rand::thread_rng;
-use rocket::{get, http::Status, serde::json::Json, State};
-use rocket_errors::anyhow::Result;
+use rocket::{get, serde::json::Json, State};
 use serde::Serialize;
 use tracing::error;
 
@@ -21,29 +21,25 @@ pub async fn sample_transactions(
     count: usize,
     id: Option<&str>,
     state: &State<ClusterState>,
-) -> Result<Json<Vec<SampleTransaction>>, Status> {
+) -> ApiResult<Json<Vec<SampleTransaction>>> {
     let node = match id {
-        Some(id) => state.get_node_by_id(id).ok_or(Status::NotFound)?,
+        Some(id) => state
+            .get_node_by_id(id)
+            .ok_or_else(|| ApiError::node_not_found(id))?,
         None => state
             .select_random_node_with_active_game()
-            .map_err(|_| Status::NotFound)?,
+            .map_err(|err| ApiError::new(ErrorCode::NodeNotFound, err.to_string()))?,
     };
 
-    let (local, remote) =
-        ConnectionInfo::from_resource(node.status.as_ref().ok_or(Status::InternalServerError)?)
-            .map_err(|_| Status::InternalServerError)?;
-
-    let client = NodeClient::new(
-        if state.remote { remote } else { local },
-        state.admin_sk.clone(),
-        state.network,
-    );
+    let connection = state
+        .connection_info(&node)
+        .map_err(|err| ApiError::new(ErrorCode::HeadUnreachable, err.to_string()))?;
+    let client = NodeClient::new(connection, state.admin_sk.clone(), state.network);
 
     let transactions = client
         .sample_txs(count)
         .await
-        .inspect_err(|err| error!("error sampling transactions: {}", err))
-        .map_err(|_| Status::InternalServerError)?
+        .inspect_err(|err| error!("error sampling transactions: {}", err))?
         .into_iter()
         .map(|x| x.into())
         .collect::<Vec<SampleTransaction>>();
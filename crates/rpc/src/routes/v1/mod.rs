@@ -0,0 +1,290 @@
+use futures_util::try_join;
+use rocket::{get, serde::json::Json, State};
+use schemars::{gen::SchemaSettings, JsonSchema};
+use serde::Serialize;
+use serde_json::{json, Value};
+
+use crate::model::{
+    api_error::{ApiError, ApiResult},
+    cluster::{ClusterState, HydraDoomNode},
+    stats::{node_totals, StatsState},
+};
+
+#[derive(Serialize, JsonSchema, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct Stats {
+    /// The number of games played, including those in progress right now
+    pub games: u64,
+    // The stats below are left out of the responses where they aren't collected
+    /// The number of transactions processed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub transactions: Option<u64>,
+    /// The total number of bytes processed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bytes: Option<u64>,
+    /// The total number of kills across all games covered by these stats
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub kills: Option<u64>,
+    // Not reported by the game servers yet
+    /// The total number of items found across all games covered by these stats
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub items: Option<u64>,
+    /// The total number of secrets found across all games covered by these stats
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub secrets: Option<u64>,
+    /// The number of seconds played across all games covered by these stats
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub play_time: Option<u64>,
+}
+
+#[derive(Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct HeadSummary {
+    pub id: String,
+    /// the number of active games on this particular head
+    pub active_games: u64,
+    /// whether the events on this head are being persisted, or are treated ephemerally
+    pub persisted: bool,
+}
+
+#[derive(Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct Head {
+    pub id: String,
+    pub active_games: Vec<Game>,
+    /// Statistics for the whole lifetime of the head
+    pub total: Stats,
+    /// Statistics for only active games on the current head. Only the number of games is known
+    /// for now.
+    pub active: Stats,
+    /// Statistics covering only the last 30 seconds. Heads don't keep a history of their stats,
+    /// so this is left out for now.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub recent: Option<Stats>,
+}
+
+#[derive(Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct Game {
+    pub id: String,
+}
+
+impl From<&HydraDoomNode> for HeadSummary {
+    fn from(node: &HydraDoomNode) -> Self {
+        Self {
+            id: node.metadata.name.clone().unwrap_or_default(),
+            active_games: node
+                .status
+                .as_ref()
+                .map(|status| status.open_games.max(0) as u64)
+                .unwrap_or_default(),
+            // Offline heads run without a layer 1, so nothing they do outlives them
+            persisted: !node.spec.offline.unwrap_or(false),
+        }
+    }
+}
+
+#[get("/v1/global")]
+pub async fn global(stats: &State<StatsState>) -> Json<Stats> {
+    Json(stats.latest().into())
+}
+
+/// The heads that are open, the others can't be played on.
+#[get("/v1/heads")]
+pub async fn heads(state: &State<ClusterState>) -> Json<Vec<HeadSummary>> {
+    Json(
+        state
+            .get_all_nodes()
+            .iter()
+            .filter(|node| {
+                node.status
+                    .as_ref()
+                    .is_some_and(|status| status.node_state == "HeadIsOpen")
+            })
+            .map(|node| node.as_ref().into())
+            .collect(),
+    )
+}
+
+#[get("/v1/heads/<head_id>")]
+pub async fn head(state: &State<ClusterState>, head_id: &str) -> ApiResult<Json<Head>> {
+    let node = state
+        .get_node_by_id(head_id)
+        .ok_or_else(|| ApiError::node_not_found(head_id))?;
+    // Asked from the node, since the games may have been created through any replica
+    let (games, totals) = try_join!(state.games_on(&node), node_totals(state, &node))?;
+    let active_games: Vec<Game> = games
+        .into_iter()
+        .filter(|game| matches!(game.state.as_str(), "Lobby" | "Running"))
+        .map(|game| Game { id: game.game_id })
+        .collect();
+
+    Ok(Json(Head {
+        id: head_id.to_string(),
+        total: Stats {
+            games: (totals.games + totals.games_current) as u64,
+            transactions: Some(totals.transactions as u64),
+            bytes: Some(totals.bytes as u64),
+            kills: Some(totals.kills as u64),
+            ..Default::default()
+        },
+        active: Stats {
+            games: active_games.len() as u64,
+            ..Default::default()
+        },
+        active_games,
+        recent: None,
+    }))
+}
+
+#[get("/openapi.json")]
+pub async fn openapi_json() -> Json<Value> {
+    Json(openapi())
+}
+
+/// The OpenAPI document of the `/v1` API. Schemas are generated from the response types.
+pub fn openapi() -> Value {
+    let mut gen = SchemaSettings::openapi3().into_generator();
+    let stats = gen.subschema_for::<Stats>();
+    let head_summaries = gen.subschema_for::<Vec<HeadSummary>>();
+    let head = gen.subschema_for::<Head>();
+
+    json!({
+        "openapi": "3.0.3",
+        "info": {
+            "title": "Hydra Doom Control Plane",
+            "version": "0.0.1",
+        },
+        "servers": [{ "url": "/v1" }],
+        "paths": {
+            "/global": {
+                "get": {
+                    "tags": ["global"],
+                    "summary": "Global statistics about the whole cluster",
+                    "operationId": "getStats",
+                    "responses": {
+                        "200": {
+                            "description": "Successful operation",
+                            "content": { "application/json": { "schema": stats } },
+                        },
+                    },
+                },
+            },
+            "/heads": {
+                "get": {
+                    "tags": ["heads"],
+                    "summary": "List the online hydra heads and some basic stats",
+                    "operationId": "getHeads",
+                    "responses": {
+                        "200": {
+                            "description": "Successful operation",
+                            "content": { "application/json": { "schema": head_summaries } },
+                        },
+                    },
+                },
+            },
+            "/heads/{headId}": {
+                "get": {
+                    "tags": ["heads"],
+                    "summary": "Get detailed information about a specific head",
+                    "operationId": "getHead",
+                    "parameters": [{
+                        "name": "headId",
+                        "in": "path",
+                        "description": "The head identifier to query about",
+                        "required": true,
+                        "schema": { "type": "string" },
+                    }],
+                    "responses": {
+                        "200": {
+                            "description": "successful operation",
+                            "content": { "application/json": { "schema": head } },
+                        },
+                        "404": { "description": "Head not found" },
+                    },
+                },
+            },
+        },
+        "components": { "schemas": gen.definitions() },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::*;
+
+    /// Reduces a schema to its shape: types, references and property names, ignoring
+    /// descriptions, formats and examples.
+    fn shape(schema: &Value) -> Value {
+        if let Some([inner]) = schema
+            .get("allOf")
+            .and_then(Value::as_array)
+            .map(Vec::as_slice)
+        {
+            return shape(inner);
+        }
+        if let Some(reference) = schema.get("$ref") {
+            return json!({ "$ref": reference });
+        }
+
+        let mut reduced = serde_json::Map::new();
+        if let Some(schema_type) = schema.get("type") {
+            reduced.insert("type".to_string(), schema_type.clone());
+        }
+        if let Some(items) = schema.get("items") {
+            reduced.insert("items".to_string(), shape(items));
+        }
+        if let Some(properties) = schema.get("properties").and_then(Value::as_object) {
+            let properties: BTreeMap<_, _> = properties
+                .iter()
+                .map(|(name, property)| (name.clone(), shape(property)))
+                .collect();
+            reduced.insert("properties".to_string(), json!(properties));
+        }
+
+        Value::Object(reduced)
+    }
+
+    fn operations(document: &Value) -> BTreeMap<String, Value> {
+        let mut operations = BTreeMap::new();
+        for (path, methods) in document["paths"].as_object().unwrap() {
+            for (method, operation) in methods.as_object().unwrap() {
+                let responses: BTreeMap<_, _> = operation["responses"]
+                    .as_object()
+                    .unwrap()
+                    .iter()
+                    .map(|(code, response)| {
+                        (
+                            code.clone(),
+                            shape(&response["content"]["application/json"]["schema"]),
+                        )
+                    })
+                    .collect();
+                operations.insert(format!("{} {}", method, path), json!(responses));
+            }
+        }
+
+        operations
+    }
+
+    #[test]
+    fn test_openapi_matches_api_yaml() {
+        let documented: Value = serde_yaml::from_str(include_str!("../../../../../api.yaml"))
+            .expect("api.yaml is not valid yaml");
+        let generated = openapi();
+
+        assert_eq!(operations(&documented), operations(&generated));
+
+        let schemas = |document: &Value| -> BTreeMap<String, Value> {
+            document["components"]["schemas"]
+                .as_object()
+                .unwrap()
+                .iter()
+                .map(|(name, schema)| (name.clone(), shape(schema)))
+                .collect()
+        };
+        assert_eq!(schemas(&documented), schemas(&generated));
+    }
+}